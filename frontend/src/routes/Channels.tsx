@@ -19,14 +19,17 @@ function SingleChannel({ channel }: { channel: MutinyChannel }) {
 
     // TODO: this should warn before closing
     async function handleCloseChannel() {
-        await nodeManager?.close_channel(channel.outpoint!);
+        await nodeManager?.close_channel(channel.outpoint!, false);
         queryClient.invalidateQueries({ queryKey: ['channels'] })
     }
 
-    let percent = Number(channel.balance / channel.size) * 100
+    const closing = channel.state === "Closing"
+    // the size of closing channels is unknown, their balance is what is left to claim
+    let percent = closing ? 100 : Number(channel.balance / channel.size) * 100
     return (
         <li className="text-off-white border-b border-blue py-2 mb-2 flex flex-col w-full">
-            {!channel.confirmed && <h3 className="font-light text-2xl opacity-70">UNCONFIRMED</h3>}
+            {closing && <h3 className="font-light text-2xl opacity-70">CLOSING</h3>}
+            {!closing && !channel.confirmed && <h3 className="font-light text-2xl opacity-70">UNCONFIRMED</h3>}
             <h3 className="text-lg">
                 {takeN(channel.peer, 28)}
             </h3>
@@ -37,7 +40,7 @@ function SingleChannel({ channel }: { channel: MutinyChannel }) {
                         <div className={"shadow-button bg-blue-button h-6 rounded"} style={{ width: `${percent}%` }} />
                     </div>
                 </div>
-                <button onClick={handleCloseChannel} className="h-[3rem] w-[3rem] p-1 flex items-center justify-center flex-0"><EjectIcon /></button>
+                {!closing && <button onClick={handleCloseChannel} className="h-[3rem] w-[3rem] p-1 flex items-center justify-center flex-0"><EjectIcon /></button>}
             </div>
             <a className="text-sm font-light opacity-50 mt-2" href={mempoolTxUrl(channel.outpoint?.split(":")[0], nodeManager?.get_network())} target="_blank" rel="noreferrer">
                 {takeN(channel.outpoint || "", 28)}
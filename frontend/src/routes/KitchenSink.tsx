@@ -116,7 +116,7 @@ function App() {
     async function get_invoice() {
         if (nodeManager) {
             try {
-                let invoice = await nodeManager.create_invoice(BigInt(1000), "hello");
+                let invoice = await nodeManager.create_invoice(BigInt(1000), "hello", undefined);
                 if (invoice.bolt11) {
                     setInvoice(invoice.bolt11)
                 }
@@ -164,7 +164,7 @@ function App() {
     async function payInvoice(e: React.SyntheticEvent) {
         e.preventDefault()
         try {
-            await nodeManager?.pay_invoice(currentNode, invoiceToPay);
+            await nodeManager?.pay_invoice(currentNode, invoiceToPay, undefined, undefined);
         } catch (e) {
             console.error(e);
         }
@@ -173,7 +173,7 @@ function App() {
     async function sendKeysend(e: React.SyntheticEvent) {
         e.preventDefault()
         try {
-            await nodeManager?.keysend(currentNode, keysend, BigInt(5000));
+            await nodeManager?.keysend(currentNode, keysend, BigInt(5000), undefined);
         } catch (e) {
             console.error(e);
         }
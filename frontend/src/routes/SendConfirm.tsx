@@ -62,15 +62,15 @@ function SendConfirm() {
           navigate(`/send/final?txid=${txid}`)
         } else if (paymentType === PaymentType.keysend) {
           let myNode = await getFirstNode(nodeManager!);
-          await nodeManager?.keysend(myNode, destination, amountInt)
+          await nodeManager?.keysend(myNode, destination, amountInt, undefined)
           navigate(`/send/final`)
         } else if (paymentType === PaymentType.invoice) {
           let myNode = await getFirstNode(nodeManager!);
           let invoice = await nodeManager?.decode_invoice(destination);
           if (invoice?.amount_sats && Number(invoice?.amount_sats) > 0) {
-            await nodeManager?.pay_invoice(myNode, destination)
+            await nodeManager?.pay_invoice(myNode, destination, undefined, undefined)
           } else {
-            await nodeManager?.pay_invoice(myNode, destination, BigInt(amount))
+            await nodeManager?.pay_invoice(myNode, destination, BigInt(amount), undefined)
           }
           navigate(`/send/final`)
         }
@@ -133,8 +133,6 @@ where
     L::Target: Logger,
 {
     /// Initializes a new [`GossipSync::Rapid`] variant.
-    // FIXME: Use for routing soon
-    #[allow(dead_code)]
     pub fn rapid(gossip_sync: R) -> Self {
         GossipSync::Rapid(gossip_sync)
     }
@@ -72,6 +72,16 @@ impl MapKey<'_> {
     }
 }
 
+impl MutinyBrowserStorage {
+    // Namespaces the key with the wallet prefix, if this storage has one
+    fn bdk_key(&self, key: MapKey) -> String {
+        match self.wallet_prefix {
+            Some(ref prefix) => format!("{prefix}{}", key.as_map_key()),
+            None => key.as_map_key(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 struct ScriptPubKeyInfo {
     pub keychain: KeychainKind,
@@ -85,10 +95,10 @@ impl BatchOperations for MutinyBrowserStorage {
         keychain: KeychainKind,
         path: u32,
     ) -> Result<(), bdk::Error> {
-        let key = MapKey::Path((Some(keychain), Some(path))).as_map_key();
+        let key = self.bdk_key(MapKey::Path((Some(keychain), Some(path))));
         self.set(key, script.clone())?;
 
-        let key = MapKey::Script(Some(script)).as_map_key();
+        let key = self.bdk_key(MapKey::Script(Some(script)));
         let spk_info = ScriptPubKeyInfo { keychain, path };
         self.set(key, spk_info)?;
 
@@ -96,19 +106,19 @@ impl BatchOperations for MutinyBrowserStorage {
     }
 
     fn set_utxo(&mut self, utxo: &LocalUtxo) -> Result<(), bdk::Error> {
-        let key = MapKey::Utxo(Some(&utxo.outpoint)).as_map_key();
+        let key = self.bdk_key(MapKey::Utxo(Some(&utxo.outpoint)));
         self.set(key, utxo)?;
 
         Ok(())
     }
     fn set_raw_tx(&mut self, transaction: &Transaction) -> Result<(), bdk::Error> {
-        let key = MapKey::RawTx(Some(&transaction.txid())).as_map_key();
+        let key = self.bdk_key(MapKey::RawTx(Some(&transaction.txid())));
         self.set(key, transaction.clone())?;
 
         Ok(())
     }
     fn set_tx(&mut self, transaction: &TransactionDetails) -> Result<(), bdk::Error> {
-        let key = MapKey::Transaction(Some(&transaction.txid)).as_map_key();
+        let key = self.bdk_key(MapKey::Transaction(Some(&transaction.txid)));
 
         // insert the raw_tx if present
         if let Some(ref tx) = transaction.transaction {
@@ -124,13 +134,13 @@ impl BatchOperations for MutinyBrowserStorage {
         Ok(())
     }
     fn set_last_index(&mut self, keychain: KeychainKind, value: u32) -> Result<(), bdk::Error> {
-        let key = MapKey::LastIndex(keychain).as_map_key();
+        let key = self.bdk_key(MapKey::LastIndex(keychain));
         self.set(key, value)?;
 
         Ok(())
     }
     fn set_sync_time(&mut self, data: SyncTime) -> Result<(), bdk::Error> {
-        let key = MapKey::SyncTime.as_map_key();
+        let key = self.bdk_key(MapKey::SyncTime);
         self.set(key, data)?;
 
         Ok(())
@@ -141,7 +151,7 @@ impl BatchOperations for MutinyBrowserStorage {
         keychain: KeychainKind,
         path: u32,
     ) -> Result<Option<Script>, bdk::Error> {
-        let key = MapKey::Path((Some(keychain), Some(path))).as_map_key();
+        let key = self.bdk_key(MapKey::Path((Some(keychain), Some(path))));
         let res: Option<Script> = self.get(&key).ok();
         LocalStorage::delete(&key);
 
@@ -151,7 +161,7 @@ impl BatchOperations for MutinyBrowserStorage {
         &mut self,
         script: &Script,
     ) -> Result<Option<(KeychainKind, u32)>, bdk::Error> {
-        let key = MapKey::Script(Some(script)).as_map_key();
+        let key = self.bdk_key(MapKey::Script(Some(script)));
         let res: Option<ScriptPubKeyInfo> = self.get(&key).ok();
         LocalStorage::delete(&key);
 
@@ -161,14 +171,14 @@ impl BatchOperations for MutinyBrowserStorage {
         }
     }
     fn del_utxo(&mut self, outpoint: &OutPoint) -> Result<Option<LocalUtxo>, bdk::Error> {
-        let key = MapKey::Utxo(Some(outpoint)).as_map_key();
+        let key = self.bdk_key(MapKey::Utxo(Some(outpoint)));
         let res: Option<LocalUtxo> = self.get(&key).ok();
         LocalStorage::delete(&key);
 
         Ok(res)
     }
     fn del_raw_tx(&mut self, txid: &Txid) -> Result<Option<Transaction>, bdk::Error> {
-        let key = MapKey::RawTx(Some(txid)).as_map_key();
+        let key = self.bdk_key(MapKey::RawTx(Some(txid)));
         let res: Option<Transaction> = self.get(&key).ok();
         LocalStorage::delete(&key);
 
@@ -185,7 +195,7 @@ impl BatchOperations for MutinyBrowserStorage {
             None
         };
 
-        let key = MapKey::Transaction(Some(txid)).as_map_key();
+        let key = self.bdk_key(MapKey::Transaction(Some(txid)));
         let res: Option<TransactionDetails> = self.get(&key).ok();
         LocalStorage::delete(&key);
 
@@ -199,14 +209,14 @@ impl BatchOperations for MutinyBrowserStorage {
         }
     }
     fn del_last_index(&mut self, keychain: KeychainKind) -> Result<Option<u32>, bdk::Error> {
-        let key = MapKey::LastIndex(keychain).as_map_key();
+        let key = self.bdk_key(MapKey::LastIndex(keychain));
         let res: Option<u32> = self.get(&key).ok();
         LocalStorage::delete(&key);
 
         Ok(res)
     }
     fn del_sync_time(&mut self) -> Result<Option<SyncTime>, bdk::Error> {
-        let key = MapKey::SyncTime.as_map_key();
+        let key = self.bdk_key(MapKey::SyncTime);
         let res: Option<SyncTime> = self.get(&key).ok();
         LocalStorage::delete(&key);
 
@@ -220,7 +230,7 @@ impl Database for MutinyBrowserStorage {
         keychain: KeychainKind,
         bytes: B,
     ) -> Result<(), bdk::Error> {
-        let key = MapKey::DescriptorChecksum(keychain).as_map_key();
+        let key = self.bdk_key(MapKey::DescriptorChecksum(keychain));
 
         let prev = self.get::<Vec<u8>>(&key).ok();
         if let Some(val) = prev {
@@ -239,7 +249,7 @@ impl Database for MutinyBrowserStorage {
         &self,
         keychain: Option<KeychainKind>,
     ) -> Result<Vec<Script>, bdk::Error> {
-        let key = MapKey::Path((keychain, None)).as_map_key();
+        let key = self.bdk_key(MapKey::Path((keychain, None)));
         Ok(self
             .scan::<Script>(key.as_str(), None)
             .into_values()
@@ -247,7 +257,7 @@ impl Database for MutinyBrowserStorage {
     }
 
     fn iter_utxos(&self) -> Result<Vec<LocalUtxo>, bdk::Error> {
-        let key = MapKey::Utxo(None).as_map_key();
+        let key = self.bdk_key(MapKey::Utxo(None));
         Ok(self
             .scan::<LocalUtxo>(key.as_str(), None)
             .into_values()
@@ -255,7 +265,7 @@ impl Database for MutinyBrowserStorage {
     }
 
     fn iter_raw_txs(&self) -> Result<Vec<Transaction>, bdk::Error> {
-        let key = MapKey::RawTx(None).as_map_key();
+        let key = self.bdk_key(MapKey::RawTx(None));
         Ok(self
             .scan::<Transaction>(key.as_str(), None)
             .into_values()
@@ -263,13 +273,15 @@ impl Database for MutinyBrowserStorage {
     }
 
     fn iter_txs(&self, include_raw: bool) -> Result<Vec<TransactionDetails>, bdk::Error> {
-        let key = MapKey::Transaction(None).as_map_key();
+        let key = self.bdk_key(MapKey::Transaction(None));
+        let prefix_len = self.wallet_prefix.as_ref().map(|p| p.len()).unwrap_or(0);
         self.scan::<TransactionDetails>(key.as_str(), None)
             .into_iter()
             .map(|(key, mut tx_details)| -> Result<_, bdk::Error> {
                 if include_raw {
                     // first byte is prefix for the map, need to drop it
-                    let rm_prefix_opt = key.get(2..key.len());
+                    // along with the wallet prefix if there is one
+                    let rm_prefix_opt = key.get(prefix_len + 2..key.len());
                     match rm_prefix_opt {
                         Some(rm_prefix) => {
                             let k_bytes = Vec::from_hex(rm_prefix)?;
@@ -293,7 +305,7 @@ impl Database for MutinyBrowserStorage {
         keychain: KeychainKind,
         path: u32,
     ) -> Result<Option<Script>, bdk::Error> {
-        let key = MapKey::Path((Some(keychain), Some(path))).as_map_key();
+        let key = self.bdk_key(MapKey::Path((Some(keychain), Some(path))));
         Ok(self.get::<Script>(key).ok())
     }
 
@@ -301,7 +313,7 @@ impl Database for MutinyBrowserStorage {
         &self,
         script: &Script,
     ) -> Result<Option<(KeychainKind, u32)>, bdk::Error> {
-        let key = MapKey::Script(Some(script)).as_map_key();
+        let key = self.bdk_key(MapKey::Script(Some(script)));
         Ok(self
             .get::<ScriptPubKeyInfo>(key)
             .ok()
@@ -309,14 +321,14 @@ impl Database for MutinyBrowserStorage {
     }
 
     fn get_utxo(&self, outpoint: &OutPoint) -> Result<Option<LocalUtxo>, bdk::Error> {
-        let key = MapKey::Utxo(Some(outpoint)).as_map_key();
+        let key = self.bdk_key(MapKey::Utxo(Some(outpoint)));
         let res: Option<LocalUtxo> = self.get(key).ok();
 
         Ok(res)
     }
 
     fn get_raw_tx(&self, txid: &Txid) -> Result<Option<Transaction>, bdk::Error> {
-        let key = MapKey::RawTx(Some(txid)).as_map_key();
+        let key = self.bdk_key(MapKey::RawTx(Some(txid)));
         Ok(self.get::<Transaction>(key).ok())
     }
 
@@ -325,7 +337,7 @@ impl Database for MutinyBrowserStorage {
         txid: &Txid,
         include_raw: bool,
     ) -> Result<Option<TransactionDetails>, bdk::Error> {
-        let key = MapKey::Transaction(Some(txid)).as_map_key();
+        let key = self.bdk_key(MapKey::Transaction(Some(txid)));
         Ok(self
             .get::<TransactionDetails>(key)
             .ok()
@@ -339,18 +351,18 @@ impl Database for MutinyBrowserStorage {
     }
 
     fn get_last_index(&self, keychain: KeychainKind) -> Result<Option<u32>, bdk::Error> {
-        let key = MapKey::LastIndex(keychain).as_map_key();
+        let key = self.bdk_key(MapKey::LastIndex(keychain));
         Ok(self.get::<u32>(key).ok())
     }
 
     fn get_sync_time(&self) -> Result<Option<SyncTime>, bdk::Error> {
-        let key = MapKey::SyncTime.as_map_key();
+        let key = self.bdk_key(MapKey::SyncTime);
         Ok(self.get::<SyncTime>(key).ok())
     }
 
     // inserts 0 if not present
     fn increment_last_index(&mut self, keychain: KeychainKind) -> Result<u32, bdk::Error> {
-        let key = MapKey::LastIndex(keychain).as_map_key();
+        let key = self.bdk_key(MapKey::LastIndex(keychain));
         let current_opt = self.get::<u32>(&key).ok();
         let value = current_opt.map(|s| s + 1).unwrap_or_else(|| 0);
         self.set(key, value)?;
@@ -363,7 +375,7 @@ impl BatchDatabase for MutinyBrowserStorage {
     type Batch = Self;
 
     fn begin_batch(&self) -> Self::Batch {
-        MutinyBrowserStorage::new(self.clone().password)
+        self.clone()
     }
 
     fn commit_batch(&mut self, mut _batch: Self::Batch) -> Result<(), bdk::Error> {
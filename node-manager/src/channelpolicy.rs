@@ -0,0 +1,436 @@
+use crate::error::MutinyError;
+use crate::ldkstorage::{MutinyNodePersister, PhantomChannelManager};
+use bitcoin_hashes::hex::ToHex;
+use lightning::ln::features::ChannelTypeFeatures;
+use log::{error, info};
+use secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use wasm_bindgen::prelude::*;
+
+/// A channel type feature that inbound channels must negotiate for us to accept them.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RequiredChannelType {
+    StaticRemoteKey,
+    ScidPrivacy,
+}
+
+impl RequiredChannelType {
+    fn is_satisfied_by(&self, channel_type: &ChannelTypeFeatures) -> bool {
+        match self {
+            RequiredChannelType::StaticRemoteKey => channel_type.supports_static_remote_key(),
+            RequiredChannelType::ScidPrivacy => channel_type.supports_scid_privacy(),
+        }
+    }
+}
+
+/// The rules a node uses to decide on inbound channel requests.
+///
+/// Requests that break a rule are rejected. Requests from the allowlist or from
+/// zero-conf peers are accepted. Everything else is accepted, unless
+/// `manual_approval` is set, in which case it is queued for the user.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ChannelAcceptancePolicy {
+    /// Pubkeys of the peers whose channels we always accept
+    #[serde(default)]
+    pub allowlist: Vec<String>,
+    /// Pubkeys of the peers we trust to open zero-conf channels to us, and to let us use
+    /// the channels we open to them before they confirm. Nobody else gets zero-conf channels.
+    #[serde(default)]
+    pub zero_conf_peers: Vec<String>,
+    #[serde(default)]
+    pub min_channel_size: Option<u64>,
+    #[serde(default)]
+    pub max_channel_size: Option<u64>,
+    #[serde(default)]
+    pub required_channel_types: Vec<RequiredChannelType>,
+    /// Queue requests from peers that are not allowlisted instead of accepting them
+    #[serde(default)]
+    pub manual_approval: bool,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) enum ChannelDecision {
+    Accept { zero_conf: bool },
+    Reject(&'static str),
+    Queue,
+}
+
+impl ChannelAcceptancePolicy {
+    fn contains(list: &[String], pubkey: &PublicKey) -> bool {
+        let pubkey = pubkey.to_hex();
+        list.iter().any(|p| p.to_lowercase() == pubkey)
+    }
+
+    pub(crate) fn is_zero_conf_peer(&self, pubkey: &PublicKey) -> bool {
+        Self::contains(&self.zero_conf_peers, pubkey)
+    }
+
+    pub(crate) fn decide(
+        &self,
+        counterparty_node_id: &PublicKey,
+        funding_satoshis: u64,
+        channel_type: &ChannelTypeFeatures,
+    ) -> ChannelDecision {
+        if self
+            .min_channel_size
+            .map_or(false, |min| funding_satoshis < min)
+        {
+            return ChannelDecision::Reject("channel is smaller than the minimum channel size");
+        }
+        if self
+            .max_channel_size
+            .map_or(false, |max| funding_satoshis > max)
+        {
+            return ChannelDecision::Reject("channel is larger than the maximum channel size");
+        }
+        if !self
+            .required_channel_types
+            .iter()
+            .all(|t| t.is_satisfied_by(channel_type))
+        {
+            return ChannelDecision::Reject("channel does not have the required channel type");
+        }
+
+        let zero_conf = self.is_zero_conf_peer(counterparty_node_id);
+        if channel_type.requires_zero_conf() && !zero_conf {
+            return ChannelDecision::Reject(
+                "zero-conf channels are only accepted from trusted peers",
+            );
+        }
+
+        if zero_conf {
+            ChannelDecision::Accept { zero_conf: true }
+        } else if Self::contains(&self.allowlist, counterparty_node_id) || !self.manual_approval {
+            ChannelDecision::Accept { zero_conf: false }
+        } else {
+            ChannelDecision::Queue
+        }
+    }
+}
+
+/// An inbound channel request waiting for the user to approve or reject it.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[wasm_bindgen]
+pub struct MutinyChannelRequest {
+    temporary_channel_id: String,
+    peer: String,
+    pub funding_satoshis: u64,
+    pub push_msat: u64,
+}
+
+#[wasm_bindgen]
+impl MutinyChannelRequest {
+    #[wasm_bindgen(getter)]
+    pub fn temporary_channel_id(&self) -> String {
+        self.temporary_channel_id.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn peer(&self) -> String {
+        self.peer.clone()
+    }
+}
+
+#[derive(Clone)]
+struct PendingChannelRequest {
+    counterparty_node_id: PublicKey,
+    funding_satoshis: u64,
+    push_msat: u64,
+}
+
+/// Applies a node's [`ChannelAcceptancePolicy`] to its inbound channel requests.
+///
+/// Queued requests are only kept in memory, LDK forgets about unaccepted channels
+/// when the peer disconnects, so there is nothing to restore on startup.
+pub(crate) struct ChannelAcceptor {
+    channel_manager: Arc<PhantomChannelManager>,
+    persister: Arc<MutinyNodePersister>,
+    policy: Mutex<ChannelAcceptancePolicy>,
+    pending: Mutex<HashMap<[u8; 32], PendingChannelRequest>>,
+}
+
+impl ChannelAcceptor {
+    pub(crate) fn new(
+        channel_manager: Arc<PhantomChannelManager>,
+        persister: Arc<MutinyNodePersister>,
+    ) -> Result<Self, MutinyError> {
+        let policy = persister.read_channel_policy()?;
+        Ok(Self {
+            channel_manager,
+            persister,
+            policy: Mutex::new(policy),
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub(crate) fn policy(&self) -> ChannelAcceptancePolicy {
+        self.policy.lock().unwrap().clone()
+    }
+
+    pub(crate) fn set_policy(&self, policy: ChannelAcceptancePolicy) -> Result<(), MutinyError> {
+        for pubkey in policy.allowlist.iter().chain(policy.zero_conf_peers.iter()) {
+            if PublicKey::from_str(pubkey).is_err() {
+                return Err(MutinyError::PeerInfoParseFailed);
+            }
+        }
+        if let (Some(min), Some(max)) = (policy.min_channel_size, policy.max_channel_size) {
+            if min > max {
+                return Err(MutinyError::InvalidArgumentsError);
+            }
+        }
+
+        self.persister.persist_channel_policy(&policy)?;
+        *self.policy.lock().unwrap() = policy;
+        Ok(())
+    }
+
+    pub(crate) fn handle_open_channel_request(
+        &self,
+        temporary_channel_id: [u8; 32],
+        counterparty_node_id: PublicKey,
+        funding_satoshis: u64,
+        push_msat: u64,
+        channel_type: &ChannelTypeFeatures,
+    ) {
+        let decision = self
+            .policy()
+            .decide(&counterparty_node_id, funding_satoshis, channel_type);
+
+        match decision {
+            ChannelDecision::Accept { zero_conf } => {
+                if let Err(e) = self.accept(&temporary_channel_id, &counterparty_node_id, zero_conf)
+                {
+                    error!("could not accept channel from {counterparty_node_id}: {e}");
+                }
+            }
+            ChannelDecision::Reject(reason) => {
+                info!("rejecting channel from {counterparty_node_id}: {reason}");
+                if let Err(e) = self.reject(&temporary_channel_id, &counterparty_node_id) {
+                    error!("could not reject channel from {counterparty_node_id}: {e}");
+                }
+            }
+            ChannelDecision::Queue => {
+                info!("queueing channel from {counterparty_node_id} for approval");
+                self.pending.lock().unwrap().insert(
+                    temporary_channel_id,
+                    PendingChannelRequest {
+                        counterparty_node_id,
+                        funding_satoshis,
+                        push_msat,
+                    },
+                );
+            }
+        }
+    }
+
+    pub(crate) fn list_pending_requests(&self) -> Vec<MutinyChannelRequest> {
+        self.pending
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, req)| MutinyChannelRequest {
+                temporary_channel_id: id.to_hex(),
+                peer: req.counterparty_node_id.to_hex(),
+                funding_satoshis: req.funding_satoshis,
+                push_msat: req.push_msat,
+            })
+            .collect()
+    }
+
+    /// Accepts a queued request. Zero-conf is only used if the peer is trusted by the policy.
+    pub(crate) fn approve_request(
+        &self,
+        temporary_channel_id: [u8; 32],
+    ) -> Result<(), MutinyError> {
+        let req = self.take_pending(&temporary_channel_id)?;
+        let zero_conf = self.policy().is_zero_conf_peer(&req.counterparty_node_id);
+        self.accept(&temporary_channel_id, &req.counterparty_node_id, zero_conf)
+    }
+
+    pub(crate) fn reject_request(&self, temporary_channel_id: [u8; 32]) -> Result<(), MutinyError> {
+        let req = self.take_pending(&temporary_channel_id)?;
+        self.reject(&temporary_channel_id, &req.counterparty_node_id)
+    }
+
+    /// Drops a queued request once LDK has closed the channel, e.g. when the peer disconnects.
+    pub(crate) fn forget_request(&self, temporary_channel_id: &[u8; 32]) {
+        self.pending.lock().unwrap().remove(temporary_channel_id);
+    }
+
+    fn take_pending(
+        &self,
+        temporary_channel_id: &[u8; 32],
+    ) -> Result<PendingChannelRequest, MutinyError> {
+        self.pending
+            .lock()
+            .unwrap()
+            .remove(temporary_channel_id)
+            .ok_or(MutinyError::ChannelRequestNotFound)
+    }
+
+    fn accept(
+        &self,
+        temporary_channel_id: &[u8; 32],
+        counterparty_node_id: &PublicKey,
+        zero_conf: bool,
+    ) -> Result<(), MutinyError> {
+        let res = if zero_conf {
+            self.channel_manager
+                .accept_inbound_channel_from_trusted_peer_0conf(
+                    temporary_channel_id,
+                    counterparty_node_id,
+                    0,
+                )
+        } else {
+            self.channel_manager.accept_inbound_channel(
+                temporary_channel_id,
+                counterparty_node_id,
+                0,
+            )
+        };
+
+        res.map_err(|e| {
+            error!("failed to accept channel: {e:?}");
+            MutinyError::ChannelCreationFailed
+        })
+    }
+
+    fn reject(
+        &self,
+        temporary_channel_id: &[u8; 32],
+        counterparty_node_id: &PublicKey,
+    ) -> Result<(), MutinyError> {
+        // force closing an unaccepted channel is how LDK rejects it
+        self.channel_manager
+            .force_close_broadcasting_latest_txn(temporary_channel_id, counterparty_node_id)
+            .map_err(|e| {
+                error!("failed to reject channel: {e:?}");
+                MutinyError::ChannelClosingFailed
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const PEER: &str = "0218845781f631c48f1c9709e23092067d06837f30aa0cd0544ac887fe91ddd166";
+    const OTHER_PEER: &str = "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54";
+
+    fn channel_type() -> ChannelTypeFeatures {
+        let mut features = ChannelTypeFeatures::empty();
+        features.set_static_remote_key_required();
+        features
+    }
+
+    #[test]
+    fn test_default_policy_accepts() {
+        log!("default policy accepts");
+
+        let policy = ChannelAcceptancePolicy::default();
+        let peer = PublicKey::from_str(PEER).unwrap();
+
+        assert_eq!(
+            policy.decide(&peer, 100_000, &channel_type()),
+            ChannelDecision::Accept { zero_conf: false }
+        );
+    }
+
+    #[test]
+    fn test_policy_decisions() {
+        log!("policy decisions");
+
+        let peer = PublicKey::from_str(PEER).unwrap();
+        let policy = ChannelAcceptancePolicy {
+            allowlist: vec![PEER.to_string()],
+            min_channel_size: Some(20_000),
+            max_channel_size: Some(1_000_000),
+            required_channel_types: vec![RequiredChannelType::ScidPrivacy],
+            manual_approval: true,
+            ..Default::default()
+        };
+
+        let mut private_type = channel_type();
+        private_type.set_scid_privacy_required();
+
+        assert_eq!(
+            policy.decide(&peer, 100_000, &private_type),
+            ChannelDecision::Accept { zero_conf: false }
+        );
+        assert!(matches!(
+            policy.decide(&peer, 10_000, &private_type),
+            ChannelDecision::Reject(_)
+        ));
+        assert!(matches!(
+            policy.decide(&peer, 2_000_000, &private_type),
+            ChannelDecision::Reject(_)
+        ));
+        assert!(matches!(
+            policy.decide(&peer, 100_000, &channel_type()),
+            ChannelDecision::Reject(_)
+        ));
+
+        let other_peer = PublicKey::from_str(OTHER_PEER).unwrap();
+        assert_eq!(
+            policy.decide(&other_peer, 100_000, &private_type),
+            ChannelDecision::Queue
+        );
+
+        let policy = ChannelAcceptancePolicy {
+            zero_conf_peers: vec![OTHER_PEER.to_string()],
+            ..policy
+        };
+        assert_eq!(
+            policy.decide(&other_peer, 100_000, &private_type),
+            ChannelDecision::Accept { zero_conf: true }
+        );
+    }
+
+    #[test]
+    fn test_zero_conf_decisions() {
+        log!("zero-conf decisions");
+
+        let trusted = PublicKey::from_str(OTHER_PEER).unwrap();
+        let untrusted = PublicKey::from_str(PEER).unwrap();
+        let policy = ChannelAcceptancePolicy {
+            allowlist: vec![PEER.to_string()],
+            zero_conf_peers: vec![OTHER_PEER.to_uppercase()],
+            ..Default::default()
+        };
+
+        let mut zero_conf_type = channel_type();
+        zero_conf_type.set_zero_conf_required();
+
+        // a trusted peer's channel can be used before it confirms, whatever it asks for
+        assert_eq!(
+            policy.decide(&trusted, 100_000, &zero_conf_type),
+            ChannelDecision::Accept { zero_conf: true }
+        );
+        assert_eq!(
+            policy.decide(&trusted, 100_000, &channel_type()),
+            ChannelDecision::Accept { zero_conf: true }
+        );
+
+        // anyone else has to wait for the funding transaction to confirm, even when allowlisted
+        assert!(matches!(
+            policy.decide(&untrusted, 100_000, &zero_conf_type),
+            ChannelDecision::Reject(_)
+        ));
+        assert_eq!(
+            policy.decide(&untrusted, 100_000, &channel_type()),
+            ChannelDecision::Accept { zero_conf: false }
+        );
+        assert!(matches!(
+            ChannelAcceptancePolicy::default().decide(&trusted, 100_000, &zero_conf_type),
+            ChannelDecision::Reject(_)
+        ));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::event::FailedPayment;
 use bdk::esplora_client;
 use lightning::ln::peer_handler::PeerHandleError;
 use lightning_invoice::payment::PaymentError;
@@ -37,6 +38,24 @@ pub enum MutinyError {
     /// No route for the given target could be found.
     #[error("Failed to find route.")]
     RoutingFailed,
+    /// The recipient failed the payment, e.g. because the invoice was already paid or expired.
+    #[error("The recipient rejected the payment.")]
+    RecipientRejected,
+    /// The payment did not resolve before the timeout, it may still succeed or fail later.
+    #[error("Payment timed out.")]
+    PaymentTimeout,
+    /// The cheapest route found costs more than the maximum routing fee.
+    #[error("The routing fee is higher than the maximum fee.")]
+    RoutingFeeTooHigh,
+    /// An outbound payment failed, with the last path tried.
+    #[error("{0}")]
+    PaymentFailed(Box<FailedPayment>),
+    /// An LNURL or Lightning Address could not be resolved, or its service returned an error.
+    #[error("Failed to use the LNURL.")]
+    LnUrlFailure,
+    /// No payment to the hold invoice is held.
+    #[error("Failed to find a held payment for the hold invoice.")]
+    HoldInvoiceNotFound,
     /// A given peer info could not be parsed.
     #[error("Failed to parse the given peer information.")]
     PeerInfoParseFailed,
@@ -46,6 +65,12 @@ pub enum MutinyError {
     /// A channel could not be closed.
     #[error("Failed to close channel.")]
     ChannelClosingFailed,
+    /// No channel matches the given outpoint or id.
+    #[error("Failed to find the channel.")]
+    ChannelNotFound,
+    /// No pending inbound channel request matches the given id.
+    #[error("Failed to find the channel request.")]
+    ChannelRequestNotFound,
     /// Persistence failed.
     #[error("Failed to persist data.")]
     PersistenceFailed {
@@ -71,6 +96,18 @@ pub enum MutinyError {
     /// A chain access operation failed.
     #[error("Failed to conduct chain access operation.")]
     ChainAccessFailed,
+    /// The given descriptor or xpub is invalid.
+    #[error("The given descriptor or xpub is invalid.")]
+    InvalidDescriptor,
+    /// Invalid arguments were given.
+    #[error("Invalid arguments were given")]
+    InvalidArgumentsError,
+    /// Failed to get or apply a rapid gossip sync snapshot.
+    #[error("Failed to sync the network graph with rapid gossip sync.")]
+    RapidGossipSyncError,
+    /// The transaction would spend the funds reserved for fee bumping anchor channels.
+    #[error("Cannot spend the on-chain funds reserved for anchor channels.")]
+    AnchorReserveError,
     /// A error with DLCs
     #[error("Failed to execute a dlc function")]
     DLCManagerError,
@@ -197,6 +234,24 @@ pub enum MutinyJsError {
     /// No route for the given target could be found.
     #[error("Failed to find route.")]
     RoutingFailed,
+    /// The recipient failed the payment, e.g. because the invoice was already paid or expired.
+    #[error("The recipient rejected the payment.")]
+    RecipientRejected,
+    /// The payment did not resolve before the timeout, it may still succeed or fail later.
+    #[error("Payment timed out.")]
+    PaymentTimeout,
+    /// The cheapest route found costs more than the maximum routing fee.
+    #[error("The routing fee is higher than the maximum fee.")]
+    RoutingFeeTooHigh,
+    /// An outbound payment failed, the message tells why and where the last path failed.
+    #[error("{0}")]
+    PaymentFailed(String),
+    /// An LNURL or Lightning Address could not be resolved, or its service returned an error.
+    #[error("Failed to use the LNURL.")]
+    LnUrlFailure,
+    /// No payment to the hold invoice is held.
+    #[error("Failed to find a held payment for the hold invoice.")]
+    HoldInvoiceNotFound,
     /// A given peer info could not be parsed.
     #[error("Failed to parse the given peer information.")]
     PeerInfoParseFailed,
@@ -206,6 +261,12 @@ pub enum MutinyJsError {
     /// A channel could not be closed.
     #[error("Failed to close channel.")]
     ChannelClosingFailed,
+    /// No channel matches the given outpoint or id.
+    #[error("Failed to find the channel.")]
+    ChannelNotFound,
+    /// No pending inbound channel request matches the given id.
+    #[error("Failed to find the channel request.")]
+    ChannelRequestNotFound,
     /// Persistence failed.
     #[error("Failed to persist data.")]
     PersistenceFailed,
@@ -240,6 +301,18 @@ pub enum MutinyJsError {
     /// Error converting JS f64 value to Amount
     #[error("Failed to convert to satoshis")]
     BadAmountError,
+    /// The given descriptor or xpub is invalid.
+    #[error("The given descriptor or xpub is invalid.")]
+    InvalidDescriptor,
+    /// Invalid arguments were given.
+    #[error("Invalid arguments were given")]
+    InvalidArgumentsError,
+    /// Failed to get or apply a rapid gossip sync snapshot.
+    #[error("Failed to sync the network graph with rapid gossip sync.")]
+    RapidGossipSyncError,
+    /// The transaction would spend the funds reserved for fee bumping anchor channels.
+    #[error("Cannot spend the on-chain funds reserved for anchor channels.")]
+    AnchorReserveError,
     /// A error with DLCs
     #[error("Failed to execute a dlc function")]
     DLCManagerError,
@@ -260,9 +333,17 @@ impl From<MutinyError> for MutinyJsError {
             MutinyError::InvoiceInvalid => MutinyJsError::InvoiceInvalid,
             MutinyError::InvoiceCreationFailed => MutinyJsError::InvoiceCreationFailed,
             MutinyError::RoutingFailed => MutinyJsError::RoutingFailed,
+            MutinyError::RecipientRejected => MutinyJsError::RecipientRejected,
+            MutinyError::PaymentTimeout => MutinyJsError::PaymentTimeout,
+            MutinyError::RoutingFeeTooHigh => MutinyJsError::RoutingFeeTooHigh,
+            MutinyError::PaymentFailed(failed) => MutinyJsError::PaymentFailed(failed.to_string()),
+            MutinyError::LnUrlFailure => MutinyJsError::LnUrlFailure,
+            MutinyError::HoldInvoiceNotFound => MutinyJsError::HoldInvoiceNotFound,
             MutinyError::PeerInfoParseFailed => MutinyJsError::PeerInfoParseFailed,
             MutinyError::ChannelCreationFailed => MutinyJsError::ChannelCreationFailed,
             MutinyError::ChannelClosingFailed => MutinyJsError::ChannelClosingFailed,
+            MutinyError::ChannelNotFound => MutinyJsError::ChannelNotFound,
+            MutinyError::ChannelRequestNotFound => MutinyJsError::ChannelRequestNotFound,
             MutinyError::PersistenceFailed { source: _ } => MutinyJsError::PersistenceFailed,
             MutinyError::ReadError { source: _ } => MutinyJsError::ReadError,
             MutinyError::LnDecodeError => MutinyJsError::LnDecodeError,
@@ -271,6 +352,10 @@ impl From<MutinyError> for MutinyJsError {
             MutinyError::InvalidMnemonic => MutinyJsError::InvalidMnemonic,
             MutinyError::WalletSigningFailed => MutinyJsError::WalletSigningFailed,
             MutinyError::ChainAccessFailed => MutinyJsError::ChainAccessFailed,
+            MutinyError::InvalidDescriptor => MutinyJsError::InvalidDescriptor,
+            MutinyError::InvalidArgumentsError => MutinyJsError::InvalidArgumentsError,
+            MutinyError::RapidGossipSyncError => MutinyJsError::RapidGossipSyncError,
+            MutinyError::AnchorReserveError => MutinyJsError::AnchorReserveError,
             MutinyError::DLCManagerError => MutinyJsError::DLCManagerError,
             MutinyError::Other(_) => MutinyJsError::UnknownError,
         }
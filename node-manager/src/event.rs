@@ -1,21 +1,47 @@
+use crate::channelpolicy::ChannelAcceptor;
+use crate::error::MutinyError;
+use crate::eventbus::{EventBus, MutinyEvent};
+use crate::holdinvoice::{HoldInvoice, HoldInvoices};
 use crate::ldkstorage::MutinyNodePersister;
 use crate::logging::MutinyLogger;
+use crate::paymentpolicy::OutboundPayments;
 use crate::utils::sleep;
 use crate::wallet::MutinyWallet;
 use crate::{chain::MutinyChain, ldkstorage::PhantomChannelManager};
-use bdk::blockchain::Blockchain;
+use bdk::blockchain::{Blockchain, GetTx};
 use bdk::wallet::AddressIndex;
-use bitcoin::secp256k1::Secp256k1;
-use bitcoin::{Address, Network};
+use bitcoin::secp256k1::{PublicKey, Secp256k1};
+use bitcoin::{Address, Network, Script};
 use bitcoin_bech32::WitnessProgram;
 use bitcoin_hashes::hex::ToHex;
+use futures::channel::oneshot;
+#[cfg(anchors)]
+use lightning::chain::chaininterface::BroadcasterInterface;
 use lightning::chain::chaininterface::{ConfirmationTarget, FeeEstimator};
-use lightning::chain::keysinterface::PhantomKeysManager;
-use lightning::util::events::{Event, PaymentPurpose};
+#[cfg(anchors)]
+use lightning::chain::keysinterface::BaseSign;
+use lightning::chain::keysinterface::{PhantomKeysManager, SpendableOutputDescriptor};
+use lightning::chain::transaction::OutPoint;
+#[cfg(anchors)]
+use lightning::ln::chan_utils;
+use lightning::ln::PaymentHash;
+use lightning::routing::gossip::NetworkUpdate;
+use lightning::routing::router::RouteHop;
+#[cfg(anchors)]
+use lightning::util::events::BumpTransactionEvent;
+use lightning::util::events::{Event, HTLCDestination, PaymentPurpose};
 use lightning::util::logger::{Logger, Record};
+use lightning_invoice::Invoice;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// The witness of a signed anchor input, a signature and the anchor script
+#[cfg(anchors)]
+const ANCHOR_INPUT_WITNESS_WEIGHT: usize = 116;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub(crate) struct PaymentInfo {
@@ -26,6 +52,144 @@ pub(crate) struct PaymentInfo {
     pub fee_paid_msat: Option<u64>,
     pub bolt11: Option<String>,
     pub last_update: u64,
+    #[serde(default)]
+    pub attempts: Vec<PaymentAttempt>,
+    #[serde(default)]
+    pub hold: Option<HoldInvoice>,
+}
+
+/// A path we tried to send (part of) an outbound payment over.
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct PaymentAttempt {
+    /// Starts at 0, anything above is a retry.
+    pub attempt: u32,
+    /// The short channel ids of the path, from our channel to the recipient.
+    pub path: Vec<u64>,
+    /// The routing fees of the path in msats.
+    pub fee_msat: u64,
+    /// The routing fees of this and all the previous attempts in msats.
+    pub total_fee_msat: u64,
+    pub succeeded: bool,
+    /// The channel the path failed at.
+    pub failing_channel: Option<u64>,
+    /// The node that failed the path, or the one forwarding over the failing channel.
+    pub failing_node: Option<String>,
+    /// What went wrong, if the attempt failed.
+    pub error: Option<String>,
+    /// The failure code of a path failed by a node on it, None if the path never left our node.
+    #[serde(default)]
+    pub failure_code: Option<FailureCode>,
+    /// Whether the failure was final, no retry can succeed.
+    pub permanent: bool,
+    pub timestamp: u64,
+}
+
+impl PaymentAttempt {
+    pub(crate) fn succeeded(path: &[RouteHop]) -> Self {
+        Self::new(path, true, None, false)
+    }
+
+    pub(crate) fn failed(path: &[RouteHop], error: String, permanent: bool) -> Self {
+        Self::new(path, false, Some(error), permanent)
+    }
+
+    fn new(path: &[RouteHop], succeeded: bool, error: Option<String>, permanent: bool) -> Self {
+        // the last hop's fee is the amount paid to the recipient
+        let fee_msat = match path.split_last() {
+            Some((_, hops)) => hops.iter().map(|h| h.fee_msat).sum(),
+            None => 0,
+        };
+        PaymentAttempt {
+            attempt: 0,
+            path: path.iter().map(|h| h.short_channel_id).collect(),
+            fee_msat,
+            total_fee_msat: fee_msat,
+            succeeded,
+            failing_channel: None,
+            failing_node: None,
+            error,
+            failure_code: None,
+            permanent,
+            timestamp: crate::utils::now().as_secs(),
+        }
+    }
+
+    /// Records where a path that left our node failed, as far as LDK tells us.
+    pub(crate) fn set_failure(
+        &mut self,
+        path: &[RouteHop],
+        our_node_id: PublicKey,
+        network_update: Option<&NetworkUpdate>,
+        short_channel_id: Option<u64>,
+    ) {
+        // the node forwarding over a channel is the one reporting trouble with it
+        let forwarding_node = |short_channel_id: u64| {
+            let index = path
+                .iter()
+                .position(|h| h.short_channel_id == short_channel_id)?;
+            Some(index.checked_sub(1).map_or(our_node_id, |i| path[i].pubkey))
+        };
+
+        let (channel, node) = match network_update {
+            Some(NetworkUpdate::ChannelUpdateMessage { msg }) => {
+                let channel = msg.contents.short_channel_id;
+                (Some(channel), forwarding_node(channel))
+            }
+            Some(NetworkUpdate::ChannelFailure {
+                short_channel_id, ..
+            }) => (Some(*short_channel_id), forwarding_node(*short_channel_id)),
+            Some(NetworkUpdate::NodeFailure { node_id, .. }) => {
+                let channel = path
+                    .iter()
+                    .find(|h| h.pubkey == *node_id)
+                    .map(|h| h.short_channel_id);
+                (channel.or(short_channel_id), Some(*node_id))
+            }
+            // the recipient failed the payment
+            None if self.permanent => (
+                path.last().map(|h| h.short_channel_id).or(short_channel_id),
+                path.last().map(|h| h.pubkey),
+            ),
+            None => (short_channel_id, short_channel_id.and_then(forwarding_node)),
+        };
+
+        self.failing_channel = channel;
+        self.failing_node = node.map(|n| n.to_hex());
+        self.failure_code = Some(FailureCode::Unavailable);
+    }
+}
+
+/// The BOLT 4 failure code of a failed path.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FailureCode {
+    Bolt4(u16),
+    /// LDK 0.0.113 decodes the failure code but doesn't pass it on with the event
+    Unavailable,
+}
+
+impl PaymentInfo {
+    /// Adds the attempt after the previous ones, numbering it and summing up its fees.
+    pub(crate) fn add_attempt(&mut self, mut attempt: PaymentAttempt) -> &PaymentAttempt {
+        if let Some(last) = self.attempts.last() {
+            attempt.attempt = last.attempt + 1;
+            attempt.total_fee_msat += last.total_fee_msat;
+        }
+        self.attempts.push(attempt);
+        &self.attempts[self.attempts.len() - 1]
+    }
+
+    /// Whether this is an unpaid invoice that can't be paid anymore.
+    pub(crate) fn is_expired(&self, now: Duration) -> bool {
+        match self.status {
+            HTLCStatus::Expired => true,
+            HTLCStatus::Pending => self
+                .bolt11
+                .as_deref()
+                .and_then(|bolt11| Invoice::from_str(bolt11).ok())
+                .map_or(false, |invoice| invoice.would_expire(now)),
+            _ => false,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -37,6 +201,120 @@ pub(crate) enum HTLCStatus {
     InFlight,
     Succeeded,
     Failed,
+    /// Claimable but held until the hold invoice is settled or cancelled
+    Held,
+    /// The invoice expired before it was paid
+    Expired,
+}
+
+/// Why an outbound payment failed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PaymentFailure {
+    /// The recipient or a node on the last path failed the payment permanently.
+    RecipientRejected,
+    /// No route was left to retry the payment over.
+    RouteNotFound,
+    /// The only routes left cost more than the policy's maximum routing fee.
+    RoutingFeeTooHigh,
+    /// The policy didn't allow another retry.
+    RetriesExhausted,
+}
+
+/// A failed outbound payment with the last path we tried.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FailedPayment {
+    pub failure: PaymentFailure,
+    pub last_attempt: Option<PaymentAttempt>,
+}
+
+impl FailedPayment {
+    fn new(failure: PaymentFailure) -> Self {
+        Self {
+            failure,
+            last_attempt: None,
+        }
+    }
+}
+
+impl fmt::Display for FailedPayment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.failure {
+            PaymentFailure::RecipientRejected => write!(f, "The recipient rejected the payment.")?,
+            PaymentFailure::RouteNotFound => write!(f, "Failed to find route.")?,
+            PaymentFailure::RoutingFeeTooHigh => {
+                write!(f, "The routing fee is higher than the maximum fee.")?
+            }
+            PaymentFailure::RetriesExhausted => write!(f, "The payment ran out of retries.")?,
+        }
+
+        if let Some(attempt) = &self.last_attempt {
+            write!(f, " Attempt {} failed", attempt.attempt)?;
+            if let Some(channel) = attempt.failing_channel {
+                write!(f, " at channel {channel}")?;
+            }
+            if let Some(node) = &attempt.failing_node {
+                write!(f, " of node {node}")?;
+            }
+            if let Some(error) = &attempt.error {
+                write!(f, ": {error}")?;
+            }
+            write!(f, ".")?;
+        }
+        Ok(())
+    }
+}
+
+impl From<FailedPayment> for MutinyError {
+    fn from(failed: FailedPayment) -> Self {
+        MutinyError::PaymentFailed(Box::new(failed))
+    }
+}
+
+/// Lets callers wait for an outbound payment to be resolved by the [`EventHandler`].
+#[derive(Default)]
+pub(crate) struct PaymentWaiters {
+    waiters: Mutex<HashMap<PaymentHash, Vec<oneshot::Sender<Result<(), FailedPayment>>>>>,
+    // The reason of the last failed path, reported when the payment fails
+    path_failures: Mutex<HashMap<PaymentHash, FailedPayment>>,
+}
+
+impl PaymentWaiters {
+    pub(crate) fn wait(
+        &self,
+        payment_hash: PaymentHash,
+    ) -> oneshot::Receiver<Result<(), FailedPayment>> {
+        let (sender, receiver) = oneshot::channel();
+        self.waiters
+            .lock()
+            .unwrap()
+            .entry(payment_hash)
+            .or_default()
+            .push(sender);
+        receiver
+    }
+
+    fn path_failed(&self, payment_hash: PaymentHash, failure: FailedPayment) {
+        self.path_failures
+            .lock()
+            .unwrap()
+            .insert(payment_hash, failure);
+    }
+
+    fn resolve(&self, payment_hash: &PaymentHash, succeeded: bool) {
+        let failure = self.path_failures.lock().unwrap().remove(payment_hash);
+        let result = if succeeded {
+            Ok(())
+        } else {
+            Err(failure.unwrap_or_else(|| FailedPayment::new(PaymentFailure::RouteNotFound)))
+        };
+
+        if let Some(waiters) = self.waiters.lock().unwrap().remove(payment_hash) {
+            for waiter in waiters {
+                // the waiter may have timed out already
+                let _ = waiter.send(result.clone());
+            }
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -46,17 +324,28 @@ pub struct EventHandler {
     wallet: Arc<MutinyWallet>,
     keys_manager: Arc<PhantomKeysManager>,
     persister: Arc<MutinyNodePersister>,
+    channel_acceptor: Arc<ChannelAcceptor>,
+    outbound_payments: Arc<OutboundPayments>,
+    hold_invoices: Arc<HoldInvoices>,
+    payment_waiters: Arc<PaymentWaiters>,
+    event_bus: Arc<EventBus>,
     network: Network,
     logger: Arc<MutinyLogger>,
 }
 
 impl EventHandler {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         channel_manager: Arc<PhantomChannelManager>,
         chain: Arc<MutinyChain>,
         wallet: Arc<MutinyWallet>,
         keys_manager: Arc<PhantomKeysManager>,
         persister: Arc<MutinyNodePersister>,
+        channel_acceptor: Arc<ChannelAcceptor>,
+        outbound_payments: Arc<OutboundPayments>,
+        hold_invoices: Arc<HoldInvoices>,
+        payment_waiters: Arc<PaymentWaiters>,
+        event_bus: Arc<EventBus>,
         network: Network,
         logger: Arc<MutinyLogger>,
     ) -> Self {
@@ -67,11 +356,18 @@ impl EventHandler {
             keys_manager,
             network,
             persister,
+            channel_acceptor,
+            outbound_payments,
+            hold_invoices,
+            payment_waiters,
+            event_bus,
             logger,
         }
     }
 
     pub async fn handle_event(&self, event: Event) {
+        self.outbound_payments.update_scorer(&event);
+
         match event {
             Event::FundingGenerationReady {
                 temporary_channel_id,
@@ -103,12 +399,12 @@ impl EventHandler {
 
                 let address = Address::from_str(addr.as_str()).expect("Failed to parse address");
 
-                let psbt = match self
+                let funding_tx = match self
                     .wallet
-                    .create_signed_psbt(address, channel_value_satoshis, None)
+                    .create_funding_psbt(address, channel_value_satoshis, None)
                     .await
                 {
-                    Ok(psbt) => psbt,
+                    Ok(psbt) => psbt.extract_tx(),
                     Err(e) => {
                         self.logger.log(&Record::new(
                                 lightning::util::logger::Level::Error,
@@ -125,10 +421,23 @@ impl EventHandler {
                     .funding_transaction_generated(
                         &temporary_channel_id,
                         &counterparty_node_id,
-                        psbt.extract_tx(),
+                        funding_tx.clone(),
                     )
                     .is_err()
                 {
+                    // the channel is gone so nothing will tell us to release the inputs
+                    if let Some(index) = funding_tx
+                        .output
+                        .iter()
+                        .position(|o| o.script_pubkey == output_script)
+                    {
+                        let funding_outpoint = OutPoint {
+                            txid: funding_tx.txid(),
+                            index: index as u16,
+                        };
+                        self.release_reserved_utxos(&funding_outpoint.to_channel_id())
+                            .await;
+                    }
                     self.logger.log(&Record::new(
                             lightning::util::logger::Level::Error,
                             format_args!("ERROR: Channel went away before we could fund it. The peer disconnected or refused the channel."),
@@ -168,6 +477,27 @@ impl EventHandler {
                     0,
                 ));
 
+                // LDK only knows the time from block headers, so it can accept payments
+                // for a while after their invoice expired
+                if self
+                    .persister
+                    .read_payment_info(payment_hash, true, self.logger.clone())
+                    .map_or(false, |i| i.is_expired(crate::utils::now()))
+                {
+                    self.logger.log(&Record::new(
+                        lightning::util::logger::Level::Warn,
+                        format_args!(
+                            "WARN: failing back payment {} to an expired invoice",
+                            payment_hash.0.to_hex()
+                        ),
+                        "event",
+                        "",
+                        0,
+                    ));
+                    self.channel_manager.fail_htlc_backwards(&payment_hash);
+                    return;
+                }
+
                 let payment_preimage = if let Some(payment_preimage) = match purpose {
                     PaymentPurpose::InvoicePayment {
                         payment_preimage, ..
@@ -175,6 +505,13 @@ impl EventHandler {
                     PaymentPurpose::SpontaneousPayment(preimage) => Some(preimage),
                 } {
                     payment_preimage
+                } else if self.hold_invoices.hold(payment_hash, amount_msat) {
+                    self.publish(|node| MutinyEvent::PaymentHeld {
+                        node,
+                        payment_hash: payment_hash.0.to_hex(),
+                        amount_sats: amount_msat / 1_000,
+                    });
+                    return;
                 } else {
                     self.logger.log(&Record::new(
                         lightning::util::logger::Level::Error,
@@ -183,6 +520,7 @@ impl EventHandler {
                         "",
                         0,
                     ));
+                    self.channel_manager.fail_htlc_backwards(&payment_hash);
                     return;
                 };
                 self.channel_manager.claim_funds(payment_preimage);
@@ -193,6 +531,11 @@ impl EventHandler {
                 purpose,
                 amount_msat,
             } => {
+                self.publish(|node| MutinyEvent::PaymentReceived {
+                    node,
+                    payment_hash: payment_hash.0.to_hex(),
+                    amount_sats: amount_msat / 1_000,
+                });
                 self.logger.log(&Record::new(
                     lightning::util::logger::Level::Debug,
                     format_args!(
@@ -218,7 +561,10 @@ impl EventHandler {
                     .read_payment_info(payment_hash, true, self.logger.clone())
                 {
                     Some(mut saved_payment_info) => {
-                        let payment_preimage = payment_preimage.map(|p| p.0);
+                        // hold invoices have their preimage stored when settled
+                        let payment_preimage = payment_preimage
+                            .map(|p| p.0)
+                            .or(saved_payment_info.preimage);
                         let payment_secret = payment_secret.map(|p| p.0);
                         saved_payment_info.status = HTLCStatus::Succeeded;
                         saved_payment_info.preimage = payment_preimage;
@@ -255,6 +601,8 @@ impl EventHandler {
                             fee_paid_msat: None,
                             bolt11: None,
                             last_update,
+                            attempts: vec![],
+                            hold: None,
                         };
                         match self
                             .persister
@@ -325,52 +673,141 @@ impl EventHandler {
                         ));
                     }
                 }
+                self.outbound_payments.payment_resolved(&payment_hash);
+                self.payment_waiters.resolve(&payment_hash, true);
+                self.publish(|node| MutinyEvent::PaymentSent {
+                    node,
+                    payment_hash: payment_hash.0.to_hex(),
+                    fees_paid: fee_paid_msat.map(|f| f / 1_000),
+                });
             }
-            Event::OpenChannelRequest { .. } => {
-                // Unreachable, we don't set manually_accept_inbound_channels
+            Event::OpenChannelRequest {
+                temporary_channel_id,
+                counterparty_node_id,
+                funding_satoshis,
+                push_msat,
+                channel_type,
+            } => {
                 self.logger.log(&Record::new(
                     lightning::util::logger::Level::Debug,
-                    format_args!("EVENT: OpenChannelRequest ignored"),
+                    format_args!(
+                        "EVENT: OpenChannelRequest from {counterparty_node_id} for {funding_satoshis} sats"
+                    ),
                     "event",
                     "",
                     0,
                 ));
+                self.channel_acceptor.handle_open_channel_request(
+                    temporary_channel_id,
+                    counterparty_node_id,
+                    funding_satoshis,
+                    push_msat,
+                    &channel_type,
+                );
             }
-            Event::PaymentPathSuccessful { .. } => {
+            Event::PaymentPathSuccessful {
+                payment_hash, path, ..
+            } => {
                 self.logger.log(&Record::new(
                     lightning::util::logger::Level::Debug,
-                    format_args!("EVENT: PaymentPathSuccessful ignored"),
+                    format_args!("EVENT: PaymentPathSuccessful"),
                     "event",
                     "",
                     0,
                 ));
+                if let Some(payment_hash) = payment_hash {
+                    self.record_payment_attempt(payment_hash, PaymentAttempt::succeeded(&path));
+                }
             }
-            Event::PaymentPathFailed { .. } => {
+            Event::PaymentPathFailed {
+                payment_hash,
+                payment_failed_permanently,
+                payment_id,
+                network_update,
+                path,
+                short_channel_id,
+                retry,
+                ..
+            } => {
                 self.logger.log(&Record::new(
                     lightning::util::logger::Level::Debug,
-                    format_args!("EVENT: PaymentPathFailed ignored"),
+                    format_args!(
+                        "EVENT: PaymentPathFailed: {}, permanently: {payment_failed_permanently}",
+                        payment_hash.0.to_hex()
+                    ),
                     "event",
                     "",
                     0,
                 ));
+                let error = match network_update {
+                    Some(NetworkUpdate::ChannelUpdateMessage { .. }) => "channel update",
+                    Some(NetworkUpdate::ChannelFailure { .. }) => "channel failure",
+                    Some(NetworkUpdate::NodeFailure { .. }) => "node failure",
+                    None if payment_failed_permanently => "rejected by recipient",
+                    None => "unknown",
+                };
+                let mut attempt =
+                    PaymentAttempt::failed(&path, error.to_string(), payment_failed_permanently);
+                attempt.set_failure(
+                    &path,
+                    self.channel_manager.get_our_node_id(),
+                    network_update.as_ref(),
+                    short_channel_id,
+                );
+                let attempt = self.record_payment_attempt(payment_hash, attempt);
+
+                let retried = match payment_id {
+                    Some(payment_id) => {
+                        let retry = if payment_failed_permanently {
+                            None
+                        } else {
+                            retry
+                        };
+                        self.outbound_payments
+                            .retry_failed_path(payment_hash, payment_id, retry)
+                    }
+                    None => Ok(()),
+                };
+                // reported if this was the last path of the payment
+                let failure = match retried {
+                    _ if payment_failed_permanently => PaymentFailure::RecipientRejected,
+                    Err(failure) => failure,
+                    Ok(()) => PaymentFailure::RouteNotFound,
+                };
+                self.payment_waiters.path_failed(
+                    payment_hash,
+                    FailedPayment {
+                        failure,
+                        last_attempt: Some(attempt),
+                    },
+                );
             }
-            Event::ProbeSuccessful { .. } => {
+            Event::ProbeSuccessful { payment_hash, .. } => {
                 self.logger.log(&Record::new(
                     lightning::util::logger::Level::Debug,
-                    format_args!("EVENT: ProbeSuccessful ignored"),
+                    format_args!("EVENT: ProbeSuccessful: {}", payment_hash.0.to_hex()),
                     "event",
                     "",
                     0,
                 ));
+                self.payment_waiters.resolve(&payment_hash, true);
             }
-            Event::ProbeFailed { .. } => {
+            Event::ProbeFailed {
+                payment_hash,
+                short_channel_id,
+                ..
+            } => {
                 self.logger.log(&Record::new(
                     lightning::util::logger::Level::Debug,
-                    format_args!("EVENT: ProbeFailed ignored"),
+                    format_args!(
+                        "EVENT: ProbeFailed: {}, at channel {short_channel_id:?}",
+                        payment_hash.0.to_hex()
+                    ),
                     "event",
                     "",
                     0,
                 ));
+                self.payment_waiters.resolve(&payment_hash, false);
             }
             Event::PaymentFailed { payment_hash, .. } => {
                 self.logger.log(&Record::new(
@@ -416,6 +853,12 @@ impl EventHandler {
                         ));
                     }
                 }
+                self.outbound_payments.payment_resolved(&payment_hash);
+                self.payment_waiters.resolve(&payment_hash, false);
+                self.publish(|node| MutinyEvent::PaymentFailed {
+                    node,
+                    payment_hash: payment_hash.0.to_hex(),
+                });
             }
             Event::PaymentForwarded { .. } => {
                 self.logger.log(&Record::new(
@@ -426,6 +869,23 @@ impl EventHandler {
                     0,
                 ));
             }
+            Event::HTLCHandlingFailed {
+                failed_next_destination: HTLCDestination::FailedPayment { payment_hash },
+                ..
+            } => {
+                self.logger.log(&Record::new(
+                    lightning::util::logger::Level::Debug,
+                    format_args!(
+                        "EVENT: HTLCHandlingFailed for payment {}",
+                        payment_hash.0.to_hex()
+                    ),
+                    "event",
+                    "",
+                    0,
+                ));
+                // held payments are failed back by LDK when they come close to expiring
+                self.hold_invoices.failed(payment_hash);
+            }
             Event::HTLCHandlingFailed { .. } => {
                 self.logger.log(&Record::new(
                     lightning::util::logger::Level::Debug,
@@ -456,34 +916,15 @@ impl EventHandler {
                     "",
                     0,
                 ));
-                let wallet_thread = self.wallet.clone();
-                let keys_manager_thread = self.keys_manager.clone();
-                let chain_thread = self.chain.clone();
-                let destination_address = wallet_thread
-                    .wallet
-                    .lock()
-                    .await
-                    .get_internal_address(AddressIndex::New)
-                    .expect("could not get new address");
-
-                let output_descriptors = &outputs.iter().collect::<Vec<_>>();
-                let tx_feerate =
-                    chain_thread.get_est_sat_per_1000_weight(ConfirmationTarget::Normal);
-                let spending_tx = keys_manager_thread
-                    .spend_spendable_outputs(
-                        output_descriptors,
-                        Vec::new(),
-                        destination_address.script_pubkey(),
-                        tx_feerate,
-                        &Secp256k1::new(),
-                    )
-                    .expect("could not spend spendable outputs");
-
-                self.wallet
-                    .blockchain
-                    .broadcast(&spending_tx)
-                    .await
-                    .expect("failed to broadcast tx");
+                if let Err(e) = self.handle_spendable_outputs(outputs).await {
+                    self.logger.log(&Record::new(
+                        lightning::util::logger::Level::Error,
+                        format_args!("ERROR: Could not spend spendable outputs: {e}"),
+                        "event",
+                        "",
+                        0,
+                    ));
+                }
             }
             Event::ChannelClosed {
                 channel_id,
@@ -501,17 +942,33 @@ impl EventHandler {
                     "",
                     0,
                 ));
+                self.release_reserved_utxos(&channel_id).await;
+                self.channel_acceptor.forget_request(&channel_id);
+                // an anchor channel keeps its reserve until its claims confirm, see
+                // `release_anchor_reserves`
+                self.publish(|node| MutinyEvent::ChannelClosed {
+                    node,
+                    channel_id: channel_id.to_hex(),
+                    reason: format!("{reason:?}"),
+                });
             }
-            Event::DiscardFunding { .. } => {
-                // A "real" node should probably "lock" the UTXOs spent in funding transactions until
-                // the funding transaction either confirms, or this event is generated.
+            Event::DiscardFunding {
+                channel_id,
+                transaction: _,
+            } => {
+                // The UTXOs spent in the funding transaction were reserved when it was created,
+                // now that it will never be broadcast they can be spent again.
                 self.logger.log(&Record::new(
                     lightning::util::logger::Level::Debug,
-                    format_args!("EVENT: DiscardFunding ignored"),
+                    format_args!(
+                        "EVENT: DiscardFunding for channel {}, releasing its utxos",
+                        channel_id.to_hex()
+                    ),
                     "event",
                     "",
                     0,
                 ));
+                self.release_reserved_utxos(&channel_id).await;
             }
             Event::ChannelReady {
                 channel_id,
@@ -526,8 +983,508 @@ impl EventHandler {
                     "",
                     0,
                 ));
+                #[cfg(anchors)]
+                if channel_type.supports_anchors_zero_fee_htlc_tx() {
+                    if let Err(e) = self.wallet.add_anchor_channel(channel_id).await {
+                        self.logger.log(&Record::new(
+                            lightning::util::logger::Level::Error,
+                            format_args!("ERROR: Could not reserve funds for anchor channel: {e}"),
+                            "event",
+                            "",
+                            0,
+                        ));
+                    }
+                }
+                self.publish(|node| MutinyEvent::ChannelReady {
+                    node,
+                    channel_id: channel_id.to_hex(),
+                    peer: counterparty_node_id.to_hex(),
+                });
             }
             Event::HTLCIntercepted { .. } => {}
+            #[cfg(anchors)]
+            Event::BumpTransaction(event) => {
+                self.logger.log(&Record::new(
+                    lightning::util::logger::Level::Debug,
+                    format_args!("EVENT: BumpTransaction processing"),
+                    "event",
+                    "",
+                    0,
+                ));
+                if let Err(e) = self.handle_bump_transaction(event).await {
+                    self.logger.log(&Record::new(
+                        lightning::util::logger::Level::Error,
+                        format_args!("ERROR: Could not fee bump transaction: {e}"),
+                        "event",
+                        "",
+                        0,
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Broadcasts a force closed anchor channel's commitment transaction along with
+    /// a child spending its anchor output that brings the package up to the target fee rate.
+    #[cfg(anchors)]
+    async fn handle_bump_transaction(
+        &self,
+        event: BumpTransactionEvent,
+    ) -> Result<(), MutinyError> {
+        match event {
+            BumpTransactionEvent::ChannelClose {
+                package_target_feerate_sat_per_1000_weight,
+                commitment_tx,
+                commitment_tx_fee_satoshis,
+                anchor_descriptor,
+                ..
+            } => {
+                let signer = self.keys_manager.derive_channel_keys(
+                    anchor_descriptor.channel_value_satoshis,
+                    &anchor_descriptor.channel_keys_id,
+                );
+                let funding_pubkey = signer.pubkeys().funding_pubkey;
+                let anchor_outpoint = anchor_descriptor.outpoint;
+
+                let psbt_input = bitcoin::psbt::Input {
+                    non_witness_utxo: Some(commitment_tx.clone()),
+                    witness_utxo: commitment_tx
+                        .output
+                        .get(anchor_outpoint.vout as usize)
+                        .cloned(),
+                    witness_script: Some(chan_utils::get_anchor_redeemscript(&funding_pubkey)),
+                    ..Default::default()
+                };
+                let target_fee_rate = bdk::FeeRate::from_sat_per_vb(
+                    package_target_feerate_sat_per_1000_weight as f32 * 4.0 / 1000.0,
+                );
+
+                let psbt = self
+                    .wallet
+                    .create_cpfp_psbt(
+                        anchor_outpoint,
+                        psbt_input,
+                        ANCHOR_INPUT_WITNESS_WEIGHT,
+                        commitment_tx.weight(),
+                        commitment_tx_fee_satoshis,
+                        target_fee_rate,
+                    )
+                    .await?;
+
+                let mut anchor_tx = psbt.extract_tx();
+                let index = anchor_tx
+                    .input
+                    .iter()
+                    .position(|i| i.previous_output == anchor_outpoint)
+                    .ok_or(MutinyError::WalletSigningFailed)?;
+                let signature = signer
+                    .sign_holder_anchor_input(&anchor_tx, index, &Secp256k1::new())
+                    .map_err(|_| MutinyError::WalletSigningFailed)?;
+                anchor_tx.input[index].witness =
+                    chan_utils::build_anchor_input_witness(&funding_pubkey, &signature);
+
+                // the child can only be relayed once the parent is in the mempool
+                self.chain.broadcast_transaction(&commitment_tx);
+                self.chain.broadcast_transaction(&anchor_tx);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sweeps the outputs of closed channels to the address given when the channel was
+    /// closed, or to our wallet when there is none. LDK hands us the outputs once they are
+    /// spendable, after the CSV delay of a force close, and each sweep pays its own fee.
+    async fn handle_spendable_outputs(
+        &self,
+        outputs: Vec<SpendableOutputDescriptor>,
+    ) -> Result<(), MutinyError> {
+        let wallet_address = self
+            .wallet
+            .wallet
+            .lock()
+            .await
+            .get_internal_address(AddressIndex::New)?
+            .address;
+
+        let mut destinations: HashMap<Script, Vec<SpendableOutputDescriptor>> = HashMap::new();
+        for descriptor in outputs {
+            let destination = self
+                .close_destination(&descriptor)
+                .await?
+                .unwrap_or_else(|| wallet_address.clone());
+            destinations
+                .entry(destination.script_pubkey())
+                .or_default()
+                .push(descriptor);
+        }
+
+        let tx_feerate = self
+            .chain
+            .get_est_sat_per_1000_weight(ConfirmationTarget::Normal);
+        for (destination, descriptors) in destinations {
+            let spending_tx = self
+                .keys_manager
+                .spend_spendable_outputs(
+                    &descriptors.iter().collect::<Vec<_>>(),
+                    Vec::new(),
+                    destination,
+                    tx_feerate,
+                    &Secp256k1::new(),
+                )
+                .map_err(|_| MutinyError::WalletSigningFailed)?;
+
+            self.wallet.blockchain.broadcast(&spending_tx).await?;
+            self.publish(|node| MutinyEvent::SpendableOutputsSwept {
+                node,
+                txid: spending_tx.txid().to_hex(),
+            });
+        }
+
+        Ok(())
+    }
+
+    // Channel outputs are in the transaction that spends the funding output,
+    // which tells us the channel they belong to.
+    async fn close_destination(
+        &self,
+        descriptor: &SpendableOutputDescriptor,
+    ) -> Result<Option<Address>, MutinyError> {
+        let outpoint = match descriptor {
+            SpendableOutputDescriptor::StaticOutput { outpoint, .. } => outpoint,
+            SpendableOutputDescriptor::DelayedPaymentOutput(d) => &d.outpoint,
+            SpendableOutputDescriptor::StaticPaymentOutput(d) => &d.outpoint,
+        };
+
+        let tx = match self.wallet.blockchain.get_tx(&outpoint.txid).await? {
+            Some(tx) => tx,
+            None => return Ok(None),
+        };
+
+        Ok(tx
+            .input
+            .iter()
+            .find_map(|i| self.persister.read_close_destination(&i.previous_output)))
+    }
+
+    // Events are tagged with the pubkey of the node they came from
+    fn publish(&self, event: impl FnOnce(String) -> MutinyEvent) {
+        let node = self.channel_manager.get_our_node_id().to_hex();
+        self.event_bus.publish(event(node));
+    }
+
+    // Returns the attempt numbered after the ones stored before it
+    fn record_payment_attempt(
+        &self,
+        payment_hash: PaymentHash,
+        attempt: PaymentAttempt,
+    ) -> PaymentAttempt {
+        let mut payment_info =
+            match self
+                .persister
+                .read_payment_info(payment_hash, false, self.logger.clone())
+            {
+                Some(payment_info) => payment_info,
+                None => {
+                    self.logger.log(&Record::new(
+                        lightning::util::logger::Level::Warn,
+                        format_args!("WARN: payment attempt for a payment we did not have stored"),
+                        "event",
+                        "",
+                        0,
+                    ));
+                    return attempt;
+                }
+            };
+
+        let attempt = payment_info.add_attempt(attempt).clone();
+        payment_info.last_update = crate::utils::now().as_secs();
+        if let Err(e) = self
+            .persister
+            .persist_payment_info(payment_hash, payment_info, false)
+        {
+            self.logger.log(&Record::new(
+                lightning::util::logger::Level::Error,
+                format_args!("ERROR: could not persist payment info: {e}"),
+                "event",
+                "",
+                0,
+            ));
         }
+        attempt
+    }
+
+    async fn release_reserved_utxos(&self, channel_id: &[u8; 32]) {
+        if let Err(e) = self.wallet.release_reserved_utxos(channel_id).await {
+            self.logger.log(&Record::new(
+                lightning::util::logger::Level::Error,
+                format_args!(
+                    "ERROR: Could not release reserved utxos for channel {}: {e}",
+                    channel_id.to_hex()
+                ),
+                "event",
+                "",
+                0,
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::*;
+    use crate::utils::currency_from_network;
+    use bitcoin::hashes::{sha256, Hash};
+    use bitcoin::secp256k1::{Message, SecretKey};
+    use bitcoin::BlockHash;
+    use lightning::ln::features::{ChannelFeatures, NodeFeatures};
+    use lightning::ln::msgs::{ChannelUpdate, UnsignedChannelUpdate};
+    use lightning::ln::PaymentSecret;
+    use lightning_invoice::InvoiceBuilder;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    async fn test_payment_waiters() {
+        log!("payment waiters");
+
+        let waiters = PaymentWaiters::default();
+
+        let sent = PaymentHash([1; 32]);
+        let receiver = waiters.wait(sent);
+        waiters.resolve(&sent, true);
+        assert_eq!(receiver.await.unwrap(), Ok(()));
+
+        let rejected = PaymentHash([2; 32]);
+        let receiver = waiters.wait(rejected);
+        waiters.path_failed(
+            rejected,
+            FailedPayment::new(PaymentFailure::RecipientRejected),
+        );
+        waiters.resolve(&rejected, false);
+        assert_eq!(
+            receiver.await.unwrap(),
+            Err(FailedPayment::new(PaymentFailure::RecipientRejected))
+        );
+
+        let failed = PaymentHash([3; 32]);
+        let receiver = waiters.wait(failed);
+        waiters.resolve(&failed, false);
+        assert_eq!(
+            receiver.await.unwrap(),
+            Err(FailedPayment::new(PaymentFailure::RouteNotFound))
+        );
+
+        // the last failed path is reported with the failure
+        let too_expensive = PaymentHash([4; 32]);
+        let receiver = waiters.wait(too_expensive);
+        let mut attempt = PaymentAttempt::failed(
+            &[hop(1, 10), hop(2, 100_000)],
+            "channel failure".to_string(),
+            false,
+        );
+        attempt.attempt = 2;
+        attempt.failing_channel = Some(2);
+        attempt.failing_node = Some("02cae09c".to_string());
+        waiters.path_failed(
+            too_expensive,
+            FailedPayment {
+                failure: PaymentFailure::RoutingFeeTooHigh,
+                last_attempt: Some(attempt.clone()),
+            },
+        );
+        waiters.resolve(&too_expensive, false);
+        let failed = receiver.await.unwrap().unwrap_err();
+        assert_eq!(failed.failure, PaymentFailure::RoutingFeeTooHigh);
+        assert_eq!(failed.last_attempt, Some(attempt));
+        assert_eq!(
+            MutinyError::from(failed).to_string(),
+            "The routing fee is higher than the maximum fee. \
+            Attempt 2 failed at channel 2 of node 02cae09c: channel failure."
+        );
+    }
+
+    fn hop(short_channel_id: u64, fee_msat: u64) -> RouteHop {
+        RouteHop {
+            pubkey: PublicKey::from_str(
+                "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54",
+            )
+            .unwrap(),
+            node_features: NodeFeatures::empty(),
+            short_channel_id,
+            channel_features: ChannelFeatures::empty(),
+            fee_msat,
+            cltv_expiry_delta: 40,
+        }
+    }
+
+    #[test]
+    fn test_payment_attempts() {
+        log!("payment attempts");
+
+        // payment info stored before attempts were recorded
+        let json = r#"{"preimage":null,"secret":null,"status":"InFlight","amt_msat":100000,"fee_paid_msat":null,"bolt11":null,"last_update":0}"#;
+        let mut payment_info: PaymentInfo = serde_json::from_str(json).unwrap();
+        assert!(payment_info.attempts.is_empty());
+
+        let first = PaymentAttempt::failed(
+            &[hop(1, 10), hop(2, 5), hop(3, 100_000)],
+            "channel failure".to_string(),
+            false,
+        );
+        assert_eq!(first.path, vec![1, 2, 3]);
+        assert_eq!(first.fee_msat, 15);
+        payment_info.add_attempt(first);
+
+        payment_info.add_attempt(PaymentAttempt::succeeded(&[hop(4, 20), hop(5, 100_000)]));
+
+        let second = &payment_info.attempts[1];
+        assert_eq!(second.attempt, 1);
+        assert_eq!(second.fee_msat, 20);
+        assert_eq!(second.total_fee_msat, 35);
+        assert!(second.succeeded);
+        assert_eq!(second.error, None);
+    }
+
+    #[test]
+    fn test_payment_attempt_failure() {
+        log!("payment attempt failure");
+
+        let secp = Secp256k1::new();
+        let key = |i: u8| SecretKey::from_slice(&[i; 32]).unwrap();
+        let node = |i: u8| PublicKey::from_secret_key(&secp, &key(i));
+        let path: Vec<RouteHop> = (1..=3)
+            .map(|i| RouteHop {
+                pubkey: node(i),
+                ..hop(i as u64 * 10, 10)
+            })
+            .collect();
+        let failed = |network_update: Option<NetworkUpdate>,
+                      short_channel_id: Option<u64>,
+                      permanent: bool| {
+            let mut attempt = PaymentAttempt::failed(&path, "failed".to_string(), permanent);
+            attempt.set_failure(&path, node(9), network_update.as_ref(), short_channel_id);
+            assert_eq!(attempt.failure_code, Some(FailureCode::Unavailable));
+            (attempt.failing_channel, attempt.failing_node)
+        };
+
+        // channel failures are reported by the node forwarding over the channel
+        let update = NetworkUpdate::ChannelUpdateMessage {
+            msg: ChannelUpdate {
+                signature: secp.sign_ecdsa(&Message::from_slice(&[1; 32]).unwrap(), &key(2)),
+                contents: UnsignedChannelUpdate {
+                    chain_hash: BlockHash::all_zeros(),
+                    short_channel_id: 30,
+                    timestamp: 0,
+                    flags: 0,
+                    cltv_expiry_delta: 40,
+                    htlc_minimum_msat: 1_000,
+                    htlc_maximum_msat: 100_000_000,
+                    fee_base_msat: 1_000,
+                    fee_proportional_millionths: 100,
+                    excess_data: vec![],
+                },
+            },
+        };
+        assert_eq!(
+            failed(Some(update), Some(30), false),
+            (Some(30), Some(node(2).to_hex()))
+        );
+        let channel_failure = NetworkUpdate::ChannelFailure {
+            short_channel_id: 20,
+            is_permanent: true,
+        };
+        assert_eq!(
+            failed(Some(channel_failure), Some(20), false),
+            (Some(20), Some(node(1).to_hex()))
+        );
+
+        // our own channel failed
+        let channel_failure = NetworkUpdate::ChannelFailure {
+            short_channel_id: 10,
+            is_permanent: false,
+        };
+        assert_eq!(
+            failed(Some(channel_failure), Some(10), false),
+            (Some(10), Some(node(9).to_hex()))
+        );
+
+        let node_failure = NetworkUpdate::NodeFailure {
+            node_id: node(2),
+            is_permanent: false,
+        };
+        assert_eq!(
+            failed(Some(node_failure), Some(30), false),
+            (Some(20), Some(node(2).to_hex()))
+        );
+
+        // the recipient rejected the payment
+        assert_eq!(failed(None, None, true), (Some(30), Some(node(3).to_hex())));
+
+        // no update, only the channel
+        assert_eq!(
+            failed(None, Some(20), false),
+            (Some(20), Some(node(1).to_hex()))
+        );
+        assert_eq!(failed(None, None, false), (None, None));
+
+        // attempts stored before failure codes were recorded
+        let json = r#"{"attempt":0,"path":[1],"fee_msat":0,"total_fee_msat":0,"succeeded":false,"failing_channel":1,"failing_node":null,"error":"channel failure","permanent":false,"timestamp":0}"#;
+        let attempt: PaymentAttempt = serde_json::from_str(json).unwrap();
+        assert_eq!(attempt.failure_code, None);
+    }
+
+    #[test]
+    fn test_hold_payment_info() {
+        log!("hold payment info");
+
+        // payment info stored before hold invoices existed
+        let json = r#"{"preimage":null,"secret":null,"status":"Pending","amt_msat":100000,"fee_paid_msat":null,"bolt11":null,"last_update":0}"#;
+        let payment_info: PaymentInfo = serde_json::from_str(json).unwrap();
+        assert_eq!(payment_info.hold, None);
+
+        let mut held = payment_info;
+        held.status = HTLCStatus::Held;
+        held.hold = Some(HoldInvoice {
+            cancel_at_height: Some(100),
+        });
+        let json = serde_json::to_string(&held).unwrap();
+        let held: PaymentInfo = serde_json::from_str(&json).unwrap();
+        assert!(matches!(held.status, HTLCStatus::Held));
+        assert_eq!(held.hold.and_then(|h| h.cancel_at_height), Some(100));
+    }
+
+    #[test]
+    fn test_expired_invoice() {
+        log!("expired invoice");
+
+        let private_key = SecretKey::from_slice(&[42; 32]).unwrap();
+        let created_at = crate::utils::now();
+        let invoice = InvoiceBuilder::new(currency_from_network(Network::Regtest))
+            .description("expiring".to_string())
+            .payment_hash(sha256::Hash::hash(&[1; 32]))
+            .payment_secret(PaymentSecret([2; 32]))
+            .duration_since_epoch(created_at)
+            .expiry_time(Duration::from_secs(600))
+            .min_final_cltv_expiry(144)
+            .build_signed(|hash| Secp256k1::new().sign_ecdsa_recoverable(hash, &private_key))
+            .unwrap();
+
+        let json = r#"{"preimage":null,"secret":null,"status":"Pending","amt_msat":null,"fee_paid_msat":null,"bolt11":null,"last_update":0}"#;
+        let mut payment_info: PaymentInfo = serde_json::from_str(json).unwrap();
+        payment_info.bolt11 = Some(invoice.to_string());
+
+        assert!(!payment_info.is_expired(created_at));
+        let later = created_at + Duration::from_secs(601);
+        assert!(payment_info.is_expired(later));
+
+        // paid invoices don't expire, expired ones stay expired
+        payment_info.status = HTLCStatus::Succeeded;
+        assert!(!payment_info.is_expired(later));
+        payment_info.status = HTLCStatus::Expired;
+        assert!(payment_info.is_expired(created_at));
     }
 }
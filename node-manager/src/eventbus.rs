@@ -0,0 +1,116 @@
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+/// Something that happened in one of our nodes that the front end may want to react to.
+/// Serialized with a `type` field naming the variant.
+#[derive(Serialize, Clone, Debug, Eq, PartialEq)]
+#[serde(tag = "type")]
+pub(crate) enum MutinyEvent {
+    PaymentReceived {
+        node: String,
+        payment_hash: String,
+        amount_sats: u64,
+    },
+    /// A payment to a hold invoice arrived, it is held until settled or cancelled.
+    PaymentHeld {
+        node: String,
+        payment_hash: String,
+        amount_sats: u64,
+    },
+    PaymentSent {
+        node: String,
+        payment_hash: String,
+        fees_paid: Option<u64>,
+    },
+    PaymentFailed {
+        node: String,
+        payment_hash: String,
+    },
+    ChannelReady {
+        node: String,
+        channel_id: String,
+        peer: String,
+    },
+    ChannelClosed {
+        node: String,
+        channel_id: String,
+        reason: String,
+    },
+    SpendableOutputsSwept {
+        node: String,
+        txid: String,
+    },
+}
+
+/// Fans the events published by every node's event handler out to the subscribers.
+#[derive(Default)]
+pub(crate) struct EventBus {
+    subscribers: Mutex<HashMap<u32, UnboundedSender<MutinyEvent>>>,
+    next_id: AtomicU32,
+}
+
+impl EventBus {
+    pub(crate) fn subscribe(&self) -> (u32, UnboundedReceiver<MutinyEvent>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = mpsc::unbounded();
+        self.subscribers.lock().unwrap().insert(id, sender);
+        (id, receiver)
+    }
+
+    /// Ends the subscription, its receiver yields `None` after the events already sent.
+    pub(crate) fn unsubscribe(&self, id: u32) {
+        self.subscribers.lock().unwrap().remove(&id);
+    }
+
+    pub(crate) fn publish(&self, event: MutinyEvent) {
+        // drop the subscribers whose receiver is gone
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|_, sender| sender.unbounded_send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::*;
+    use futures::StreamExt;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn payment_failed(payment_hash: &str) -> MutinyEvent {
+        MutinyEvent::PaymentFailed {
+            node: "node".to_string(),
+            payment_hash: payment_hash.to_string(),
+        }
+    }
+
+    #[test]
+    async fn test_event_bus() {
+        log!("event bus");
+
+        let bus = EventBus::default();
+        let (first, mut first_receiver) = bus.subscribe();
+        let (_, mut second_receiver) = bus.subscribe();
+
+        bus.publish(payment_failed("a"));
+        assert_eq!(first_receiver.next().await, Some(payment_failed("a")));
+        assert_eq!(second_receiver.next().await, Some(payment_failed("a")));
+
+        bus.unsubscribe(first);
+        bus.publish(payment_failed("b"));
+        assert_eq!(first_receiver.next().await, None);
+        assert_eq!(second_receiver.next().await, Some(payment_failed("b")));
+
+        // dropped receivers are cleaned up on the next publish
+        drop(second_receiver);
+        bus.publish(payment_failed("c"));
+        assert!(bus.subscribers.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,654 @@
+use crate::background::GossipSync;
+use crate::error::{MutinyError, MutinyStorageError};
+use crate::localstorage::MutinyBrowserStorage;
+use crate::logging::MutinyLogger;
+use crate::node::NetworkGraph;
+use crate::utils;
+use bitcoin::blockdata::constants::genesis_block;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{BlockHash, Network};
+use gloo_storage::errors::StorageError;
+use lightning::chain;
+use lightning::ln::features::{InitFeatures, NodeFeatures};
+use lightning::ln::msgs::{
+    ChannelAnnouncement, ChannelUpdate, GossipTimestampFilter, Init, LightningError,
+    NodeAnnouncement, QueryChannelRange, QueryShortChannelIds, ReplyChannelRange,
+    ReplyShortChannelIdsEnd, RoutingMessageHandler,
+};
+use lightning::ln::peer_handler::IgnoringMessageHandler;
+use lightning::util::events::{MessageSendEvent, MessageSendEventsProvider};
+use lightning::util::ser::{ReadableArgs, Writeable};
+use log::{debug, error, info};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+pub(crate) type RapidGossipSync =
+    lightning_rapid_gossip_sync::RapidGossipSync<Arc<NetworkGraph>, Arc<MutinyLogger>>;
+
+pub(crate) type P2PGossipSync = lightning::routing::gossip::P2PGossipSync<
+    Arc<NetworkGraph>,
+    Arc<dyn chain::Access + Send + Sync>,
+    Arc<MutinyLogger>,
+>;
+
+pub(crate) type MutinyGossipSync = GossipSync<
+    Arc<P2PGossipSync>,
+    Arc<RapidGossipSync>,
+    Arc<NetworkGraph>,
+    Arc<dyn chain::Access + Send + Sync>,
+    Arc<MutinyLogger>,
+>;
+
+// The network graph is shared between all of the nodes so it is not keyed by node
+pub(crate) const NETWORK_GRAPH_KEY: &str = "network_graph";
+const RGS_SYNC_TIMESTAMP_KEY: &str = "rgs_sync_timestamp";
+const GOSSIP_SETTINGS_KEY: &str = "gossip_settings";
+
+/// The default number of peers the graph is fully synced from when using p2p gossip.
+pub(crate) const DEFAULT_MAX_FULL_SYNC_PEERS: u32 = 2;
+
+// Live channels send an update at least every two weeks, so a full sync asks for that much
+const FULL_SYNC_SECS: u64 = 60 * 60 * 24 * 7 * 2;
+
+// The other peers only send the gossip of the last hour, enough to keep the graph up to date
+const PARTIAL_SYNC_SECS: u64 = 60 * 60;
+
+/// How often to fetch incremental updates from the rapid gossip sync server, in seconds.
+pub(crate) const RGS_SYNC_INTERVAL: u64 = 60 * 60;
+
+pub(crate) fn default_rgs_url(network: Network) -> Option<String> {
+    match network {
+        Network::Bitcoin => Some("https://rapidsync.lightningdevkit.org/snapshot/".to_string()),
+        Network::Testnet => {
+            Some("https://rapidsync.lightningdevkit.org/testnet/snapshot/".to_string())
+        }
+        Network::Signet | Network::Regtest => None,
+    }
+}
+
+// This is the GossipSettings object saved to the DB
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct GossipSettings {
+    /// Sync the graph from our peers instead of a rapid gossip sync server
+    pub p2p_gossip: bool,
+    pub max_full_sync_peers: Option<u32>,
+}
+
+pub(crate) fn get_gossip_settings(
+    storage: &MutinyBrowserStorage,
+) -> Result<GossipSettings, MutinyError> {
+    let res: Result<GossipSettings, MutinyStorageError> = storage.get(GOSSIP_SETTINGS_KEY);
+    match res {
+        Ok(settings) => Ok(settings),
+        Err(MutinyStorageError::StorageError {
+            source: StorageError::KeyNotFound(_),
+        }) => Ok(GossipSettings::default()),
+        Err(e) => Err(MutinyError::read_err(e)),
+    }
+}
+
+pub(crate) fn insert_gossip_settings(
+    storage: &MutinyBrowserStorage,
+    settings: GossipSettings,
+) -> Result<(), MutinyError> {
+    Ok(storage.set(GOSSIP_SETTINGS_KEY, settings)?)
+}
+
+/// How the shared network graph is kept up to date.
+#[derive(Clone)]
+pub(crate) enum Gossip {
+    Rapid(Arc<RapidGossipSync>),
+    P2P(Arc<P2PGossipSync>, Arc<LimitedGossipHandler>),
+}
+
+impl Gossip {
+    /// The gossip sync to give to the background processor, it prunes and persists the graph.
+    pub(crate) fn background_sync(&self) -> MutinyGossipSync {
+        match self {
+            Gossip::Rapid(gossip_sync) => GossipSync::Rapid(gossip_sync.clone()),
+            Gossip::P2P(gossip_sync, _) => GossipSync::P2P(gossip_sync.clone()),
+        }
+    }
+
+    /// The handler for gossip messages from our peers, they are ignored unless using p2p gossip.
+    pub(crate) fn route_handler(&self) -> Arc<dyn RoutingMessageHandler + Send + Sync> {
+        match self {
+            Gossip::Rapid(_) => Arc::new(IgnoringMessageHandler {}),
+            Gossip::P2P(_, handler) => handler.clone(),
+        }
+    }
+}
+
+/// Passes gossip from our peers on to the [`P2PGossipSync`] while limiting the bandwidth used.
+///
+/// Peers that support gossip queries only send us gossip newer than the timestamp we ask
+/// for. The whole graph is only asked of the first `max_full_sync_peers` peers, the others
+/// only send the gossip of the last hour, so each update is downloaded a few times at most.
+pub(crate) struct LimitedGossipHandler {
+    gossip_sync: Arc<P2PGossipSync>,
+    chain_hash: BlockHash,
+    max_full_sync_peers: u32,
+    // the peers asked for a full sync, they are asked again when they reconnect
+    full_sync_peers: Mutex<Vec<PublicKey>>,
+    pending_events: Mutex<Vec<MessageSendEvent>>,
+}
+
+impl LimitedGossipHandler {
+    pub(crate) fn new(
+        gossip_sync: Arc<P2PGossipSync>,
+        network: Network,
+        max_full_sync_peers: u32,
+    ) -> Self {
+        Self {
+            gossip_sync,
+            chain_hash: genesis_block(network).block_hash(),
+            max_full_sync_peers,
+            full_sync_peers: Mutex::new(vec![]),
+            pending_events: Mutex::new(vec![]),
+        }
+    }
+
+    /// How far back the gossip we ask of the peer goes.
+    fn sync_secs(&self, their_node_id: &PublicKey) -> u64 {
+        let mut full_sync_peers = self.full_sync_peers.lock().unwrap();
+        if full_sync_peers.contains(their_node_id) {
+            return FULL_SYNC_SECS;
+        }
+        if full_sync_peers.len() < self.max_full_sync_peers as usize {
+            full_sync_peers.push(*their_node_id);
+            return FULL_SYNC_SECS;
+        }
+        PARTIAL_SYNC_SECS
+    }
+}
+
+impl MessageSendEventsProvider for LimitedGossipHandler {
+    fn get_and_clear_pending_msg_events(&self) -> Vec<MessageSendEvent> {
+        let mut events = std::mem::take(&mut *self.pending_events.lock().unwrap());
+        events.append(&mut self.gossip_sync.get_and_clear_pending_msg_events());
+        events
+    }
+}
+
+impl RoutingMessageHandler for LimitedGossipHandler {
+    fn handle_node_announcement(&self, msg: &NodeAnnouncement) -> Result<bool, LightningError> {
+        self.gossip_sync.handle_node_announcement(msg)
+    }
+
+    fn handle_channel_announcement(
+        &self,
+        msg: &ChannelAnnouncement,
+    ) -> Result<bool, LightningError> {
+        self.gossip_sync.handle_channel_announcement(msg)
+    }
+
+    fn handle_channel_update(&self, msg: &ChannelUpdate) -> Result<bool, LightningError> {
+        self.gossip_sync.handle_channel_update(msg)
+    }
+
+    fn get_next_channel_announcement(
+        &self,
+        starting_point: u64,
+    ) -> Option<(
+        ChannelAnnouncement,
+        Option<ChannelUpdate>,
+        Option<ChannelUpdate>,
+    )> {
+        self.gossip_sync
+            .get_next_channel_announcement(starting_point)
+    }
+
+    fn get_next_node_announcement(
+        &self,
+        starting_point: Option<&PublicKey>,
+    ) -> Option<NodeAnnouncement> {
+        self.gossip_sync.get_next_node_announcement(starting_point)
+    }
+
+    /// Asks the peer for the gossip we want instead of letting the [`P2PGossipSync`] ask
+    /// every peer for a full sync.
+    fn peer_connected(&self, their_node_id: &PublicKey, init: &Init) -> Result<(), ()> {
+        // peers without gossip queries send us all of their gossip on their own
+        if !init.features.supports_gossip_queries() {
+            return Ok(());
+        }
+
+        let sync_secs = self.sync_secs(their_node_id);
+        let first_timestamp = utils::now().as_secs().saturating_sub(sync_secs);
+        debug!("asking {their_node_id} for the gossip since {first_timestamp}");
+        self.pending_events
+            .lock()
+            .unwrap()
+            .push(MessageSendEvent::SendGossipTimestampFilter {
+                node_id: *their_node_id,
+                msg: GossipTimestampFilter {
+                    chain_hash: self.chain_hash,
+                    first_timestamp: first_timestamp as u32,
+                    timestamp_range: u32::MAX,
+                },
+            });
+        Ok(())
+    }
+
+    fn handle_reply_channel_range(
+        &self,
+        their_node_id: &PublicKey,
+        msg: ReplyChannelRange,
+    ) -> Result<(), LightningError> {
+        self.gossip_sync
+            .handle_reply_channel_range(their_node_id, msg)
+    }
+
+    fn handle_reply_short_channel_ids_end(
+        &self,
+        their_node_id: &PublicKey,
+        msg: ReplyShortChannelIdsEnd,
+    ) -> Result<(), LightningError> {
+        self.gossip_sync
+            .handle_reply_short_channel_ids_end(their_node_id, msg)
+    }
+
+    fn handle_query_channel_range(
+        &self,
+        their_node_id: &PublicKey,
+        msg: QueryChannelRange,
+    ) -> Result<(), LightningError> {
+        self.gossip_sync
+            .handle_query_channel_range(their_node_id, msg)
+    }
+
+    fn handle_query_short_channel_ids(
+        &self,
+        their_node_id: &PublicKey,
+        msg: QueryShortChannelIds,
+    ) -> Result<(), LightningError> {
+        self.gossip_sync
+            .handle_query_short_channel_ids(their_node_id, msg)
+    }
+
+    fn provided_node_features(&self) -> NodeFeatures {
+        self.gossip_sync.provided_node_features()
+    }
+
+    fn provided_init_features(&self, their_node_id: &PublicKey) -> InitFeatures {
+        self.gossip_sync.provided_init_features(their_node_id)
+    }
+}
+
+pub(crate) fn read_network_graph(
+    storage: &MutinyBrowserStorage,
+    network: Network,
+    logger: Arc<MutinyLogger>,
+) -> NetworkGraph {
+    let genesis_hash = genesis_block(network).block_hash();
+    let res: Result<Vec<u8>, MutinyStorageError> = storage.get(NETWORK_GRAPH_KEY);
+    match res {
+        Ok(kv_value) => {
+            let mut readable_kv_value = Cursor::new(kv_value);
+            match NetworkGraph::read(&mut readable_kv_value, logger.clone()) {
+                Ok(graph) => graph,
+                Err(e) => {
+                    error!("Error reading NetworkGraph: {}", e.to_string());
+                    NetworkGraph::new(genesis_hash, logger)
+                }
+            }
+        }
+        Err(_) => NetworkGraph::new(genesis_hash, logger),
+    }
+}
+
+/// Each node used to store its own network graph under a key with its id. The first of them
+/// becomes the shared graph if none is stored yet, then they are all deleted.
+pub(crate) fn migrate_node_network_graphs(
+    storage: &MutinyBrowserStorage,
+) -> Result<(), MutinyError> {
+    let node_graphs: HashMap<String, Vec<u8>> =
+        storage.scan(&format!("{NETWORK_GRAPH_KEY}_"), None);
+    if node_graphs.is_empty() {
+        return Ok(());
+    }
+
+    let shared: Result<Vec<u8>, MutinyStorageError> = storage.get(NETWORK_GRAPH_KEY);
+    if shared.is_err() {
+        if let Some(graph) = node_graphs.values().next() {
+            storage.set(NETWORK_GRAPH_KEY, graph)?;
+        }
+    }
+
+    for key in node_graphs.keys() {
+        MutinyBrowserStorage::delete(key);
+    }
+    Ok(())
+}
+
+pub(crate) fn persist_network_graph(
+    storage: &MutinyBrowserStorage,
+    network_graph: &NetworkGraph,
+) -> Result<(), MutinyError> {
+    Ok(storage.set(NETWORK_GRAPH_KEY, network_graph.encode())?)
+}
+
+/// The timestamp of the last applied snapshot, 0 if we have never synced.
+pub(crate) fn get_rgs_sync_timestamp(storage: &MutinyBrowserStorage) -> Result<u32, MutinyError> {
+    let res: Result<u32, MutinyStorageError> = storage.get(RGS_SYNC_TIMESTAMP_KEY);
+    match res {
+        Ok(timestamp) => Ok(timestamp),
+        Err(MutinyStorageError::StorageError {
+            source: StorageError::KeyNotFound(_),
+        }) => Ok(0),
+        Err(e) => Err(MutinyError::read_err(e)),
+    }
+}
+
+fn insert_rgs_sync_timestamp(
+    storage: &MutinyBrowserStorage,
+    timestamp: u32,
+) -> Result<(), MutinyError> {
+    Ok(storage.set(RGS_SYNC_TIMESTAMP_KEY, timestamp)?)
+}
+
+/// Applies a snapshot to the graph, then persists the graph and the snapshot's timestamp.
+/// `current_time` is used to reject stale snapshots, if given.
+pub(crate) fn apply_rgs_snapshot(
+    storage: &MutinyBrowserStorage,
+    gossip_sync: &RapidGossipSync,
+    snapshot: &[u8],
+    current_time: Option<u64>,
+) -> Result<u32, MutinyError> {
+    let timestamp = gossip_sync
+        .update_network_graph_no_std(snapshot, current_time)
+        .map_err(|e| {
+            error!("Error applying rapid gossip sync snapshot: {e:?}");
+            MutinyError::RapidGossipSyncError
+        })?;
+
+    persist_network_graph(storage, gossip_sync.network_graph())?;
+    insert_rgs_sync_timestamp(storage, timestamp)?;
+
+    Ok(timestamp)
+}
+
+async fn fetch_rgs_snapshot(
+    rgs_url: &str,
+    last_sync_timestamp: u32,
+) -> Result<Vec<u8>, MutinyError> {
+    let url = format!("{rgs_url}{last_sync_timestamp}");
+    debug!("fetching rapid gossip sync snapshot from {url}");
+
+    let client = Client::builder()
+        .build()
+        .map_err(|_| MutinyError::RapidGossipSyncError)?;
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|_| MutinyError::RapidGossipSyncError)?
+        .error_for_status()
+        .map_err(|_| MutinyError::RapidGossipSyncError)?;
+
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|_| MutinyError::RapidGossipSyncError)?;
+
+    Ok(bytes.to_vec())
+}
+
+/// Downloads the updates since our last sync and applies them to the shared graph.
+pub(crate) async fn sync_rapid_gossip(
+    storage: &MutinyBrowserStorage,
+    gossip_sync: &RapidGossipSync,
+    rgs_url: &str,
+) -> Result<u32, MutinyError> {
+    let last_sync_timestamp = get_rgs_sync_timestamp(storage)?;
+    let snapshot = fetch_rgs_snapshot(rgs_url, last_sync_timestamp).await?;
+    let now = utils::now().as_secs();
+    let timestamp = apply_rgs_snapshot(storage, gossip_sync, &snapshot, Some(now))?;
+
+    info!("Applied rapid gossip sync snapshot, graph synced to {timestamp}");
+    Ok(timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gossip::*;
+    use crate::test::*;
+
+    use bitcoin::hashes::sha256d::Hash as Sha256dHash;
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+    use lightning::ln::features::ChannelFeatures;
+    use lightning::ln::msgs::{UnsignedChannelAnnouncement, UnsignedChannelUpdate};
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    // A testnet snapshot with a single channel between two nodes and one channel update
+    const SNAPSHOT: &[u8] = include_bytes!("../test_data/rgs_snapshot.bin");
+    const SNAPSHOT_TIMESTAMP: u32 = 1672531200;
+
+    #[test]
+    fn test_apply_rgs_snapshot() {
+        log!("apply rapid gossip sync snapshot");
+
+        let storage = MutinyBrowserStorage::new("".to_string());
+        let logger = Arc::new(MutinyLogger::default());
+        let network_graph = Arc::new(read_network_graph(
+            &storage,
+            Network::Testnet,
+            logger.clone(),
+        ));
+        let gossip_sync = RapidGossipSync::new(network_graph.clone());
+
+        assert_eq!(0, get_rgs_sync_timestamp(&storage).unwrap());
+
+        let timestamp = apply_rgs_snapshot(&storage, &gossip_sync, SNAPSHOT, None).unwrap();
+        assert_eq!(SNAPSHOT_TIMESTAMP, timestamp);
+        assert_eq!(
+            SNAPSHOT_TIMESTAMP,
+            get_rgs_sync_timestamp(&storage).unwrap()
+        );
+        assert_eq!(1, network_graph.read_only().channels().len());
+        assert_eq!(2, network_graph.read_only().nodes().len());
+
+        // the graph should be read back from storage
+        let read_graph = read_network_graph(&storage, Network::Testnet, logger);
+        assert_eq!(1, read_graph.read_only().channels().len());
+
+        cleanup_test();
+    }
+
+    #[test]
+    fn test_migrate_node_network_graphs() {
+        log!("migrate node network graphs");
+
+        let storage = MutinyBrowserStorage::new("".to_string());
+        let logger = Arc::new(MutinyLogger::default());
+        let network_graph = Arc::new(read_network_graph(
+            &storage,
+            Network::Testnet,
+            logger.clone(),
+        ));
+        let gossip_sync = RapidGossipSync::new(network_graph.clone());
+        gossip_sync
+            .update_network_graph_no_std(SNAPSHOT, None)
+            .unwrap();
+
+        let node_key = format!("{NETWORK_GRAPH_KEY}_node");
+        storage.set(&node_key, network_graph.encode()).unwrap();
+
+        migrate_node_network_graphs(&storage).unwrap();
+        let read_graph = read_network_graph(&storage, Network::Testnet, logger);
+        assert_eq!(1, read_graph.read_only().channels().len());
+        let node_graph: Result<Vec<u8>, MutinyStorageError> = storage.get(&node_key);
+        assert!(node_graph.is_err());
+
+        cleanup_test();
+    }
+
+    fn announce_channel(
+        handler: &LimitedGossipHandler,
+        short_channel_id: u64,
+        timestamp: u32,
+    ) -> Result<(), LightningError> {
+        let secp = Secp256k1::new();
+        let chain_hash = genesis_block(Network::Testnet).block_hash();
+        let key = |i: u64| {
+            let mut bytes = [1u8; 32];
+            bytes[..8].copy_from_slice(&(short_channel_id * 4 + i).to_be_bytes());
+            SecretKey::from_slice(&bytes).unwrap()
+        };
+        let sign = |data: Vec<u8>, key: &SecretKey| {
+            let hash = Sha256dHash::hash(&data);
+            secp.sign_ecdsa(&Message::from_slice(&hash[..]).unwrap(), key)
+        };
+
+        let mut node_keys = [key(0), key(1)];
+        node_keys.sort_by_key(|k| PublicKey::from_secret_key(&secp, k).serialize());
+        let bitcoin_keys = [key(2), key(3)];
+
+        let contents = UnsignedChannelAnnouncement {
+            features: ChannelFeatures::empty(),
+            chain_hash,
+            short_channel_id,
+            node_id_1: PublicKey::from_secret_key(&secp, &node_keys[0]),
+            node_id_2: PublicKey::from_secret_key(&secp, &node_keys[1]),
+            bitcoin_key_1: PublicKey::from_secret_key(&secp, &bitcoin_keys[0]),
+            bitcoin_key_2: PublicKey::from_secret_key(&secp, &bitcoin_keys[1]),
+            excess_data: vec![],
+        };
+        let announcement = ChannelAnnouncement {
+            node_signature_1: sign(contents.encode(), &node_keys[0]),
+            node_signature_2: sign(contents.encode(), &node_keys[1]),
+            bitcoin_signature_1: sign(contents.encode(), &bitcoin_keys[0]),
+            bitcoin_signature_2: sign(contents.encode(), &bitcoin_keys[1]),
+            contents,
+        };
+        handler.handle_channel_announcement(&announcement)?;
+
+        for (direction, node_key) in node_keys.iter().enumerate() {
+            let contents = UnsignedChannelUpdate {
+                chain_hash,
+                short_channel_id,
+                timestamp,
+                flags: direction as u8,
+                cltv_expiry_delta: 144,
+                htlc_minimum_msat: 1_000,
+                htlc_maximum_msat: 100_000_000,
+                fee_base_msat: 1_000,
+                fee_proportional_millionths: 100,
+                excess_data: vec![],
+            };
+            let update = ChannelUpdate {
+                signature: sign(contents.encode(), node_key),
+                contents,
+            };
+            handler.handle_channel_update(&update)?;
+        }
+
+        Ok(())
+    }
+
+    fn create_handler(max_full_sync_peers: u32) -> (Arc<NetworkGraph>, LimitedGossipHandler) {
+        let storage = MutinyBrowserStorage::new("".to_string());
+        let logger = Arc::new(MutinyLogger::default());
+        let network_graph = Arc::new(read_network_graph(
+            &storage,
+            Network::Testnet,
+            logger.clone(),
+        ));
+        let gossip_sync = Arc::new(P2PGossipSync::new(network_graph.clone(), None, logger));
+        let handler = LimitedGossipHandler::new(gossip_sync, Network::Testnet, max_full_sync_peers);
+        (network_graph, handler)
+    }
+
+    #[test]
+    fn test_p2p_gossip_converges() {
+        log!("p2p gossip converges");
+
+        let (network_graph, handler) = create_handler(DEFAULT_MAX_FULL_SYNC_PEERS);
+
+        // more messages than a peer sends in a minute, none of them should be dropped
+        let channels = 400;
+        let timestamp = utils::now().as_secs() as u32;
+        for short_channel_id in 1..=channels {
+            announce_channel(&handler, short_channel_id, timestamp).unwrap();
+        }
+
+        let graph = network_graph.read_only();
+        assert_eq!(channels as usize, graph.channels().len());
+        assert_eq!(2 * channels as usize, graph.nodes().len());
+        for short_channel_id in 1..=channels {
+            let channel = graph.channels().get(&short_channel_id).unwrap();
+            assert!(channel.one_to_two.is_some());
+            assert!(channel.two_to_one.is_some());
+        }
+
+        // a newer update from another peer replaces the one in the graph
+        drop(graph);
+        announce_channel(&handler, 1, timestamp + 1).unwrap();
+        let graph = network_graph.read_only();
+        let channel = graph.channels().get(&1).unwrap();
+        assert_eq!(
+            timestamp + 1,
+            channel.one_to_two.as_ref().unwrap().last_update
+        );
+
+        cleanup_test();
+    }
+
+    #[test]
+    fn test_full_sync_peers() {
+        log!("full sync peers");
+
+        let (_, handler) = create_handler(2);
+        let secp = Secp256k1::new();
+        let peer =
+            |i: u8| PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[i; 32]).unwrap());
+
+        let gossip_queries = Init {
+            features: handler.provided_init_features(&peer(1)),
+            remote_network_address: None,
+        };
+        assert!(gossip_queries.features.supports_gossip_queries());
+        let no_gossip_queries = Init {
+            features: InitFeatures::empty(),
+            remote_network_address: None,
+        };
+
+        let first_timestamp = |their_node_id: PublicKey, init: &Init| {
+            handler.peer_connected(&their_node_id, init).unwrap();
+            let events = handler.get_and_clear_pending_msg_events();
+            assert!(events.len() <= 1);
+            events.into_iter().find_map(|event| match event {
+                MessageSendEvent::SendGossipTimestampFilter { node_id, msg } => {
+                    assert_eq!(their_node_id, node_id);
+                    assert_eq!(genesis_block(Network::Testnet).block_hash(), msg.chain_hash);
+                    Some(msg.first_timestamp as u64)
+                }
+                _ => None,
+            })
+        };
+
+        let now = utils::now().as_secs();
+        let full_sync = now - FULL_SYNC_SECS;
+        let partial_sync = now - PARTIAL_SYNC_SECS;
+
+        // peers without gossip queries send all their gossip and don't take a full sync
+        assert_eq!(None, first_timestamp(peer(1), &no_gossip_queries));
+
+        assert!(first_timestamp(peer(2), &gossip_queries).unwrap() <= full_sync + 1);
+        assert!(first_timestamp(peer(3), &gossip_queries).unwrap() <= full_sync + 1);
+        assert!(first_timestamp(peer(4), &gossip_queries).unwrap() >= partial_sync);
+
+        // the full sync peers keep their full sync when reconnecting
+        assert!(first_timestamp(peer(2), &gossip_queries).unwrap() <= full_sync + 1);
+        assert!(first_timestamp(peer(4), &gossip_queries).unwrap() >= partial_sync);
+
+        cleanup_test();
+    }
+}
@@ -0,0 +1,211 @@
+use crate::error::MutinyError;
+use crate::event::{HTLCStatus, MillisatAmount, PaymentInfo};
+use crate::ldkstorage::{MutinyNodePersister, PhantomChannelManager};
+use crate::logging::MutinyLogger;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin_hashes::hex::ToHex;
+use lightning::ln::channelmanager::MIN_FINAL_CLTV_EXPIRY;
+use lightning::ln::{PaymentHash, PaymentPreimage};
+use lightning_invoice::Invoice;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// How often held payments are checked for being close to their CLTV deadline.
+pub(crate) const HOLD_INVOICE_CHECK_INTERVAL_SECS: u64 = 60;
+
+/// The final CLTV delta of hold invoices created without one, about a day to settle them in.
+pub(crate) const DEFAULT_HOLD_INVOICE_MIN_FINAL_CLTV_EXPIRY: u32 = 144;
+
+// Senders add the hops of their route to the final CLTV delta and don't lock up their
+// funds for more than 1008 blocks in total, so leave room for the route.
+const MAX_HOLD_INVOICE_MIN_FINAL_CLTV_EXPIRY: u32 = 720;
+
+// LDK fails a claimable HTLC back on its own once it is this close to expiring
+const HTLC_FAIL_BACK_BLOCKS: u32 = MIN_FINAL_CLTV_EXPIRY - 3;
+
+// Blocks found while a payment is routed to us make its HTLC expire sooner after it arrives
+// than the invoice asked for.
+const HOLD_INVOICE_ROUTING_BLOCKS: u32 = 6;
+
+// The least time a hold invoice gives to settle its payment, about an hour
+const MIN_HOLD_BLOCKS: u32 = 6;
+
+/// How many blocks a payment to a hold invoice with the final CLTV delta is held for
+/// before we cancel it, a block before LDK would fail it back.
+fn hold_blocks(min_final_cltv_expiry: u32) -> u32 {
+    min_final_cltv_expiry.saturating_sub(HTLC_FAIL_BACK_BLOCKS + HOLD_INVOICE_ROUTING_BLOCKS + 1)
+}
+
+/// What a hold invoice is created with.
+pub(crate) struct HoldInvoiceParams {
+    pub payment_hash: PaymentHash,
+    /// How many blocks the payment's HTLC expires after it arrives at the earliest,
+    /// which bounds how long it can be held.
+    pub min_final_cltv_expiry: u32,
+}
+
+impl HoldInvoiceParams {
+    pub(crate) fn new(
+        payment_hash: PaymentHash,
+        min_final_cltv_expiry: Option<u32>,
+    ) -> Result<Self, MutinyError> {
+        let min_final_cltv_expiry =
+            min_final_cltv_expiry.unwrap_or(DEFAULT_HOLD_INVOICE_MIN_FINAL_CLTV_EXPIRY);
+        if hold_blocks(min_final_cltv_expiry) < MIN_HOLD_BLOCKS
+            || min_final_cltv_expiry > MAX_HOLD_INVOICE_MIN_FINAL_CLTV_EXPIRY
+        {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        Ok(Self {
+            payment_hash,
+            min_final_cltv_expiry,
+        })
+    }
+}
+
+/// The state of a hold invoice, its payments are only claimed once the preimage is given.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct HoldInvoice {
+    /// The block height the held payment is cancelled at if it is not settled before,
+    /// set once the payment arrives.
+    pub cancel_at_height: Option<u32>,
+}
+
+/// Holds the payments to our hold invoices until they are settled or cancelled.
+pub(crate) struct HoldInvoices {
+    channel_manager: Arc<PhantomChannelManager>,
+    persister: Arc<MutinyNodePersister>,
+    logger: Arc<MutinyLogger>,
+}
+
+impl HoldInvoices {
+    pub(crate) fn new(
+        channel_manager: Arc<PhantomChannelManager>,
+        persister: Arc<MutinyNodePersister>,
+        logger: Arc<MutinyLogger>,
+    ) -> Self {
+        Self {
+            channel_manager,
+            persister,
+            logger,
+        }
+    }
+
+    /// Holds a payment that became claimable without us knowing its preimage. Returns false
+    /// when it is not for one of our hold invoices and should be failed back.
+    ///
+    /// Hold invoices are only paid to the node that created them, so it knows their
+    /// final CLTV delta and with it when their payments expire.
+    pub(crate) fn hold(&self, payment_hash: PaymentHash, amount_msat: u64) -> bool {
+        let mut payment_info = match self.read(payment_hash) {
+            Some(payment_info) => payment_info,
+            None => return false,
+        };
+        let min_final_cltv_expiry = payment_info
+            .bolt11
+            .as_deref()
+            .and_then(|bolt11| Invoice::from_str(bolt11).ok())
+            .map_or(MIN_FINAL_CLTV_EXPIRY, |invoice| {
+                invoice.min_final_cltv_expiry() as u32
+            });
+        let height = self.channel_manager.current_best_block().height();
+        let cancel_at_height = height + hold_blocks(min_final_cltv_expiry);
+
+        let cancel_at_height = match payment_info.hold {
+            // the payment may already be held when its event is handled again
+            Some(ref mut hold)
+                if matches!(payment_info.status, HTLCStatus::Pending | HTLCStatus::Held) =>
+            {
+                *hold.cancel_at_height.get_or_insert(cancel_at_height)
+            }
+            _ => return false,
+        };
+        payment_info.status = HTLCStatus::Held;
+        payment_info.amt_msat = MillisatAmount(Some(amount_msat));
+
+        info!(
+            "holding payment {} until block {cancel_at_height}",
+            payment_hash.0.to_hex()
+        );
+        if let Err(e) = self.persist(payment_hash, payment_info) {
+            error!("could not persist held payment: {e}");
+        }
+        true
+    }
+
+    /// Claims the held payment for the preimage's hash.
+    pub(crate) fn settle(&self, preimage: PaymentPreimage) -> Result<(), MutinyError> {
+        let payment_hash = PaymentHash(sha256::Hash::hash(&preimage.0).into_inner());
+        let mut payment_info = self.held(payment_hash)?;
+
+        // the claim event does not carry the preimage of payments we created for a hash
+        payment_info.preimage = Some(preimage.0);
+        self.persist(payment_hash, payment_info)?;
+        self.channel_manager.claim_funds(preimage);
+        Ok(())
+    }
+
+    /// Fails the held payment back to its sender.
+    pub(crate) fn cancel(&self, payment_hash: PaymentHash) -> Result<(), MutinyError> {
+        let mut payment_info = self.held(payment_hash)?;
+
+        self.channel_manager.fail_htlc_backwards(&payment_hash);
+        payment_info.status = HTLCStatus::Failed;
+        self.persist(payment_hash, payment_info)
+    }
+
+    /// Marks a held payment failed after LDK failed it back, it won't be settled anymore.
+    pub(crate) fn failed(&self, payment_hash: PaymentHash) {
+        if let Ok(mut payment_info) = self.held(payment_hash) {
+            payment_info.status = HTLCStatus::Failed;
+            if let Err(e) = self.persist(payment_hash, payment_info) {
+                error!("could not persist failed held payment: {e}");
+            }
+        }
+    }
+
+    /// Cancels the held payments that reached their deadline before being settled.
+    pub(crate) fn cancel_expired(&self) {
+        let height = self.channel_manager.current_best_block().height();
+        for (payment_hash, payment_info) in self.persister.list_inbound_payments() {
+            let expired = matches!(payment_info.status, HTLCStatus::Held)
+                && payment_info
+                    .hold
+                    .and_then(|h| h.cancel_at_height)
+                    .map_or(false, |cancel_at_height| height >= cancel_at_height);
+            if !expired {
+                continue;
+            }
+
+            let hash = payment_hash.0.to_hex();
+            warn!("cancelling held payment {hash}, it was not settled in time");
+            if let Err(e) = self.cancel(payment_hash) {
+                error!("could not cancel held payment {hash}: {e}");
+            }
+        }
+    }
+
+    fn held(&self, payment_hash: PaymentHash) -> Result<PaymentInfo, MutinyError> {
+        self.read(payment_hash)
+            .filter(|p| p.hold.is_some() && matches!(p.status, HTLCStatus::Held))
+            .ok_or(MutinyError::HoldInvoiceNotFound)
+    }
+
+    fn read(&self, payment_hash: PaymentHash) -> Option<PaymentInfo> {
+        self.persister
+            .read_payment_info(payment_hash, true, self.logger.clone())
+    }
+
+    fn persist(
+        &self,
+        payment_hash: PaymentHash,
+        mut payment_info: PaymentInfo,
+    ) -> Result<(), MutinyError> {
+        payment_info.last_update = crate::utils::now().as_secs();
+        Ok(self
+            .persister
+            .persist_payment_info(payment_hash, payment_info, true)?)
+    }
+}
@@ -6,7 +6,7 @@ use bitcoin_hashes::Hash;
 use core::ops::Deref;
 use core::time::Duration;
 use lightning::chain::keysinterface::{KeysInterface, Recipient, Sign};
-use lightning::ln::channelmanager::{ChannelDetails, MIN_FINAL_CLTV_EXPIRY};
+use lightning::ln::channelmanager::ChannelDetails;
 use lightning::ln::channelmanager::{PhantomRouteHints, MIN_CLTV_EXPIRY_DELTA};
 use lightning::ln::inbound_payment::{create, create_from_hash, ExpandedKey};
 use lightning::ln::PaymentHash;
@@ -23,6 +23,7 @@ pub fn create_phantom_invoice<Signer: Sign, K: Deref>(
     phantom_route_hints: Vec<PhantomRouteHints>,
     keys_manager: K,
     network: Currency,
+    min_final_cltv_expiry: u32,
 ) -> Result<Invoice, SignOrCreationError<()>>
 where
     K::Target: KeysInterface,
@@ -35,6 +36,7 @@ where
         phantom_route_hints,
         keys_manager,
         network,
+        min_final_cltv_expiry,
     )
 }
 
@@ -46,6 +48,7 @@ fn _create_phantom_invoice<Signer: Sign, K: Deref>(
     phantom_route_hints: Vec<PhantomRouteHints>,
     keys_manager: K,
     network: Currency,
+    min_final_cltv_expiry: u32,
 ) -> Result<Invoice, SignOrCreationError<()>>
 where
     K::Target: KeysInterface,
@@ -87,7 +90,7 @@ where
         .duration_since_epoch(Duration::from_secs(now))
         .payment_hash(Hash::from_slice(&payment_hash.0).unwrap())
         .payment_secret(payment_secret)
-        .min_final_cltv_expiry(MIN_FINAL_CLTV_EXPIRY.into())
+        .min_final_cltv_expiry(min_final_cltv_expiry.into())
         .expiry_time(Duration::from_secs(invoice_expiry_delta_secs.into()));
     if let Some(amt) = amt_msat {
         invoice = invoice.amount_milli_satoshis(amt);
@@ -1,7 +1,9 @@
 use crate::error::MutinyError;
-use bip32::XPrv;
+use bip32::{ChildNumber, XPrv};
 use bip39::Mnemonic;
-use bitcoin::secp256k1::{PublicKey, Secp256k1};
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
 use lightning::chain::keysinterface::{KeysInterface, PhantomKeysManager, Recipient};
 
 pub(crate) fn generate_seed(num_words: u8) -> Result<Mnemonic, MutinyError> {
@@ -62,6 +64,43 @@ pub(crate) fn pubkey_from_keys_manager(keys_manager: &PhantomKeysManager) -> Pub
     PublicKey::from_secret_key(&secp_ctx, &our_network_key)
 }
 
+// LNURL-auth linking keys are derived from `m/138'/l1/l2/l3/l4` as in LUD-05, where the
+// path comes from an HMAC of the service's domain keyed by the hashing key at `m/138'/0`.
+pub(crate) fn lnurl_auth_linking_key(mnemonic: &Mnemonic, domain: &str) -> SecretKey {
+    let auth_key = XPrv::new(mnemonic.to_seed(""))
+        .unwrap()
+        .derive_child(ChildNumber::new(138, true).unwrap())
+        .unwrap();
+    let hashing_key = auth_key
+        .derive_child(ChildNumber::new(0, false).unwrap())
+        .unwrap();
+
+    let linking_key = lnurl_auth_path(&hashing_key.to_bytes(), domain)
+        .into_iter()
+        .fold(auth_key, |key, index| {
+            // the path indexes are full u32s, the top bit decides if they are hardened
+            let child = ChildNumber::new(
+                index & !ChildNumber::HARDENED_FLAG,
+                index >= ChildNumber::HARDENED_FLAG,
+            );
+            key.derive_child(child.unwrap()).unwrap()
+        });
+
+    SecretKey::from_slice(&linking_key.to_bytes()).expect("valid linking key")
+}
+
+fn lnurl_auth_path(hashing_key: &[u8], domain: &str) -> [u32; 4] {
+    let mut engine = HmacEngine::<sha256::Hash>::new(hashing_key);
+    engine.input(domain.as_bytes());
+    let derivation_material = Hmac::<sha256::Hash>::from_engine(engine).into_inner();
+
+    let mut path = [0; 4];
+    for (index, bytes) in path.iter_mut().zip(derivation_material.chunks_exact(4)) {
+        *index = u32::from_be_bytes(bytes.try_into().unwrap());
+    }
+    path
+}
+
 #[cfg(test)]
 mod tests {
     use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
@@ -70,8 +109,9 @@ mod tests {
 
     use crate::{keymanager::pubkey_from_keys_manager, test::*};
 
-    use super::create_keys_manager;
+    use super::{create_keys_manager, lnurl_auth_linking_key, lnurl_auth_path};
     use bip39::Mnemonic;
+    use bitcoin::hashes::hex::FromHex;
     use std::str::FromStr;
 
     #[test]
@@ -99,4 +139,24 @@ mod tests {
 
         assert_eq!(second_pubkey, second_pubkey_again);
     }
+
+    #[test]
+    fn derive_lnurl_auth_linking_keys() {
+        log!("deriving lnurl auth linking keys");
+
+        // the example from LUD-05
+        let hashing_key =
+            Vec::<u8>::from_hex("7d417a6a5e9a6a4a879aeaba11a11838764c8fa2b959c242d43dea682b3e409b")
+                .unwrap();
+        assert_eq!(
+            lnurl_auth_path(&hashing_key, "site.com"),
+            [1588488367, 2659270754, 38110259, 4136336762]
+        );
+
+        let mnemonic = Mnemonic::from_str("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about").expect("could not generate");
+
+        let key = lnurl_auth_linking_key(&mnemonic, "site.com");
+        assert_eq!(key, lnurl_auth_linking_key(&mnemonic, "site.com"));
+        assert_ne!(key, lnurl_auth_linking_key(&mnemonic, "other.com"));
+    }
 }
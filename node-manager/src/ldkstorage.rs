@@ -1,17 +1,22 @@
 use crate::chain::MutinyChain;
+use crate::channelpolicy::ChannelAcceptancePolicy;
 use crate::error;
-use crate::error::MutinyError;
+use crate::error::{MutinyError, MutinyStorageError};
 use crate::event::PaymentInfo;
+use crate::gossip::NETWORK_GRAPH_KEY;
 use crate::localstorage::MutinyBrowserStorage;
 use crate::logging::MutinyLogger;
 use crate::node::NetworkGraph;
-use crate::node::{default_user_config, ChainMonitor};
+use crate::node::{default_user_config, ChainMonitor, DEFAULT_INVOICE_EXPIRY_SECS};
+use crate::paymentpolicy::{PaymentDestination, PaymentPolicy};
 use anyhow::anyhow;
 use bdk::blockchain::EsploraBlockchain;
 use bitcoin::BlockHash;
 use bitcoin::Network;
-use bitcoin_hashes::hex::ToHex;
+use bitcoin::{Address, OutPoint};
+use bitcoin_hashes::hex::{FromHex, ToHex};
 use futures::{try_join, TryFutureExt};
+use gloo_storage::errors::StorageError;
 use lightning::chain::channelmonitor::ChannelMonitor;
 use lightning::chain::keysinterface::InMemorySigner;
 use lightning::chain::keysinterface::PhantomKeysManager;
@@ -35,13 +40,18 @@ use std::ops::Deref;
 use std::str::FromStr;
 use std::sync::Arc;
 
-const NETWORK_KEY: &str = "network";
 const PROB_SCORER_KEY: &str = "prob_scorer";
 const CHANNEL_MANAGER_KEY: &str = "manager";
 const MONITORS_PREFIX_KEY: &str = "monitors/";
 const PAYMENT_INBOUND_PREFIX_KEY: &str = "payment_inbound/";
 const PAYMENT_OUTBOUND_PREFIX_KEY: &str = "payment_outbound/";
 const PEER_PREFIX_KEY: &str = "peer/";
+const CHANNEL_POLICY_KEY: &str = "channel_policy";
+const PAYMENT_POLICY_KEY: &str = "payment_policy";
+const PAYMENT_DESTINATIONS_KEY: &str = "payment_destinations";
+const BACKGROUND_PROBING_KEY: &str = "background_probing";
+const INVOICE_EXPIRY_KEY: &str = "invoice_expiry";
+const CLOSE_DESTINATION_PREFIX_KEY: &str = "close_destination/";
 
 pub(crate) type PhantomChannelManager = LdkChannelManager<
     Arc<ChainMonitor>,
@@ -78,32 +88,6 @@ impl MutinyNodePersister {
         self.storage.get(key).map_err(MutinyError::read_err)
     }
 
-    // FIXME: Useful to use soon when we implement paying network nodes
-    #[allow(dead_code)]
-    pub fn persist_network_graph(&self, network_graph: &NetworkGraph) -> io::Result<()> {
-        self.persist(NETWORK_KEY, network_graph)
-    }
-
-    pub fn read_network_graph(
-        &self,
-        genesis_hash: BlockHash,
-        logger: Arc<MutinyLogger>,
-    ) -> NetworkGraph {
-        match self.read_value(NETWORK_KEY) {
-            Ok(kv_value) => {
-                let mut readable_kv_value = Cursor::new(kv_value);
-                match NetworkGraph::read(&mut readable_kv_value, logger.clone()) {
-                    Ok(graph) => graph,
-                    Err(e) => {
-                        error!("Error reading NetworkGraph: {}", e.to_string());
-                        NetworkGraph::new(genesis_hash, logger)
-                    }
-                }
-            }
-            Err(_) => NetworkGraph::new(genesis_hash, logger),
-        }
-    }
-
     // FIXME: Useful to use soon when we implement paying network nodes
     #[allow(dead_code)]
     pub fn persist_scorer(
@@ -283,6 +267,23 @@ impl MutinyNodePersister {
         map.into_iter().collect()
     }
 
+    /// The inbound payments of this node, unlike `list_payment_info` without the other nodes'.
+    pub(crate) fn list_inbound_payments(&self) -> Vec<(PaymentHash, PaymentInfo)> {
+        let suffix = format!("_{}", self.node_id);
+        let map: HashMap<String, PaymentInfo> =
+            self.storage.scan(PAYMENT_INBOUND_PREFIX_KEY, Some(&suffix));
+
+        map.into_iter()
+            .filter_map(|(key, i)| {
+                let hash = key
+                    .strip_prefix(PAYMENT_INBOUND_PREFIX_KEY)?
+                    .strip_suffix(&suffix)?;
+                let hash = <[u8; 32]>::from_hex(hash).ok()?;
+                Some((PaymentHash(hash), i))
+            })
+            .collect()
+    }
+
     pub(crate) fn read_peer_connection_info(&self, peer_pubkey: String) -> Option<String> {
         let key = self.get_key(peer_key(peer_pubkey).as_str());
         let deserialized_value: Result<String, MutinyError> =
@@ -308,6 +309,118 @@ impl MutinyNodePersister {
         MutinyBrowserStorage::delete(key)
     }
 
+    pub(crate) fn read_channel_policy(&self) -> Result<ChannelAcceptancePolicy, MutinyError> {
+        let key = self.get_key(CHANNEL_POLICY_KEY);
+        let res: Result<ChannelAcceptancePolicy, MutinyStorageError> = self.storage.get(key);
+        match res {
+            Ok(policy) => Ok(policy),
+            Err(MutinyStorageError::StorageError {
+                source: StorageError::KeyNotFound(_),
+            }) => Ok(ChannelAcceptancePolicy::default()),
+            Err(e) => Err(MutinyError::read_err(e)),
+        }
+    }
+
+    pub(crate) fn persist_channel_policy(
+        &self,
+        policy: &ChannelAcceptancePolicy,
+    ) -> Result<(), MutinyError> {
+        let key = self.get_key(CHANNEL_POLICY_KEY);
+        Ok(self.storage.set(key, policy)?)
+    }
+
+    pub(crate) fn read_payment_policy(&self) -> Result<PaymentPolicy, MutinyError> {
+        let key = self.get_key(PAYMENT_POLICY_KEY);
+        let res: Result<PaymentPolicy, MutinyStorageError> = self.storage.get(key);
+        match res {
+            Ok(policy) => Ok(policy),
+            Err(MutinyStorageError::StorageError {
+                source: StorageError::KeyNotFound(_),
+            }) => Ok(PaymentPolicy::default()),
+            Err(e) => Err(MutinyError::read_err(e)),
+        }
+    }
+
+    pub(crate) fn persist_payment_policy(&self, policy: &PaymentPolicy) -> Result<(), MutinyError> {
+        let key = self.get_key(PAYMENT_POLICY_KEY);
+        Ok(self.storage.set(key, policy)?)
+    }
+
+    pub(crate) fn read_payment_destinations(&self) -> Result<Vec<PaymentDestination>, MutinyError> {
+        let key = self.get_key(PAYMENT_DESTINATIONS_KEY);
+        let res: Result<Vec<PaymentDestination>, MutinyStorageError> = self.storage.get(key);
+        match res {
+            Ok(destinations) => Ok(destinations),
+            Err(MutinyStorageError::StorageError {
+                source: StorageError::KeyNotFound(_),
+            }) => Ok(vec![]),
+            Err(e) => Err(MutinyError::read_err(e)),
+        }
+    }
+
+    pub(crate) fn persist_payment_destinations(
+        &self,
+        destinations: &[PaymentDestination],
+    ) -> Result<(), MutinyError> {
+        let key = self.get_key(PAYMENT_DESTINATIONS_KEY);
+        Ok(self.storage.set(key, destinations)?)
+    }
+
+    pub(crate) fn read_background_probing(&self) -> Result<bool, MutinyError> {
+        let key = self.get_key(BACKGROUND_PROBING_KEY);
+        let res: Result<bool, MutinyStorageError> = self.storage.get(key);
+        match res {
+            Ok(enabled) => Ok(enabled),
+            Err(MutinyStorageError::StorageError {
+                source: StorageError::KeyNotFound(_),
+            }) => Ok(false),
+            Err(e) => Err(MutinyError::read_err(e)),
+        }
+    }
+
+    pub(crate) fn persist_background_probing(&self, enabled: bool) -> Result<(), MutinyError> {
+        let key = self.get_key(BACKGROUND_PROBING_KEY);
+        Ok(self.storage.set(key, enabled)?)
+    }
+
+    pub(crate) fn read_invoice_expiry(&self) -> Result<u32, MutinyError> {
+        let key = self.get_key(INVOICE_EXPIRY_KEY);
+        let res: Result<u32, MutinyStorageError> = self.storage.get(key);
+        match res {
+            Ok(expiry_secs) => Ok(expiry_secs),
+            Err(MutinyStorageError::StorageError {
+                source: StorageError::KeyNotFound(_),
+            }) => Ok(DEFAULT_INVOICE_EXPIRY_SECS),
+            Err(e) => Err(MutinyError::read_err(e)),
+        }
+    }
+
+    pub(crate) fn persist_invoice_expiry(&self, expiry_secs: u32) -> Result<(), MutinyError> {
+        let key = self.get_key(INVOICE_EXPIRY_KEY);
+        Ok(self.storage.set(key, expiry_secs)?)
+    }
+
+    /// Where the funds of a closed channel should be swept to, by funding outpoint.
+    pub(crate) fn read_close_destination(&self, funding_outpoint: &OutPoint) -> Option<Address> {
+        let key = self.get_key(close_destination_key(funding_outpoint).as_str());
+        let deserialized_value: Result<String, MutinyError> =
+            self.storage.get(key).map_err(MutinyError::read_err);
+        deserialized_value
+            .ok()
+            .and_then(|address| Address::from_str(&address).ok())
+    }
+
+    pub(crate) fn persist_close_destination(
+        &self,
+        funding_outpoint: &OutPoint,
+        address: &Address,
+    ) -> io::Result<()> {
+        let key = self.get_key(close_destination_key(funding_outpoint).as_str());
+        self.storage
+            .set(key, address.to_string())
+            .map_err(io::Error::other)
+    }
+
     pub(crate) fn list_peer_connection_info(&self) -> Vec<(PublicKey, String)> {
         let suffix = self.node_id.as_str();
         let map: HashMap<String, String> = self.storage.scan(PEER_PREFIX_KEY, Some(suffix));
@@ -326,6 +439,10 @@ fn peer_key(pubkey: String) -> String {
     format!("{PEER_PREFIX_KEY}{pubkey}")
 }
 
+fn close_destination_key(funding_outpoint: &OutPoint) -> String {
+    format!("{CLOSE_DESTINATION_PREFIX_KEY}{funding_outpoint}")
+}
+
 fn payment_key(inbound: bool, payment_hash: PaymentHash) -> String {
     let key = if inbound {
         format!(
@@ -345,7 +462,12 @@ fn payment_key(inbound: bool, payment_hash: PaymentHash) -> String {
 
 impl KVStorePersister for MutinyNodePersister {
     fn persist<W: Writeable>(&self, key: &str, object: &W) -> io::Result<()> {
-        let key_with_node = self.get_key(key);
+        // all of the nodes share one network graph
+        let key_with_node = if key == NETWORK_GRAPH_KEY {
+            key.to_string()
+        } else {
+            self.get_key(key)
+        };
         self.storage
             .set(key_with_node, object.encode())
             .map_err(io::Error::other)
@@ -7,16 +7,22 @@
 mod background;
 mod bdkstorage;
 mod chain;
+mod channelpolicy;
 mod encrypt;
 mod error;
 mod event;
+mod eventbus;
+mod gossip;
+mod holdinvoice;
 mod invoice;
 mod keymanager;
 mod ldkstorage;
+mod lnurl;
 mod localstorage;
 mod logging;
 mod node;
 mod nodemanager;
+mod paymentpolicy;
 mod peermanager;
 mod proxy;
 mod socket;
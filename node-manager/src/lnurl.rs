@@ -0,0 +1,598 @@
+use crate::error::MutinyError;
+use crate::keymanager::lnurl_auth_linking_key;
+use crate::utils::currency_from_network;
+use async_trait::async_trait;
+use bip39::Mnemonic;
+use bitcoin::bech32::{self, FromBase32};
+use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1};
+use bitcoin::Network;
+use lightning_invoice::{Invoice, InvoiceDescription};
+use log::{debug, error};
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::str::FromStr;
+
+/// Fetches the LNURL endpoints, a stand-in is used in the tests.
+#[async_trait(?Send)]
+pub(crate) trait LnUrlClient {
+    /// Sends a GET request to the url and returns the body of the response.
+    async fn get(&self, url: &str) -> Result<String, MutinyError>;
+}
+
+pub(crate) struct HttpLnUrlClient {
+    client: Client,
+}
+
+impl HttpLnUrlClient {
+    pub(crate) fn new() -> Result<Self, MutinyError> {
+        let client = Client::builder()
+            .build()
+            .map_err(|_| MutinyError::LnUrlFailure)?;
+        Ok(Self { client })
+    }
+}
+
+#[async_trait(?Send)]
+impl LnUrlClient for HttpLnUrlClient {
+    async fn get(&self, url: &str) -> Result<String, MutinyError> {
+        debug!("fetching {url}");
+        self.client
+            .get(url)
+            .send()
+            .await
+            .map_err(|_| MutinyError::LnUrlFailure)?
+            .error_for_status()
+            .map_err(|_| MutinyError::LnUrlFailure)?
+            .text()
+            .await
+            .map_err(|_| MutinyError::LnUrlFailure)
+    }
+}
+
+/// The parameters of an LNURL-pay endpoint (LUD-06), to show the user before paying it.
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct LnUrlPayRequest {
+    pub callback: String,
+    pub min_sendable: u64,
+    pub max_sendable: u64,
+    /// The raw metadata, the invoice's description hash must commit to it
+    pub metadata: String,
+    /// The `text/plain` entry of the metadata
+    pub description: Option<String>,
+    /// How long a comment may be, no comments are allowed when 0 (LUD-12)
+    pub comment_allowed: u16,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PayResponse {
+    callback: String,
+    min_sendable: u64,
+    max_sendable: u64,
+    metadata: String,
+    tag: String,
+    #[serde(default)]
+    comment_allowed: u16,
+}
+
+#[derive(Deserialize)]
+struct InvoiceResponse {
+    pr: String,
+}
+
+/// The parameters of an LNURL-withdraw voucher (LUD-03), to show the user before claiming it.
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct LnUrlWithdrawRequest {
+    pub callback: String,
+    pub k1: String,
+    pub default_description: String,
+    pub min_withdrawable: u64,
+    pub max_withdrawable: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WithdrawResponse {
+    callback: String,
+    k1: String,
+    #[serde(default)]
+    default_description: String,
+    min_withdrawable: u64,
+    max_withdrawable: u64,
+    tag: String,
+}
+
+#[derive(Deserialize)]
+struct StatusResponse {
+    status: String,
+}
+
+/// Turns a Lightning Address (LUD-16) or a bech32 encoded LNURL (LUD-01) into the url to fetch.
+pub(crate) fn lnurl_to_url(lnurl: &str) -> Result<Url, MutinyError> {
+    let lnurl = lnurl.trim();
+    let lnurl = lnurl
+        .strip_prefix("lightning:")
+        .or_else(|| lnurl.strip_prefix("LIGHTNING:"))
+        .unwrap_or(lnurl);
+
+    let url = if let Some((user, domain)) = lnurl.split_once('@') {
+        if user.is_empty() || domain.is_empty() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        // onion services don't have certificates
+        let scheme = if domain.ends_with(".onion") {
+            "http"
+        } else {
+            "https"
+        };
+        format!(
+            "{scheme}://{domain}/.well-known/lnurlp/{}",
+            user.to_lowercase()
+        )
+    } else {
+        let (hrp, data, _) =
+            bech32::decode(lnurl).map_err(|_| MutinyError::InvalidArgumentsError)?;
+        if hrp != "lnurl" {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        let bytes =
+            Vec::<u8>::from_base32(&data).map_err(|_| MutinyError::InvalidArgumentsError)?;
+        String::from_utf8(bytes).map_err(|_| MutinyError::InvalidArgumentsError)?
+    };
+
+    Url::parse(&url).map_err(|_| MutinyError::InvalidArgumentsError)
+}
+
+// LNURL services answer with a status and reason instead of the response when they fail
+fn parse_response<T: for<'de> Deserialize<'de>>(body: &str) -> Result<T, MutinyError> {
+    let value: Value = serde_json::from_str(body).map_err(|_| MutinyError::LnUrlFailure)?;
+    if value.get("status").and_then(Value::as_str) == Some("ERROR") {
+        let reason = value.get("reason").and_then(Value::as_str).unwrap_or("");
+        error!("LNURL service returned an error: {reason}");
+        return Err(MutinyError::LnUrlFailure);
+    }
+    serde_json::from_value(value).map_err(|_| MutinyError::LnUrlFailure)
+}
+
+fn metadata_description(metadata: &str) -> Option<String> {
+    let entries: Vec<(String, Value)> = serde_json::from_str(metadata).ok()?;
+    entries
+        .into_iter()
+        .find(|(mime, _)| mime == "text/plain")
+        .and_then(|(_, value)| value.as_str().map(str::to_string))
+}
+
+pub(crate) async fn fetch_pay_request(
+    client: &impl LnUrlClient,
+    lnurl: &str,
+) -> Result<LnUrlPayRequest, MutinyError> {
+    let url = lnurl_to_url(lnurl)?;
+    let response: PayResponse = parse_response(&client.get(url.as_str()).await?)?;
+
+    if response.tag != "payRequest" || response.min_sendable > response.max_sendable {
+        error!("not a valid LNURL-pay response from {url}");
+        return Err(MutinyError::LnUrlFailure);
+    }
+
+    Ok(LnUrlPayRequest {
+        callback: response.callback,
+        min_sendable: response.min_sendable,
+        max_sendable: response.max_sendable,
+        description: metadata_description(&response.metadata),
+        metadata: response.metadata,
+        comment_allowed: response.comment_allowed,
+    })
+}
+
+/// Requests an invoice for the amount from the pay request's callback and checks
+/// that it is for the amount, on our network and commits to the metadata.
+pub(crate) async fn fetch_invoice(
+    client: &impl LnUrlClient,
+    pay_request: &LnUrlPayRequest,
+    amount_msat: u64,
+    comment: Option<&str>,
+    network: Network,
+) -> Result<Invoice, MutinyError> {
+    if amount_msat < pay_request.min_sendable || amount_msat > pay_request.max_sendable {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+
+    let mut callback = Url::parse(&pay_request.callback).map_err(|_| MutinyError::LnUrlFailure)?;
+    callback
+        .query_pairs_mut()
+        .append_pair("amount", &amount_msat.to_string());
+    if let Some(comment) = comment.filter(|c| !c.is_empty()) {
+        if comment.chars().count() > pay_request.comment_allowed as usize {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        callback.query_pairs_mut().append_pair("comment", comment);
+    }
+
+    let response: InvoiceResponse = parse_response(&client.get(callback.as_str()).await?)?;
+    let invoice = Invoice::from_str(&response.pr)?;
+
+    if invoice.currency() != currency_from_network(network)
+        || invoice.amount_milli_satoshis() != Some(amount_msat)
+    {
+        error!("LNURL service returned an invoice for another network or amount");
+        return Err(MutinyError::InvoiceInvalid);
+    }
+    let metadata_hash = sha256::Hash::hash(pay_request.metadata.as_bytes());
+    match invoice.description() {
+        InvoiceDescription::Hash(hash) if hash.0 == metadata_hash => Ok(invoice),
+        _ => {
+            error!("LNURL service returned an invoice that does not commit to its metadata");
+            Err(MutinyError::InvoiceInvalid)
+        }
+    }
+}
+
+// the callbacks of withdraw and auth only answer with a status
+fn parse_status(body: &str) -> Result<(), MutinyError> {
+    let response: StatusResponse = parse_response(body)?;
+    if response.status != "OK" {
+        error!(
+            "LNURL service returned an unknown status: {}",
+            response.status
+        );
+        return Err(MutinyError::LnUrlFailure);
+    }
+    Ok(())
+}
+
+pub(crate) async fn fetch_withdraw_request(
+    client: &impl LnUrlClient,
+    lnurl: &str,
+) -> Result<LnUrlWithdrawRequest, MutinyError> {
+    let url = lnurl_to_url(lnurl)?;
+    let response: WithdrawResponse = parse_response(&client.get(url.as_str()).await?)?;
+
+    if response.tag != "withdrawRequest" || response.min_withdrawable > response.max_withdrawable {
+        error!("not a valid LNURL-withdraw response from {url}");
+        return Err(MutinyError::LnUrlFailure);
+    }
+
+    Ok(LnUrlWithdrawRequest {
+        callback: response.callback,
+        k1: response.k1,
+        default_description: response.default_description,
+        min_withdrawable: response.min_withdrawable,
+        max_withdrawable: response.max_withdrawable,
+    })
+}
+
+impl LnUrlWithdrawRequest {
+    /// Whether the voucher can be claimed for the amount, to check before creating the invoice.
+    pub(crate) fn allows_amount(&self, amount_msat: u64) -> bool {
+        amount_msat >= self.min_withdrawable && amount_msat <= self.max_withdrawable
+    }
+}
+
+/// Claims the voucher by handing our invoice to its callback, the service pays it
+/// in the background once it accepted it.
+pub(crate) async fn submit_withdraw_invoice(
+    client: &impl LnUrlClient,
+    withdraw_request: &LnUrlWithdrawRequest,
+    invoice: &Invoice,
+) -> Result<(), MutinyError> {
+    let amount_msat = invoice
+        .amount_milli_satoshis()
+        .ok_or(MutinyError::InvalidArgumentsError)?;
+    if !withdraw_request.allows_amount(amount_msat) {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+
+    let mut callback =
+        Url::parse(&withdraw_request.callback).map_err(|_| MutinyError::LnUrlFailure)?;
+    callback
+        .query_pairs_mut()
+        .append_pair("k1", &withdraw_request.k1)
+        .append_pair("pr", &invoice.to_string());
+
+    parse_status(&client.get(callback.as_str()).await?)
+}
+
+/// Logs into the service with LNURL-auth (LUD-04), signing its challenge with the
+/// linking key for its domain so the service sees the same key on every login.
+pub(crate) async fn lnurl_auth(
+    client: &impl LnUrlClient,
+    mnemonic: &Mnemonic,
+    lnurl: &str,
+) -> Result<(), MutinyError> {
+    let mut url = lnurl_to_url(lnurl)?;
+    let query = |key: &str| {
+        url.query_pairs()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.to_string())
+    };
+    if query("tag").as_deref() != Some("login") {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+    let k1 = query("k1")
+        .and_then(|k1| Vec::<u8>::from_hex(&k1).ok())
+        .and_then(|k1| Message::from_slice(&k1).ok())
+        .ok_or(MutinyError::InvalidArgumentsError)?;
+    let domain = url
+        .host_str()
+        .ok_or(MutinyError::InvalidArgumentsError)?
+        .to_string();
+
+    let secp = Secp256k1::new();
+    let linking_key = lnurl_auth_linking_key(mnemonic, &domain);
+    let sig = secp.sign_ecdsa(&k1, &linking_key);
+    url.query_pairs_mut()
+        .append_pair("sig", &sig.serialize_der().to_hex())
+        .append_pair(
+            "key",
+            &PublicKey::from_secret_key(&secp, &linking_key).to_string(),
+        );
+
+    parse_status(&client.get(url.as_str()).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::*;
+    use bitcoin::bech32::{ToBase32, Variant};
+    use bitcoin::secp256k1::ecdsa::Signature;
+    use bitcoin::secp256k1::SecretKey;
+    use lightning::ln::PaymentSecret;
+    use lightning_invoice::InvoiceBuilder;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    // the example from LUD-01
+    const LNURL: &str = "LNURL1DP68GURN8GHJ7UM9WFMXJCM99E3K7MF0V9CXJ0M385EKVCENXC6R2C35XVUKXEFCV5MKVV34X5EKZD3EV56NYD3HXQURZEPEXEJXXEPNXSCRVWFNV9NXZCN9XQ6XYEFHVGCXXCMYXYMNSERXFQ5FNS";
+    const METADATA: &str =
+        r#"[["text/plain","Pay alice"],["text/identifier","alice@example.com"]]"#;
+
+    /// Answers requests from canned responses by path, and remembers the urls it was sent.
+    #[derive(Default)]
+    struct StandInServer {
+        responses: HashMap<String, String>,
+        requests: Mutex<Vec<String>>,
+    }
+
+    #[async_trait(?Send)]
+    impl LnUrlClient for StandInServer {
+        async fn get(&self, url: &str) -> Result<String, MutinyError> {
+            self.requests.lock().unwrap().push(url.to_string());
+            let path = Url::parse(url).unwrap().path().to_string();
+            self.responses
+                .get(&path)
+                .cloned()
+                .ok_or(MutinyError::LnUrlFailure)
+        }
+    }
+
+    fn invoice(amount_msat: u64, metadata: &str) -> String {
+        let private_key = SecretKey::from_slice(&[42; 32]).unwrap();
+        InvoiceBuilder::new(currency_from_network(Network::Regtest))
+            .description_hash(sha256::Hash::hash(metadata.as_bytes()))
+            .payment_hash(sha256::Hash::hash(&[1; 32]))
+            .payment_secret(PaymentSecret([2; 32]))
+            .duration_since_epoch(crate::utils::now())
+            .min_final_cltv_expiry(144)
+            .amount_milli_satoshis(amount_msat)
+            .build_signed(|hash| Secp256k1::new().sign_ecdsa_recoverable(hash, &private_key))
+            .unwrap()
+            .to_string()
+    }
+
+    fn encode_lnurl(url: &str) -> String {
+        bech32::encode("lnurl", url.as_bytes().to_base32(), Variant::Bech32).unwrap()
+    }
+
+    fn server(invoice: String) -> StandInServer {
+        let pay_response = serde_json::json!({
+            "callback": "https://example.com/lnurlp/alice/callback",
+            "minSendable": 1_000,
+            "maxSendable": 1_000_000,
+            "metadata": METADATA,
+            "tag": "payRequest",
+            "commentAllowed": 10,
+        });
+        let mut responses = HashMap::new();
+        responses.insert(
+            "/.well-known/lnurlp/alice".to_string(),
+            pay_response.to_string(),
+        );
+        responses.insert(
+            "/lnurlp/alice/callback".to_string(),
+            serde_json::json!({ "pr": invoice, "routes": [] }).to_string(),
+        );
+        StandInServer {
+            responses,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_lnurl_to_url() {
+        log!("lnurl to url");
+
+        assert_eq!(
+            lnurl_to_url("Alice@example.com").unwrap().as_str(),
+            "https://example.com/.well-known/lnurlp/alice"
+        );
+        assert_eq!(
+            lnurl_to_url(&format!("lightning:{LNURL}"))
+                .unwrap()
+                .as_str(),
+            "https://service.com/api?q=3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df"
+        );
+        assert!(lnurl_to_url("@example.com").is_err());
+        assert!(lnurl_to_url("lnbc1").is_err());
+    }
+
+    #[test]
+    async fn test_lnurl_pay() {
+        log!("lnurl pay");
+
+        let server = server(invoice(10_000, METADATA));
+        let pay_request = fetch_pay_request(&server, "alice@example.com")
+            .await
+            .unwrap();
+        assert_eq!(pay_request.description, Some("Pay alice".to_string()));
+        assert_eq!(pay_request.comment_allowed, 10);
+
+        let invoice = fetch_invoice(
+            &server,
+            &pay_request,
+            10_000,
+            Some("thanks"),
+            Network::Regtest,
+        )
+        .await
+        .unwrap();
+        assert_eq!(invoice.amount_milli_satoshis(), Some(10_000));
+        assert_eq!(
+            server.requests.lock().unwrap().last().unwrap(),
+            "https://example.com/lnurlp/alice/callback?amount=10000&comment=thanks"
+        );
+
+        // amounts and comments outside the limits are not even requested
+        let requests = server.requests.lock().unwrap().len();
+        for (amount_msat, comment) in [
+            (500, None),
+            (2_000_000, None),
+            (10_000, Some("a comment that is too long")),
+        ] {
+            let res = fetch_invoice(
+                &server,
+                &pay_request,
+                amount_msat,
+                comment,
+                Network::Regtest,
+            )
+            .await;
+            assert!(matches!(res, Err(MutinyError::InvalidArgumentsError)));
+        }
+        assert_eq!(server.requests.lock().unwrap().len(), requests);
+
+        // the invoice is for another amount than asked for
+        let res = fetch_invoice(&server, &pay_request, 20_000, None, Network::Regtest).await;
+        assert!(matches!(res, Err(MutinyError::InvoiceInvalid)));
+    }
+
+    #[test]
+    async fn test_lnurl_pay_checks_description_hash() {
+        log!("lnurl pay checks description hash");
+
+        let server = server(invoice(10_000, "other metadata"));
+        let pay_request = fetch_pay_request(&server, "alice@example.com")
+            .await
+            .unwrap();
+        let res = fetch_invoice(&server, &pay_request, 10_000, None, Network::Regtest).await;
+        assert!(matches!(res, Err(MutinyError::InvoiceInvalid)));
+    }
+
+    #[test]
+    async fn test_lnurl_error_response() {
+        log!("lnurl error response");
+
+        let mut server = StandInServer::default();
+        server.responses.insert(
+            "/.well-known/lnurlp/bob".to_string(),
+            r#"{"status":"ERROR","reason":"unknown user"}"#.to_string(),
+        );
+        let res = fetch_pay_request(&server, "bob@example.com").await;
+        assert!(matches!(res, Err(MutinyError::LnUrlFailure)));
+    }
+
+    #[test]
+    async fn test_lnurl_withdraw() {
+        log!("lnurl withdraw");
+
+        let withdraw_response = serde_json::json!({
+            "callback": "https://example.com/withdraw/callback",
+            "k1": "voucher",
+            "defaultDescription": "A voucher",
+            "minWithdrawable": 1_000,
+            "maxWithdrawable": 100_000,
+            "tag": "withdrawRequest",
+        });
+        let mut service = StandInServer::default();
+        service
+            .responses
+            .insert("/withdraw".to_string(), withdraw_response.to_string());
+        service.responses.insert(
+            "/withdraw/callback".to_string(),
+            r#"{"status":"OK"}"#.to_string(),
+        );
+
+        let lnurl = encode_lnurl("https://example.com/withdraw");
+        let withdraw_request = fetch_withdraw_request(&service, &lnurl).await.unwrap();
+        assert_eq!(withdraw_request.default_description, "A voucher");
+        assert!(withdraw_request.allows_amount(100_000));
+        assert!(!withdraw_request.allows_amount(100_001));
+
+        let claim = Invoice::from_str(&invoice(10_000, METADATA)).unwrap();
+        submit_withdraw_invoice(&service, &withdraw_request, &claim)
+            .await
+            .unwrap();
+        let callback = Url::parse(service.requests.lock().unwrap().last().unwrap()).unwrap();
+        let query: HashMap<String, String> = callback.query_pairs().into_owned().collect();
+        assert_eq!(query["k1"], "voucher");
+        assert_eq!(query["pr"], claim.to_string());
+
+        // an invoice for more than the voucher is worth is not submitted
+        let requests = service.requests.lock().unwrap().len();
+        let claim = Invoice::from_str(&invoice(200_000, METADATA)).unwrap();
+        let res = submit_withdraw_invoice(&service, &withdraw_request, &claim).await;
+        assert!(matches!(res, Err(MutinyError::InvalidArgumentsError)));
+        assert_eq!(service.requests.lock().unwrap().len(), requests);
+
+        // a pay request is not a voucher
+        let pay_server = server(claim.to_string());
+        let res = fetch_withdraw_request(&pay_server, "alice@example.com").await;
+        assert!(matches!(res, Err(MutinyError::LnUrlFailure)));
+    }
+
+    #[test]
+    async fn test_lnurl_auth() {
+        log!("lnurl auth");
+
+        let mnemonic = Mnemonic::from_str("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about").unwrap();
+        let k1 = [7; 32];
+        let mut server = StandInServer::default();
+        server
+            .responses
+            .insert("/auth".to_string(), r#"{"status":"OK"}"#.to_string());
+
+        let lnurl = encode_lnurl(&format!(
+            "https://example.com/auth?tag=login&k1={}",
+            k1.to_hex()
+        ));
+        lnurl_auth(&server, &mnemonic, &lnurl).await.unwrap();
+
+        // the challenge is signed by the linking key for the domain
+        let login = Url::parse(server.requests.lock().unwrap().last().unwrap()).unwrap();
+        let query: HashMap<String, String> = login.query_pairs().into_owned().collect();
+        let sig = Signature::from_der(&Vec::<u8>::from_hex(&query["sig"]).unwrap()).unwrap();
+        let key = PublicKey::from_str(&query["key"]).unwrap();
+        let linking_key = lnurl_auth_linking_key(&mnemonic, "example.com");
+        assert_eq!(
+            key,
+            PublicKey::from_secret_key(&Secp256k1::new(), &linking_key)
+        );
+        Secp256k1::new()
+            .verify_ecdsa(&Message::from_slice(&k1).unwrap(), &sig, &key)
+            .unwrap();
+
+        // only login requests with a valid challenge are signed
+        let lnurl = encode_lnurl("https://example.com/auth?tag=login&k1=00");
+        let res = lnurl_auth(&server, &mnemonic, &lnurl).await;
+        assert!(matches!(res, Err(MutinyError::InvalidArgumentsError)));
+        let res = lnurl_auth(&server, &mnemonic, "alice@example.com").await;
+        assert!(matches!(res, Err(MutinyError::InvalidArgumentsError)));
+    }
+}
@@ -1,8 +1,9 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::str;
 use std::str::FromStr;
 
 use bip39::Mnemonic;
+use bitcoin::OutPoint;
 
 use gloo_storage::errors::StorageError;
 use gloo_storage::{LocalStorage, Storage};
@@ -10,20 +11,41 @@ use serde::{Deserialize, Serialize};
 
 use crate::encrypt::*;
 use crate::error::MutinyStorageError;
-use crate::nodemanager::NodeStorage;
+use crate::nodemanager::{AccountStorage, NodeStorage, WatchOnlyStorage};
+use crate::wallet::WalletSettings;
 
 const mnemonic_key: &str = "mnemonic";
+const wallet_settings_key: &str = "wallet_settings";
 const nodes_key: &str = "nodes";
 const fee_estimates_key: &str = "fee_estimates";
+const watch_only_wallets_key: &str = "watch_only_wallets";
+const accounts_key: &str = "accounts";
+const frozen_utxos_key: &str = "frozen_utxos";
+const reserved_utxos_key: &str = "reserved_utxos";
+const anchor_channels_key: &str = "anchor_channels";
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MutinyBrowserStorage {
     pub(crate) password: String,
+    // Prefix for the bdk wallet keys, None for the main wallet
+    pub(crate) wallet_prefix: Option<String>,
 }
 
 impl MutinyBrowserStorage {
     pub fn new(password: String) -> MutinyBrowserStorage {
-        MutinyBrowserStorage { password }
+        MutinyBrowserStorage {
+            password,
+            wallet_prefix: None,
+        }
+    }
+
+    /// Creates a storage that keeps its bdk wallet data under the given prefix
+    /// so that multiple wallets can live side by side in local storage.
+    pub(crate) fn with_wallet_prefix(&self, prefix: String) -> MutinyBrowserStorage {
+        MutinyBrowserStorage {
+            password: self.password.clone(),
+            wallet_prefix: Some(prefix),
+        }
     }
 
     // A wrapper for LocalStorage::set that converts the error to MutinyError
@@ -56,12 +78,27 @@ impl MutinyBrowserStorage {
         }
     }
 
-    // FIXME: Useful to keep around until we use it
-    #[allow(dead_code)]
     pub(crate) fn delete(key: impl AsRef<str>) {
         LocalStorage::delete(key);
     }
 
+    pub(crate) fn delete_with_prefix(prefix: &str) {
+        let local_storage = LocalStorage::raw();
+        let length = LocalStorage::length();
+        let mut keys = Vec::new();
+        for index in 0..length {
+            if let Some(key) = local_storage.key(index).unwrap() {
+                if key.starts_with(prefix) {
+                    keys.push(key);
+                }
+            }
+        }
+
+        for key in keys {
+            LocalStorage::delete(key);
+        }
+    }
+
     pub(crate) fn scan<T>(&self, prefix: &str, suffix: Option<&str>) -> HashMap<String, T>
     where
         T: for<'de> Deserialize<'de>,
@@ -106,6 +143,24 @@ impl MutinyBrowserStorage {
         }
     }
 
+    pub(crate) fn get_wallet_settings(&self) -> Result<WalletSettings, MutinyStorageError> {
+        let res: Result<WalletSettings, MutinyStorageError> = self.get(wallet_settings_key);
+        match res {
+            Ok(s) => Ok(s),
+            Err(MutinyStorageError::StorageError {
+                source: StorageError::KeyNotFound(_),
+            }) => Ok(WalletSettings::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub(crate) fn insert_wallet_settings(
+        &self,
+        settings: WalletSettings,
+    ) -> Result<(), MutinyStorageError> {
+        self.set(wallet_settings_key, settings)
+    }
+
     pub(crate) fn has_mnemonic() -> bool {
         LocalStorage::get::<String>("mnemonic").is_ok()
     }
@@ -132,6 +187,116 @@ impl MutinyBrowserStorage {
         Ok(LocalStorage::set(nodes_key, nodes)?)
     }
 
+    pub(crate) fn get_watch_only_wallets(&self) -> Result<WatchOnlyStorage, MutinyStorageError> {
+        let res: Result<WatchOnlyStorage, MutinyStorageError> = self.get(watch_only_wallets_key);
+        match res {
+            Ok(w) => Ok(w),
+            Err(MutinyStorageError::StorageError {
+                source: StorageError::KeyNotFound(_),
+            }) => Ok(WatchOnlyStorage {
+                wallets: HashMap::new(),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub(crate) fn insert_watch_only_wallets(
+        &self,
+        wallets: WatchOnlyStorage,
+    ) -> Result<(), MutinyStorageError> {
+        self.set(watch_only_wallets_key, wallets)
+    }
+
+    pub(crate) fn get_accounts(&self) -> Result<AccountStorage, MutinyStorageError> {
+        let res: Result<AccountStorage, MutinyStorageError> = self.get(accounts_key);
+        match res {
+            Ok(a) => Ok(a),
+            Err(MutinyStorageError::StorageError {
+                source: StorageError::KeyNotFound(_),
+            }) => Ok(AccountStorage {
+                accounts: HashMap::new(),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub(crate) fn insert_accounts(
+        &self,
+        accounts: AccountStorage,
+    ) -> Result<(), MutinyStorageError> {
+        self.set(accounts_key, accounts)
+    }
+
+    // Frozen and reserved utxos are kept per wallet so they use the wallet prefix
+    fn wallet_key(&self, key: &str) -> String {
+        match self.wallet_prefix {
+            Some(ref prefix) => format!("{prefix}{key}"),
+            None => key.to_string(),
+        }
+    }
+
+    pub(crate) fn get_frozen_utxos(&self) -> Result<Vec<OutPoint>, MutinyStorageError> {
+        let res: Result<Vec<OutPoint>, MutinyStorageError> =
+            self.get(self.wallet_key(frozen_utxos_key));
+        match res {
+            Ok(f) => Ok(f),
+            Err(MutinyStorageError::StorageError {
+                source: StorageError::KeyNotFound(_),
+            }) => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub(crate) fn insert_frozen_utxos(
+        &self,
+        outpoints: Vec<OutPoint>,
+    ) -> Result<(), MutinyStorageError> {
+        self.set(self.wallet_key(frozen_utxos_key), outpoints)
+    }
+
+    /// Utxos spent by channel funding transactions that haven't been discarded or closed yet,
+    /// keyed by the hex channel id, and by transaction previews that are still pending.
+    pub(crate) fn get_reserved_utxos(
+        &self,
+    ) -> Result<HashMap<String, Vec<OutPoint>>, MutinyStorageError> {
+        let res: Result<HashMap<String, Vec<OutPoint>>, MutinyStorageError> =
+            self.get(self.wallet_key(reserved_utxos_key));
+        match res {
+            Ok(r) => Ok(r),
+            Err(MutinyStorageError::StorageError {
+                source: StorageError::KeyNotFound(_),
+            }) => Ok(HashMap::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub(crate) fn insert_reserved_utxos(
+        &self,
+        reserved: HashMap<String, Vec<OutPoint>>,
+    ) -> Result<(), MutinyStorageError> {
+        self.set(self.wallet_key(reserved_utxos_key), reserved)
+    }
+
+    /// The hex ids of the anchor channels that on-chain funds are kept aside for.
+    pub(crate) fn get_anchor_channels(&self) -> Result<HashSet<String>, MutinyStorageError> {
+        let res: Result<HashSet<String>, MutinyStorageError> =
+            self.get(self.wallet_key(anchor_channels_key));
+        match res {
+            Ok(a) => Ok(a),
+            Err(MutinyStorageError::StorageError {
+                source: StorageError::KeyNotFound(_),
+            }) => Ok(HashSet::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub(crate) fn insert_anchor_channels(
+        &self,
+        channel_ids: HashSet<String>,
+    ) -> Result<(), MutinyStorageError> {
+        self.set(self.wallet_key(anchor_channels_key), channel_ids)
+    }
+
     pub(crate) fn get_fee_estimates() -> Result<HashMap<String, f64>, MutinyStorageError> {
         Ok(LocalStorage::get(fee_estimates_key)?)
     }
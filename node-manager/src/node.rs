@@ -1,10 +1,22 @@
 use crate::chain::MutinyChain;
+use crate::channelpolicy::{ChannelAcceptancePolicy, ChannelAcceptor};
 use crate::error::MutinyStorageError;
-use crate::event::{EventHandler, HTLCStatus, MillisatAmount, PaymentInfo};
+use crate::event::{
+    EventHandler, HTLCStatus, MillisatAmount, PaymentAttempt, PaymentInfo, PaymentWaiters,
+};
+use crate::eventbus::EventBus;
+use crate::gossip::Gossip;
+use crate::holdinvoice::{
+    HoldInvoice, HoldInvoiceParams, HoldInvoices, HOLD_INVOICE_CHECK_INTERVAL_SECS,
+};
 use crate::invoice::create_phantom_invoice;
 use crate::ldkstorage::{MutinyNodePersister, PhantomChannelManager};
 use crate::localstorage::MutinyBrowserStorage;
-use crate::nodemanager::{MutinyInvoice, MutinyInvoiceParams};
+use crate::nodemanager::{MutinyChannel, MutinyInvoice, MutinyInvoiceParams};
+use crate::paymentpolicy::{
+    invoice_amount_msat, invoice_route_params, OutboundPayments, PaymentEstimate, PaymentPolicy,
+    BACKGROUND_PROBING_INTERVAL_SECS,
+};
 use crate::peermanager::{PeerManager, PeerManagerImpl};
 use crate::proxy::WsProxy;
 use crate::socket::WsTcpSocketDescriptor;
@@ -12,7 +24,7 @@ use crate::socket::{schedule_descriptor_read, MultiWsSocketDescriptor, WsSocketD
 use crate::utils::{currency_from_network, sleep};
 use crate::wallet::MutinyWallet;
 use crate::{
-    background::{process_events_async, GossipSync},
+    background::process_events_async,
     error::MutinyError,
     keymanager::{create_keys_manager, pubkey_from_keys_manager},
     logging::MutinyLogger,
@@ -21,43 +33,66 @@ use crate::{
 use anyhow::Context;
 use bdk::blockchain::EsploraBlockchain;
 use bip39::Mnemonic;
-use bitcoin::blockdata::constants::genesis_block;
 use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::hashes::Hash;
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::Network;
-use bitcoin_hashes::hex::ToHex;
+use bitcoin_hashes::hex::{FromHex, ToHex};
+use futures::future::{join_all, select, Either};
+use futures::pin_mut;
+use lightning::chain::channelmonitor::Balance;
 use lightning::chain::keysinterface::{
     InMemorySigner, KeysInterface, PhantomKeysManager, Recipient,
 };
+use lightning::chain::transaction::OutPoint;
 use lightning::chain::{chainmonitor, Filter, Watch};
-use lightning::ln::channelmanager::PhantomRouteHints;
-use lightning::ln::msgs::NetAddress;
+use lightning::ln::channelmanager::{PhantomRouteHints, MIN_FINAL_CLTV_EXPIRY};
+use lightning::ln::msgs::{NetAddress, RoutingMessageHandler};
 use lightning::ln::peer_handler::{
     IgnoringMessageHandler, MessageHandler as LdkMessageHandler,
     SocketDescriptor as LdkSocketDescriptor,
 };
 use lightning::ln::{PaymentHash, PaymentPreimage};
+use lightning::onion_message;
 use lightning::routing::gossip;
-use lightning::routing::router::DefaultRouter;
 use lightning::routing::scoring::{ProbabilisticScorer, ProbabilisticScoringParameters};
 use lightning::util::config::{ChannelHandshakeConfig, ChannelHandshakeLimits, UserConfig};
 use lightning::util::logger::{Logger, Record};
 use lightning::util::ser::Writeable;
 use lightning_invoice::utils::create_invoice_from_channelmanager_and_duration_since_epoch;
-use lightning_invoice::{payment, Invoice};
+use lightning_invoice::Invoice;
 use log::{debug, error, info, trace};
+use std::collections::HashSet;
 use std::net::SocketAddr;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use wasm_bindgen_futures::spawn_local;
 
+/// How long to wait for a payment to resolve when no timeout is given.
+pub const DEFAULT_PAYMENT_TIMEOUT_SECS: u64 = 60;
+
+/// How long invoices are valid for when no expiry is given or set for the node.
+pub const DEFAULT_INVOICE_EXPIRY_SECS: u32 = 1500;
+
+// How often the unpaid invoices are checked for having expired
+const INVOICE_EXPIRY_CHECK_INTERVAL_SECS: u64 = 60;
+
+// How often closed anchor channels are checked for having nothing left to claim
+const ANCHOR_RESERVE_CHECK_INTERVAL_SECS: u64 = 10 * 60;
+
 pub(crate) type NetworkGraph = gossip::NetworkGraph<Arc<MutinyLogger>>;
 
+pub(crate) type OnionMessenger = onion_message::OnionMessenger<
+    InMemorySigner,
+    Arc<PhantomKeysManager>,
+    Arc<MutinyLogger>,
+    Arc<IgnoringMessageHandler>,
+>;
+
 pub(crate) type MessageHandler = LdkMessageHandler<
     Arc<PhantomChannelManager>,
-    Arc<IgnoringMessageHandler>,
-    Arc<IgnoringMessageHandler>,
+    Arc<dyn RoutingMessageHandler + Send + Sync>,
+    Arc<OnionMessenger>,
 >;
 
 pub(crate) type ChainMonitor = chainmonitor::ChainMonitor<
@@ -69,14 +104,7 @@ pub(crate) type ChainMonitor = chainmonitor::ChainMonitor<
     Arc<MutinyNodePersister>,
 >;
 
-pub(crate) type InvoicePayer<E> =
-    payment::InvoicePayer<Arc<PhantomChannelManager>, Router, Arc<MutinyLogger>, E>;
-
-type Router = DefaultRouter<
-    Arc<NetworkGraph>,
-    Arc<MutinyLogger>,
-    Arc<Mutex<ProbabilisticScorer<Arc<NetworkGraph>, Arc<MutinyLogger>>>>,
->;
+pub(crate) type Scorer = ProbabilisticScorer<Arc<NetworkGraph>, Arc<MutinyLogger>>;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(crate) enum ConnectionType {
@@ -125,7 +153,10 @@ pub(crate) struct Node {
     pub keys_manager: Arc<PhantomKeysManager>,
     pub channel_manager: Arc<PhantomChannelManager>,
     pub chain_monitor: Arc<ChainMonitor>,
-    pub invoice_payer: Arc<InvoicePayer<EventHandler>>,
+    pub outbound_payments: Arc<OutboundPayments>,
+    pub hold_invoices: Arc<HoldInvoices>,
+    pub channel_acceptor: Arc<ChannelAcceptor>,
+    payment_waiters: Arc<PaymentWaiters>,
     network: Network,
     pub persister: Arc<MutinyNodePersister>,
     logger: Arc<MutinyLogger>,
@@ -144,6 +175,9 @@ impl Node {
         network: Network,
         websocket_proxy_addr: String,
         esplora: Arc<EsploraBlockchain>,
+        network_graph: Arc<NetworkGraph>,
+        gossip: Gossip,
+        event_bus: Arc<EventBus>,
     ) -> Result<Self, MutinyError> {
         info!("initialized a new node: {}", node_index.uuid);
 
@@ -187,20 +221,73 @@ impl Node {
         let channel_manager: Arc<PhantomChannelManager> =
             Arc::new(read_channel_manager.channel_manager);
 
+        // keep on-chain funds aside to fee bump our existing anchor channels
+        #[cfg(anchors)]
+        for channel in channel_manager.list_channels() {
+            if channel
+                .channel_type
+                .as_ref()
+                .map_or(false, |t| t.supports_anchors_zero_fee_htlc_tx())
+            {
+                wallet.add_anchor_channel(channel.channel_id).await?;
+            }
+        }
+
+        let channel_acceptor = Arc::new(ChannelAcceptor::new(
+            channel_manager.clone(),
+            persister.clone(),
+        )?);
+
+        // init onion messenger, it relays onion messages for our peers
+        let onion_messenger = Arc::new(OnionMessenger::new(
+            keys_manager.clone(),
+            logger.clone(),
+            Arc::new(IgnoringMessageHandler {}),
+        ));
+
         // init peer manager
         let ln_msg_handler = MessageHandler {
             chan_handler: channel_manager.clone(),
-            route_handler: Arc::new(IgnoringMessageHandler {}),
-            onion_message_handler: Arc::new(IgnoringMessageHandler {}),
+            route_handler: gossip.route_handler(),
+            onion_message_handler: onion_messenger,
         };
 
+        // create scorer
+        let params = ProbabilisticScoringParameters::default();
+        let scorer = Arc::new(Mutex::new(ProbabilisticScorer::new(
+            params,
+            network_graph.clone(),
+            logger.clone(),
+        )));
+
+        let outbound_payments = Arc::new(OutboundPayments::new(
+            channel_manager.clone(),
+            network_graph,
+            scorer.clone(),
+            keys_manager.clone(),
+            persister.clone(),
+            logger.clone(),
+        )?);
+
+        let hold_invoices = Arc::new(HoldInvoices::new(
+            channel_manager.clone(),
+            persister.clone(),
+            logger.clone(),
+        ));
+
         // init event handler
+        let payment_waiters = Arc::new(PaymentWaiters::default());
         let event_handler = EventHandler::new(
             channel_manager.clone(),
             chain.clone(),
             wallet.clone(),
             keys_manager.clone(),
             persister.clone(),
+            channel_acceptor.clone(),
+            outbound_payments.clone(),
+            hold_invoices.clone(),
+            payment_waiters.clone(),
+            event_bus,
             network,
             logger.clone(),
         );
@@ -240,34 +327,6 @@ impl Node {
             }
         }
 
-        // todo use RGS
-        // get network graph
-        let genesis_hash = genesis_block(network).block_hash();
-        let network_graph = Arc::new(persister.read_network_graph(genesis_hash, logger.clone()));
-
-        // create scorer
-        let params = ProbabilisticScoringParameters::default();
-        let scorer = Arc::new(Mutex::new(ProbabilisticScorer::new(
-            params,
-            network_graph.clone(),
-            logger.clone(),
-        )));
-
-        let router: Router = DefaultRouter::new(
-            network_graph,
-            logger.clone(),
-            keys_manager.get_secure_random_bytes(),
-            scorer.clone(),
-        );
-
-        let invoice_payer: Arc<InvoicePayer<EventHandler>> = Arc::new(InvoicePayer::new(
-            Arc::clone(&channel_manager),
-            router,
-            Arc::clone(&logger),
-            event_handler.clone(),
-            payment::Retry::Attempts(5),
-        ));
-
         let background_persister = persister.clone();
         let background_event_handler = event_handler.clone();
         let background_processor_logger = logger.clone();
@@ -277,7 +336,7 @@ impl Node {
 
         spawn_local(async move {
             loop {
-                let gs: GossipSync<_, _, &NetworkGraph, _, Arc<MutinyLogger>> = GossipSync::none();
+                let gs = gossip.background_sync();
                 let ev = background_event_handler.clone();
                 process_events_async(
                     background_persister.clone(),
@@ -298,6 +357,51 @@ impl Node {
             }
         });
 
+        // keep the scorer warm for the destinations we pay the most
+        let background_outbound_payments = outbound_payments.clone();
+        spawn_local(async move {
+            loop {
+                sleep((BACKGROUND_PROBING_INTERVAL_SECS * 1_000) as i32).await;
+                if background_outbound_payments.background_probing() {
+                    background_outbound_payments.probe_destinations();
+                }
+            }
+        });
+
+        // cancel the held payments before they get close to expiring
+        let background_hold_invoices = hold_invoices.clone();
+        spawn_local(async move {
+            loop {
+                background_hold_invoices.cancel_expired();
+                sleep((HOLD_INVOICE_CHECK_INTERVAL_SECS * 1_000) as i32).await;
+            }
+        });
+
+        // mark the invoices that expired unpaid, late payments to them are failed back
+        let expiry_persister = persister.clone();
+        spawn_local(async move {
+            loop {
+                expire_invoices(&expiry_persister);
+                sleep((INVOICE_EXPIRY_CHECK_INTERVAL_SECS * 1_000) as i32).await;
+            }
+        });
+
+        // release the fee bumping reserve of anchor channels once their closing is resolved
+        let reserve_channel_manager = channel_manager.clone();
+        let reserve_chain_monitor = chain_monitor.clone();
+        let reserve_wallet = wallet.clone();
+        spawn_local(async move {
+            loop {
+                release_anchor_reserves(
+                    &reserve_channel_manager,
+                    &reserve_chain_monitor,
+                    &reserve_wallet,
+                )
+                .await;
+                sleep((ANCHOR_RESERVE_CHECK_INTERVAL_SECS * 1_000) as i32).await;
+            }
+        });
+
         // create a connection immediately to the user's
         // specified mutiny websocket proxy provider.
         let self_connection = PubkeyConnectionInfo {
@@ -425,7 +529,10 @@ impl Node {
             keys_manager,
             channel_manager,
             chain_monitor,
-            invoice_payer,
+            outbound_payments,
+            hold_invoices,
+            channel_acceptor,
+            payment_waiters,
             network,
             persister,
             logger,
@@ -502,15 +609,25 @@ impl Node {
         self.channel_manager.get_phantom_route_hints()
     }
 
-    pub fn create_invoice(
+    /// Creates an invoice, or a hold invoice when given its payment hash. The payments to
+    /// a hold invoice are held until they are settled with the preimage or cancelled.
+    /// Without an expiry the node's invoice expiry is used.
+    pub(crate) fn create_invoice(
         &self,
         amount_sat: Option<u64>,
         description: String,
         route_hints: Option<Vec<PhantomRouteHints>>,
+        hold: Option<HoldInvoiceParams>,
+        expiry_secs: Option<u32>,
     ) -> Result<Invoice, MutinyError> {
         let amount_msat = amount_sat.map(|s| s * 1_000);
-        let invoice_res = match route_hints {
-            None => {
+        let expiry_secs = match expiry_secs {
+            Some(0) => return Err(MutinyError::InvalidArgumentsError),
+            Some(expiry_secs) => expiry_secs,
+            None => self.persister.read_invoice_expiry()?,
+        };
+        let invoice_res = match (route_hints, &hold) {
+            (None, None) => {
                 let now = crate::utils::now();
                 create_invoice_from_channelmanager_and_duration_since_epoch(
                     &self.channel_manager.clone(),
@@ -520,17 +637,29 @@ impl Node {
                     amount_msat,
                     description,
                     now,
-                    1500,
+                    expiry_secs,
                 )
             }
-            Some(r) => create_phantom_invoice::<InMemorySigner, Arc<PhantomKeysManager>>(
+            // hold invoices are only paid to this node, which knows to hold their payments
+            (_, Some(hold)) => create_phantom_invoice::<InMemorySigner, Arc<PhantomKeysManager>>(
+                amount_msat,
+                Some(hold.payment_hash),
+                description,
+                expiry_secs,
+                vec![self.get_phantom_route_hint()],
+                self.keys_manager.clone(),
+                currency_from_network(self.network),
+                hold.min_final_cltv_expiry,
+            ),
+            (Some(r), None) => create_phantom_invoice::<InMemorySigner, Arc<PhantomKeysManager>>(
                 amount_msat,
                 None,
                 description,
-                1500,
+                expiry_secs,
                 r,
                 self.keys_manager.clone(),
                 currency_from_network(self.network),
+                MIN_FINAL_CLTV_EXPIRY,
             ),
         };
         let invoice = invoice_res.map_err(|e| {
@@ -554,6 +683,8 @@ impl Node {
             fee_paid_msat: None,
             bolt11: Some(invoice.to_string()),
             last_update,
+            attempts: vec![],
+            hold: hold.map(|_| HoldInvoice::default()),
         };
         self.persister
             .persist_payment_info(payment_hash, payment_info, true)
@@ -579,12 +710,28 @@ impl Node {
         Ok(invoice)
     }
 
+    /// How long the invoices of this node are valid for when no expiry is given.
+    pub fn invoice_expiry(&self) -> Result<u32, MutinyError> {
+        self.persister.read_invoice_expiry()
+    }
+
+    pub fn set_invoice_expiry(&self, expiry_secs: u32) -> Result<(), MutinyError> {
+        if expiry_secs == 0 {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        self.persister.persist_invoice_expiry(expiry_secs)
+    }
+
     pub fn get_invoice(&self, invoice: Invoice) -> Result<MutinyInvoice, MutinyError> {
         let payment_hash = invoice.payment_hash();
         let (payment_info, inbound) = self.get_payment_info_from_persisters(payment_hash)?;
         let mut mutiny_invoice: MutinyInvoice = invoice.into();
         mutiny_invoice.is_send = !inbound;
         mutiny_invoice.paid = matches!(payment_info.status, HTLCStatus::Succeeded);
+        mutiny_invoice.held = matches!(payment_info.status, HTLCStatus::Held);
+        mutiny_invoice.preimage = payment_info.preimage.map(|p| p.to_hex());
+        mutiny_invoice.fees_paid = payment_info.fee_paid_msat.map(|f| f / 1_000);
+        mutiny_invoice.attempts = payment_info.attempts;
         Ok(mutiny_invoice)
     }
 
@@ -606,14 +753,22 @@ impl Node {
                     let invoice_res = Invoice::from_str(&bolt11).map_err(Into::<MutinyError>::into);
                     match invoice_res {
                         Ok(invoice) => {
+                            // keep failed payments around, their attempts tell what went wrong
                             if invoice.would_expire(now)
-                                && !matches!(i.status, HTLCStatus::Succeeded | HTLCStatus::InFlight)
+                                && !matches!(
+                                    i.status,
+                                    HTLCStatus::Succeeded
+                                        | HTLCStatus::InFlight
+                                        | HTLCStatus::Failed
+                                        | HTLCStatus::Held
+                                )
                             {
                                 None
                             } else {
                                 let mut mutiny_invoice: MutinyInvoice = invoice.clone().into();
                                 mutiny_invoice.is_send = !inbound;
                                 mutiny_invoice.paid = matches!(i.status, HTLCStatus::Succeeded);
+                                mutiny_invoice.held = matches!(i.status, HTLCStatus::Held);
                                 mutiny_invoice.amount_sats =
                                     if let Some(inv_amt) = invoice.amount_milli_satoshis() {
                                         if inv_amt == 0 {
@@ -624,6 +779,7 @@ impl Node {
                                     } else {
                                         i.amt_msat.0.map(|a| a / 1_000)
                                     };
+                                mutiny_invoice.attempts = i.attempts;
                                 Some(mutiny_invoice)
                             }
                         }
@@ -645,10 +801,13 @@ impl Node {
                         amount_sats,
                         expire: i.last_update,
                         paid,
+                        held: matches!(i.status, HTLCStatus::Held),
                         fees_paid,
                         is_send: !inbound,
                     };
-                    Some(MutinyInvoice::new(params))
+                    let mut mutiny_invoice = MutinyInvoice::new(params);
+                    mutiny_invoice.attempts = i.attempts;
+                    Some(mutiny_invoice)
                 }
             })
             .collect()
@@ -683,26 +842,12 @@ impl Node {
         &self,
         invoice: Invoice,
         amt_sats: Option<u64>,
+        policy: Option<PaymentPolicy>,
     ) -> Result<MutinyInvoice, MutinyError> {
-        let (pay_result, amt_msat) = if invoice.amount_milli_satoshis().is_none() {
-            if amt_sats.is_none() {
-                return Err(MutinyError::InvoiceInvalid);
-            }
-            let amt_msats = amt_sats.unwrap() * 1_000;
-            (
-                self.invoice_payer
-                    .pay_zero_value_invoice(&invoice, amt_msats),
-                amt_msats,
-            )
-        } else {
-            if amt_sats.is_some() {
-                return Err(MutinyError::InvoiceInvalid);
-            }
-            (
-                self.invoice_payer.pay_invoice(&invoice),
-                invoice.amount_milli_satoshis().unwrap(),
-            )
-        };
+        let amt_msat = invoice_amount_msat(&invoice, amt_sats)?;
+        let pay_result = self
+            .outbound_payments
+            .pay_invoice(&invoice, amt_msat, policy);
 
         let last_update = crate::utils::now().as_secs();
         let mut payment_info = PaymentInfo {
@@ -713,6 +858,8 @@ impl Node {
             fee_paid_msat: None,
             bolt11: Some(invoice.to_string()),
             last_update,
+            attempts: vec![],
+            hold: None,
         };
         self.persister.persist_payment_info(
             PaymentHash(invoice.payment_hash().into_inner()),
@@ -734,29 +881,34 @@ impl Node {
                 debug!("current channel details: {:?}", current_channels);
 
                 payment_info.status = HTLCStatus::Failed;
+                payment_info.add_attempt(PaymentAttempt::failed(&[], e.to_string(), false));
                 self.persister.persist_payment_info(
                     PaymentHash(invoice.payment_hash().into_inner()),
                     payment_info,
                     false,
                 )?;
-                Err(MutinyError::RoutingFailed)
+                Err(e)
             }
         }
     }
 
     /// keysend sends off the payment but does not wait for results
-    pub fn keysend(&self, to_node: PublicKey, amt_sats: u64) -> Result<MutinyInvoice, MutinyError> {
+    pub fn keysend(
+        &self,
+        to_node: PublicKey,
+        amt_sats: u64,
+        policy: Option<PaymentPolicy>,
+    ) -> Result<MutinyInvoice, MutinyError> {
         let mut entropy = [0u8; 32];
         getrandom::getrandom(&mut entropy).map_err(|_| MutinyError::SeedGenerationFailed)?;
         let preimage = PaymentPreimage(entropy);
 
         let amt_msats = amt_sats * 1000;
 
-        let pay_result = self
-            .invoice_payer
-            .pay_pubkey(to_node, preimage, amt_msats, 40);
-
         let payment_hash = PaymentHash(Sha256::hash(&preimage.0).into_inner());
+        let pay_result =
+            self.outbound_payments
+                .keysend(to_node, amt_msats, preimage, payment_hash, policy);
 
         let last_update = crate::utils::now().as_secs();
         let mut payment_info = PaymentInfo {
@@ -767,6 +919,8 @@ impl Node {
             fee_paid_msat: None,
             bolt11: None,
             last_update,
+            attempts: vec![],
+            hold: None,
         };
 
         self.persister
@@ -783,27 +937,126 @@ impl Node {
                     amount_sats: Some(amt_sats),
                     expire: payment_info.last_update,
                     paid: false,
+                    held: false,
                     fees_paid: None,
                     is_send: true,
                 };
                 let mutiny_invoice: MutinyInvoice = MutinyInvoice::new(params);
                 Ok(mutiny_invoice)
             }
-            Err(_) => {
+            Err(e) => {
                 payment_info.status = HTLCStatus::Failed;
+                payment_info.add_attempt(PaymentAttempt::failed(&[], e.to_string(), false));
                 self.persister
                     .persist_payment_info(payment_hash, payment_info, false)?;
-                Err(MutinyError::RoutingFailed)
+                Err(e)
             }
         }
     }
 
+    /// Finds the route we would pay the invoice over and its fees, without paying it.
+    pub fn estimate_payment(
+        &self,
+        invoice: &Invoice,
+        amt_sats: Option<u64>,
+    ) -> Result<PaymentEstimate, MutinyError> {
+        let amt_msat = invoice_amount_msat(invoice, amt_sats)?;
+        self.outbound_payments
+            .estimate(&invoice_route_params(invoice, amt_msat))
+    }
+
+    /// Probes the routes to the node for the amount and waits for the probes to come back,
+    /// for at most `timeout_secs` or [`DEFAULT_PAYMENT_TIMEOUT_SECS`].
+    /// Returns whether every path of the route could carry the amount.
+    pub async fn probe(
+        &self,
+        to_node: PublicKey,
+        amt_sats: u64,
+        timeout_secs: Option<u64>,
+    ) -> Result<bool, MutinyError> {
+        let timeout_secs = timeout_secs.unwrap_or(DEFAULT_PAYMENT_TIMEOUT_SECS);
+        let payment_hashes = self.outbound_payments.probe(to_node, amt_sats * 1_000)?;
+        // wait on every probe before yielding, so no result can be missed
+        let results = join_all(
+            payment_hashes
+                .into_iter()
+                .map(|payment_hash| self.payment_waiters.wait(payment_hash)),
+        );
+        let timeout = sleep((timeout_secs * 1_000) as i32);
+        pin_mut!(timeout);
+
+        match select(results, timeout).await {
+            Either::Left((results, _)) => Ok(results.iter().all(|r| matches!(r, Ok(Ok(()))))),
+            Either::Right(_) => Err(MutinyError::PaymentTimeout),
+        }
+    }
+
+    /// Pays the invoice and waits for the payment to succeed or fail,
+    /// for at most `timeout_secs` or [`DEFAULT_PAYMENT_TIMEOUT_SECS`].
+    pub async fn pay_invoice_with_timeout(
+        &self,
+        invoice: &Invoice,
+        amt_sats: Option<u64>,
+        policy: Option<PaymentPolicy>,
+        timeout_secs: Option<u64>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        // Events are handled on the same thread, so nothing can resolve
+        // the payment before we start waiting on it.
+        self.pay_invoice(invoice.clone(), amt_sats, policy)?;
+        let payment_hash = PaymentHash(invoice.payment_hash().into_inner());
+        self.await_payment(payment_hash, timeout_secs).await?;
+
+        self.get_invoice(invoice.clone())
+    }
+
+    /// Keysends to the node and waits for the payment to succeed or fail,
+    /// for at most `timeout_secs` or [`DEFAULT_PAYMENT_TIMEOUT_SECS`].
+    pub async fn keysend_with_timeout(
+        &self,
+        to_node: PublicKey,
+        amt_sats: u64,
+        policy: Option<PaymentPolicy>,
+        timeout_secs: Option<u64>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        let mut mutiny_invoice = self.keysend(to_node, amt_sats, policy)?;
+        let payment_hash = <[u8; 32]>::from_hex(&mutiny_invoice.payment_hash())
+            .map(PaymentHash)
+            .map_err(|_| MutinyError::InvoiceInvalid)?;
+        self.await_payment(payment_hash, timeout_secs).await?;
+
+        let payment_info = self
+            .persister
+            .read_payment_info(payment_hash, false, self.logger.clone())
+            .ok_or(MutinyError::InvoiceInvalid)?;
+        mutiny_invoice.paid = true;
+        mutiny_invoice.fees_paid = payment_info.fee_paid_msat.map(|f| f / 1_000);
+        Ok(mutiny_invoice)
+    }
+
+    async fn await_payment(
+        &self,
+        payment_hash: PaymentHash,
+        timeout_secs: Option<u64>,
+    ) -> Result<(), MutinyError> {
+        let timeout_secs = timeout_secs.unwrap_or(DEFAULT_PAYMENT_TIMEOUT_SECS);
+        let result = self.payment_waiters.wait(payment_hash);
+        let timeout = sleep((timeout_secs * 1_000) as i32);
+        pin_mut!(timeout);
+
+        match select(result, timeout).await {
+            Either::Left((Ok(result), _)) => Ok(result?),
+            // the event handler was dropped without resolving the payment
+            Either::Left((Err(_), _)) => Err(MutinyError::NotRunning),
+            Either::Right(_) => Err(MutinyError::PaymentTimeout),
+        }
+    }
+
     pub async fn open_channel(
         &self,
         pubkey: PublicKey,
         amount_sat: u64,
     ) -> Result<[u8; 32], MutinyError> {
-        let config = default_user_config();
+        let config = outbound_channel_config(&self.channel_acceptor.policy(), &pubkey);
         match self
             .channel_manager
             .create_channel(pubkey, amount_sat, 0, 0, Some(config))
@@ -830,6 +1083,39 @@ impl Node {
             }
         }
     }
+
+    /// The channels of this node, including the ones being opened and the closed ones that
+    /// still have funds to claim on-chain.
+    pub fn list_channels(&self) -> Vec<MutinyChannel> {
+        let channels = self.channel_manager.list_channels();
+        let open: HashSet<OutPoint> = channels.iter().filter_map(|c| c.funding_txo).collect();
+
+        // the monitors of closed channels are kept until we remove them
+        let closing = self
+            .chain_monitor
+            .list_monitors()
+            .into_iter()
+            .filter(|outpoint| !open.contains(outpoint))
+            .filter_map(|outpoint| {
+                let monitor = self.chain_monitor.get_monitor(outpoint).ok()?;
+                let balances = monitor.get_claimable_balances();
+                if balances.is_empty() {
+                    return None;
+                }
+
+                Some(MutinyChannel::closing(
+                    outpoint.into_bitcoin_outpoint(),
+                    monitor.get_counterparty_node_id().map(|p| p.to_hex()),
+                    balances.iter().map(claimable_amount_sats).sum(),
+                ))
+            });
+
+        channels
+            .iter()
+            .map(MutinyChannel::from)
+            .chain(closing)
+            .collect()
+    }
 }
 
 pub(crate) async fn connect_peer_if_necessary(
@@ -912,6 +1198,84 @@ fn try_get_net_addr_from_socket(socket_addr: &str) -> Option<NetAddress> {
         })
 }
 
+fn claimable_amount_sats(balance: &Balance) -> u64 {
+    match balance {
+        Balance::ClaimableOnChannelClose {
+            claimable_amount_satoshis,
+        }
+        | Balance::ClaimableAwaitingConfirmations {
+            claimable_amount_satoshis,
+            ..
+        }
+        | Balance::ContentiousClaimable {
+            claimable_amount_satoshis,
+            ..
+        }
+        | Balance::MaybeTimeoutClaimableHTLC {
+            claimable_amount_satoshis,
+            ..
+        }
+        | Balance::MaybePreimageClaimableHTLC {
+            claimable_amount_satoshis,
+            ..
+        }
+        | Balance::CounterpartyRevokedOutputClaimable {
+            claimable_amount_satoshis,
+        } => *claimable_amount_satoshis,
+    }
+}
+
+// A closed anchor channel may need its reserve to fee bump the commitment transaction and the
+// HTLC claims, so the reserve is kept until the monitor has nothing left to claim
+async fn release_anchor_reserves(
+    channel_manager: &PhantomChannelManager,
+    chain_monitor: &ChainMonitor,
+    wallet: &MutinyWallet,
+) {
+    let open: HashSet<[u8; 32]> = channel_manager
+        .list_channels()
+        .iter()
+        .map(|c| c.channel_id)
+        .collect();
+
+    for outpoint in chain_monitor.list_monitors() {
+        let channel_id = outpoint.to_channel_id();
+        if open.contains(&channel_id) {
+            continue;
+        }
+        let resolved = chain_monitor
+            .get_monitor(outpoint)
+            .map_or(false, |m| m.get_claimable_balances().is_empty());
+        if !resolved {
+            continue;
+        }
+
+        if let Err(e) = wallet.remove_anchor_channel(&channel_id).await {
+            error!(
+                "could not release the reserve of channel {}: {e}",
+                channel_id.to_hex()
+            );
+        }
+    }
+}
+
+// Marks the unpaid invoices that expired, they are only hidden from the listings otherwise
+fn expire_invoices(persister: &MutinyNodePersister) {
+    let now = crate::utils::now();
+    for (payment_hash, mut payment_info) in persister.list_inbound_payments() {
+        if !matches!(payment_info.status, HTLCStatus::Pending) || !payment_info.is_expired(now) {
+            continue;
+        }
+
+        debug!("invoice {} expired", payment_hash.0.to_hex());
+        payment_info.status = HTLCStatus::Expired;
+        payment_info.last_update = now.as_secs();
+        if let Err(e) = persister.persist_payment_info(payment_hash, payment_info, true) {
+            error!("could not persist expired invoice: {e}");
+        }
+    }
+}
+
 pub(crate) fn create_peer_manager(
     km: Arc<PhantomKeysManager>,
     lightning_msg_handler: MessageHandler,
@@ -970,24 +1334,42 @@ pub(crate) fn default_user_config() -> UserConfig {
         channel_handshake_limits: ChannelHandshakeLimits {
             // lnd's max to_self_delay is 2016, so we want to be compatible.
             their_to_self_delay: 2016,
+            // zero-conf is only allowed with the peers we trust, see ChannelAcceptancePolicy
+            trust_own_funding_0conf: false,
             ..Default::default()
         },
         channel_handshake_config: ChannelHandshakeConfig {
             minimum_depth: 1,
             announced_channel: false,
             negotiate_scid_privacy: true,
+            #[cfg(anchors)]
+            negotiate_anchors_zero_fee_htlc_tx: true,
             ..Default::default()
         },
+        // inbound channels are decided on by the node's ChannelAcceptor
+        manually_accept_inbound_channels: true,
         ..Default::default()
     }
 }
 
+/// The config of a channel we open to the peer.
+pub(crate) fn outbound_channel_config(
+    policy: &ChannelAcceptancePolicy,
+    pubkey: &PublicKey,
+) -> UserConfig {
+    let mut config = default_user_config();
+    // a trusted peer may let us use the channel before our funding transaction confirms
+    config.channel_handshake_limits.trust_own_funding_0conf = policy.is_zero_conf_peer(pubkey);
+    config
+}
+
 #[cfg(test)]
 mod tests {
     use crate::test::*;
     use std::str::FromStr;
 
-    use crate::node::parse_peer_info;
+    use crate::channelpolicy::ChannelAcceptancePolicy;
+    use crate::node::{default_user_config, outbound_channel_config, parse_peer_info};
 
     use secp256k1::PublicKey;
     use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
@@ -1026,4 +1408,44 @@ mod tests {
         assert_eq!(pub_key, peer_pubkey);
         assert_eq!(format!("{addr}:{port}"), peer_addr);
     }
+
+    #[test]
+    fn test_outbound_zero_conf_trust() {
+        log!("outbound zero-conf trust");
+
+        let trusted = PublicKey::from_str(
+            "0218845781f631c48f1c9709e23092067d06837f30aa0cd0544ac887fe91ddd166",
+        )
+        .unwrap();
+        let untrusted = PublicKey::from_str(
+            "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54",
+        )
+        .unwrap();
+        let policy = ChannelAcceptancePolicy {
+            zero_conf_peers: vec![trusted.to_string()],
+            ..Default::default()
+        };
+
+        // no peer is trusted with our unconfirmed funding unless it is listed
+        assert!(
+            !default_user_config()
+                .channel_handshake_limits
+                .trust_own_funding_0conf
+        );
+        assert!(
+            outbound_channel_config(&policy, &trusted)
+                .channel_handshake_limits
+                .trust_own_funding_0conf
+        );
+        assert!(
+            !outbound_channel_config(&policy, &untrusted)
+                .channel_handshake_limits
+                .trust_own_funding_0conf
+        );
+        assert!(
+            !outbound_channel_config(&ChannelAcceptancePolicy::default(), &trusted)
+                .channel_handshake_limits
+                .trust_own_funding_0conf
+        );
+    }
 }
@@ -6,11 +6,27 @@ use std::ops::Deref;
 use std::{str::FromStr, sync::Arc};
 
 use crate::chain::MutinyChain;
+use crate::channelpolicy::{ChannelAcceptancePolicy, MutinyChannelRequest};
 use crate::error::{MutinyError, MutinyJsError, MutinyStorageError};
+use crate::event::PaymentAttempt;
+use crate::eventbus::EventBus;
+use crate::gossip::{
+    self, Gossip, GossipSettings, LimitedGossipHandler, P2PGossipSync, RapidGossipSync,
+    DEFAULT_MAX_FULL_SYNC_PEERS,
+};
+use crate::holdinvoice::HoldInvoiceParams;
 use crate::keymanager;
-use crate::node::{Node, PubkeyConnectionInfo};
-use crate::utils::currency_from_network;
-use crate::wallet::esplora_from_network;
+use crate::lnurl::{
+    fetch_invoice, fetch_pay_request, fetch_withdraw_request, lnurl_auth, submit_withdraw_invoice,
+    HttpLnUrlClient,
+};
+use crate::logging::MutinyLogger;
+use crate::node::{NetworkGraph, Node, PubkeyConnectionInfo};
+use crate::paymentpolicy::{cheapest_estimate, PaymentPolicy};
+use crate::utils::{currency_from_network, sleep};
+use crate::wallet::{
+    esplora_from_network, parse_watch_only_descriptors, DescriptorType, DEFAULT_GAP_LIMIT,
+};
 use crate::{localstorage::MutinyBrowserStorage, utils::set_panic_hook, wallet::MutinyWallet};
 use bdk::wallet::AddressIndex;
 use bip39::Mnemonic;
@@ -18,16 +34,19 @@ use bitcoin::consensus::deserialize;
 use bitcoin::hashes::hex::{FromHex, ToHex};
 use bitcoin::{Address, Network, OutPoint, PublicKey, Transaction, Txid};
 use futures::lock::Mutex;
+use futures::StreamExt;
 use lightning::chain::chaininterface::{BroadcasterInterface, ConfirmationTarget, FeeEstimator};
 use lightning::chain::keysinterface::{KeysInterface, Recipient};
 use lightning::chain::Confirm;
 use lightning::ln::channelmanager::{ChannelDetails, PhantomRouteHints};
+use lightning::ln::{PaymentHash, PaymentPreimage};
 use lightning_invoice::{Invoice, InvoiceDescription};
 use log::{debug, error, info};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::spawn_local;
 
 #[wasm_bindgen]
 pub struct NodeManager {
@@ -40,6 +59,12 @@ pub struct NodeManager {
     storage: MutinyBrowserStorage,
     node_storage: Mutex<NodeStorage>,
     nodes: Arc<Mutex<HashMap<String, Arc<Node>>>>,
+    sync_progress: Arc<Mutex<MutinySyncProgress>>,
+    network_graph: Arc<NetworkGraph>,
+    gossip: Gossip,
+    event_bus: Arc<EventBus>,
+    accounts: Arc<Mutex<HashMap<u32, Arc<MutinyWallet>>>>,
+    watch_only_wallets: Arc<Mutex<HashMap<String, Arc<MutinyWallet>>>>,
 }
 
 // This is the NodeStorage object saved to the DB
@@ -55,6 +80,52 @@ pub(crate) struct NodeIndex {
     pub child_index: u32,
 }
 
+// This is the AccountStorage object saved to the DB,
+// the default account 0 is not stored here
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct AccountStorage {
+    pub accounts: HashMap<u32, AccountIndex>,
+}
+
+// This is the AccountIndex reference that is saved to the DB
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct AccountIndex {
+    pub account: u32,
+    pub label: Option<String>,
+    pub descriptor_type: DescriptorType,
+    // The account used in the derivation path
+    pub account_number: u32,
+}
+
+impl AccountIndex {
+    // Each additional account keeps its bdk data under its own prefix
+    fn storage_prefix(&self) -> String {
+        format!("account/{}/", self.account)
+    }
+}
+
+// This is the WatchOnlyStorage object saved to the DB
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct WatchOnlyStorage {
+    pub wallets: HashMap<String, WatchOnlyIndex>,
+}
+
+// This is the WatchOnlyIndex reference that is saved to the DB
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct WatchOnlyIndex {
+    pub uuid: String,
+    pub label: Option<String>,
+    pub descriptor: String,
+    pub change_descriptor: Option<String>,
+}
+
+impl WatchOnlyIndex {
+    // Each watch only wallet keeps its bdk data under its own prefix
+    fn storage_prefix(&self) -> String {
+        format!("watch_only/{}/", self.uuid)
+    }
+}
+
 // This is the NodeIdentity that refer to a specific node
 // Used for public facing identification.
 #[wasm_bindgen]
@@ -82,13 +153,16 @@ pub struct MutinyInvoice {
     bolt11: Option<String>,
     description: Option<String>,
     payment_hash: String,
-    preimage: Option<String>,
+    pub(crate) preimage: Option<String>,
     payee_pubkey: Option<String>,
     pub amount_sats: Option<u64>,
     pub expire: u64,
     pub paid: bool,
+    /// A payment to our hold invoice arrived and waits to be settled or cancelled
+    pub held: bool,
     pub fees_paid: Option<u64>,
     pub is_send: bool,
+    pub(crate) attempts: Vec<PaymentAttempt>,
 }
 
 pub(crate) struct MutinyInvoiceParams {
@@ -100,6 +174,7 @@ pub(crate) struct MutinyInvoiceParams {
     pub amount_sats: Option<u64>,
     pub expire: u64,
     pub paid: bool,
+    pub held: bool,
     pub fees_paid: Option<u64>,
     pub is_send: bool,
 }
@@ -147,8 +222,10 @@ impl MutinyInvoice {
             amount_sats: p.amount_sats,
             expire: p.expire,
             paid: p.paid,
+            held: p.held,
             fees_paid: p.fees_paid,
             is_send: p.is_send,
+            attempts: vec![],
         }
     }
 }
@@ -179,6 +256,12 @@ impl MutinyInvoice {
     pub fn payee_pubkey(&self) -> Option<String> {
         self.payee_pubkey.clone()
     }
+
+    /// The paths tried for an outbound payment and why they failed
+    #[wasm_bindgen(getter)]
+    pub fn attempts(&self) -> Result<JsValue /* Vec<PaymentAttempt> */, MutinyJsError> {
+        Ok(serde_wasm_bindgen::to_value(&self.attempts)?)
+    }
 }
 
 impl From<Invoice> for MutinyInvoice {
@@ -200,8 +283,96 @@ impl From<Invoice> for MutinyInvoice {
             amount_sats: value.amount_milli_satoshis().map(|m| m / 1000),
             expire: expiry,
             paid: false,
+            held: false,
             fees_paid: None,
             is_send: false, // todo this could be bad
+            attempts: vec![],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[wasm_bindgen]
+pub struct MutinyAccount {
+    account: u32,
+    label: Option<String>,
+    descriptor_type: DescriptorType,
+    account_number: u32,
+}
+
+#[wasm_bindgen]
+impl MutinyAccount {
+    #[wasm_bindgen(getter)]
+    pub fn account(&self) -> u32 {
+        self.account
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn label(&self) -> Option<String> {
+        self.label.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn descriptor_type(&self) -> DescriptorType {
+        self.descriptor_type
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn account_number(&self) -> u32 {
+        self.account_number
+    }
+}
+
+impl From<&AccountIndex> for MutinyAccount {
+    fn from(a: &AccountIndex) -> Self {
+        MutinyAccount {
+            account: a.account,
+            label: a.label.clone(),
+            descriptor_type: a.descriptor_type,
+            account_number: a.account_number,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[wasm_bindgen]
+pub struct MutinyWatchOnlyWallet {
+    id: String,
+    label: Option<String>,
+    descriptor: String,
+    change_descriptor: Option<String>,
+}
+
+#[wasm_bindgen]
+impl MutinyWatchOnlyWallet {
+    #[wasm_bindgen(getter)]
+    pub fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn label(&self) -> Option<String> {
+        self.label.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn descriptor(&self) -> String {
+        self.descriptor.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn change_descriptor(&self) -> Option<String> {
+        self.change_descriptor.clone()
+    }
+}
+
+impl From<&WatchOnlyIndex> for MutinyWatchOnlyWallet {
+    fn from(w: &WatchOnlyIndex) -> Self {
+        MutinyWatchOnlyWallet {
+            id: w.uuid.clone(),
+            label: w.label.clone(),
+            descriptor: w.descriptor.clone(),
+            change_descriptor: w.change_descriptor.clone(),
         }
     }
 }
@@ -242,15 +413,47 @@ impl Ord for MutinyPeer {
     }
 }
 
+/// Where a channel is in its lifecycle.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChannelState {
+    /// Waiting for the funding transaction to be created or to confirm
+    Opening,
+    /// Confirmed and ready, though the peer may be offline
+    Ready,
+    /// Closed, with funds still to be claimed on-chain
+    Closing,
+}
+
+/// The fees and cltv delta the peer charges to forward payments to us over the channel.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ChannelForwardingInfo {
+    pub fee_base_msat: u32,
+    pub fee_proportional_millionths: u32,
+    pub cltv_expiry_delta: u16,
+}
+
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
 #[wasm_bindgen]
 pub struct MutinyChannel {
+    /// What we can send, or for closing channels what is left to claim on-chain
     pub balance: u64,
+    /// What we can receive
+    pub inbound: u64,
+    /// Unknown for closing channels, 0
     pub size: u64,
     pub reserve: u64,
     outpoint: Option<String>,
     peer: String,
+    pub short_channel_id: Option<u64>,
+    /// The alias our invoices' route hints use, known before the channel confirmed
+    pub scid_alias: Option<u64>,
     pub confirmed: bool,
+    pub confirmations: u32,
+    pub confirmations_required: Option<u32>,
+    pub is_usable: bool,
+    pub is_public: bool,
+    state: ChannelState,
+    forwarding_info: Option<ChannelForwardingInfo>,
 }
 
 #[wasm_bindgen]
@@ -264,17 +467,74 @@ impl MutinyChannel {
     pub fn peer(&self) -> String {
         self.peer.clone()
     }
+
+    /// `Opening`, `Ready` or `Closing`
+    #[wasm_bindgen(getter)]
+    pub fn state(&self) -> String {
+        format!("{:?}", self.state)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn forwarding_info(
+        &self,
+    ) -> Result<JsValue /* Option<ChannelForwardingInfo> */, MutinyJsError> {
+        Ok(serde_wasm_bindgen::to_value(&self.forwarding_info)?)
+    }
+}
+
+impl MutinyChannel {
+    /// A closed channel that still has funds to claim, all we know is in its monitor.
+    pub(crate) fn closing(outpoint: OutPoint, peer: Option<String>, claimable_sats: u64) -> Self {
+        MutinyChannel {
+            balance: claimable_sats,
+            inbound: 0,
+            size: 0,
+            reserve: 0,
+            outpoint: Some(outpoint.to_string()),
+            peer: peer.unwrap_or_default(),
+            short_channel_id: None,
+            scid_alias: None,
+            confirmed: true,
+            confirmations: 0,
+            confirmations_required: None,
+            is_usable: false,
+            is_public: false,
+            state: ChannelState::Closing,
+            forwarding_info: None,
+        }
+    }
 }
 
 impl From<&ChannelDetails> for MutinyChannel {
     fn from(c: &ChannelDetails) -> Self {
+        let state = if c.is_channel_ready {
+            ChannelState::Ready
+        } else {
+            ChannelState::Opening
+        };
         MutinyChannel {
             balance: c.outbound_capacity_msat / 1_000,
+            inbound: c.inbound_capacity_msat / 1_000,
             size: c.channel_value_satoshis,
             reserve: c.unspendable_punishment_reserve.unwrap_or(0),
             outpoint: c.funding_txo.map(|f| f.into_bitcoin_outpoint().to_string()),
             peer: c.counterparty.node_id.to_hex(),
-            confirmed: c.is_channel_ready, // fixme not exactly correct
+            short_channel_id: c.short_channel_id,
+            scid_alias: c.inbound_scid_alias,
+            // both sides saw the funding transaction reach the required depth
+            confirmed: c.is_channel_ready,
+            confirmations: c.confirmations.unwrap_or(0),
+            confirmations_required: c.confirmations_required,
+            is_usable: c.is_usable,
+            is_public: c.is_public,
+            state,
+            forwarding_info: c.counterparty.forwarding_info.as_ref().map(|f| {
+                ChannelForwardingInfo {
+                    fee_base_msat: f.fee_base_msat,
+                    fee_proportional_millionths: f.fee_proportional_millionths,
+                    cltv_expiry_delta: f.cltv_expiry_delta,
+                }
+            }),
         }
     }
 }
@@ -284,6 +544,23 @@ pub struct MutinyBalance {
     pub confirmed: u64,
     pub unconfirmed: u64,
     pub lightning: u64,
+    /// Part of the confirmed balance kept aside for fee bumping anchor channels
+    pub reserved: u64,
+}
+
+/// The progress of a sync, counted in whole wallets.
+///
+/// bdk 0.25 does not report progress while syncing a wallet through esplora, so there is
+/// nothing finer grained than the number of wallets done.
+#[derive(Clone, Copy, Default)]
+#[wasm_bindgen]
+pub struct MutinySyncProgress {
+    pub syncing: bool,
+    pub lightning_synced: bool,
+    pub wallets_synced: u32,
+    pub wallets_total: u32,
+    pub tip_height: Option<u32>,
+    pub birthday: Option<u32>,
 }
 
 #[wasm_bindgen]
@@ -293,13 +570,25 @@ impl NodeManager {
         MutinyBrowserStorage::has_mnemonic()
     }
 
+    /// Creates the node manager, restoring from `mnemonic` if one is given.
+    ///
+    /// The `birthday` of a restored mnemonic is stored and reported back, but it does not
+    /// shorten the on-chain scan: esplora's address history endpoints have no height filter,
+    /// so bdk 0.25 always fetches the whole history of every address within the gap limit.
+    ///
+    /// The network graph is synced from `rgs_url`, or the default rapid gossip sync server of
+    /// the network when none is given. An empty `rgs_url` doesn't sync it at all.
     #[wasm_bindgen(constructor)]
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         password: String,
         mnemonic: Option<String>,
         websocket_proxy_addr: Option<String>,
         network_str: Option<String>,
         user_esplora_url: Option<String>,
+        birthday: Option<u32>,
+        gap_limit: Option<u32>,
+        rgs_url: Option<String>,
     ) -> Result<NodeManager, MutinyJsError> {
         set_panic_hook();
 
@@ -312,6 +601,10 @@ impl NodeManager {
             .expect("invalid network");
 
         let storage = MutinyBrowserStorage::new(password);
+        let mut settings = storage
+            .get_wallet_settings()
+            .map_err(MutinyError::read_err)?;
+        let mut new_seed = false;
 
         let mnemonic = match mnemonic {
             Some(m) => {
@@ -323,18 +616,37 @@ impl NodeManager {
                         return Err(MutinyError::InvalidMnemonic.into());
                     }
                 };
+                // a restored mnemonic has no history before its birthday
+                settings.birthday = birthday;
                 storage.insert_mnemonic(seed)
             }
             None => match storage.get_mnemonic() {
                 Ok(mnemonic) => mnemonic,
                 Err(_) => {
+                    new_seed = true;
                     let seed = keymanager::generate_seed(12)?;
                     storage.insert_mnemonic(seed)
                 }
             },
         };
 
-        let esplora = Arc::new(esplora_from_network(network, user_esplora_url));
+        if let Some(gap_limit) = gap_limit {
+            settings.gap_limit = Some(gap_limit as usize);
+        }
+
+        let esplora = Arc::new(esplora_from_network(
+            network,
+            user_esplora_url,
+            settings.gap_limit.unwrap_or(DEFAULT_GAP_LIMIT),
+        ));
+
+        // a new seed has no history before the current tip
+        if new_seed {
+            settings.birthday = esplora.get_height().await.ok();
+        }
+        storage
+            .insert_wallet_settings(settings.clone())
+            .map_err(MutinyError::from)?;
 
         let wallet = Arc::new(MutinyWallet::new(
             mnemonic.clone(),
@@ -355,6 +667,53 @@ impl NodeManager {
             }
         };
 
+        // all of the nodes share one network graph that is kept up to date with
+        // either rapid gossip sync or gossip from our peers
+        gossip::migrate_node_network_graphs(&storage)?;
+        let logger = Arc::new(MutinyLogger::default());
+        let network_graph = Arc::new(gossip::read_network_graph(
+            &storage,
+            network,
+            logger.clone(),
+        ));
+        let gossip_settings = gossip::get_gossip_settings(&storage)?;
+
+        let gossip = if gossip_settings.p2p_gossip {
+            let gossip_sync = Arc::new(P2PGossipSync::new(network_graph.clone(), None, logger));
+            let handler = Arc::new(LimitedGossipHandler::new(
+                gossip_sync.clone(),
+                network,
+                gossip_settings
+                    .max_full_sync_peers
+                    .unwrap_or(DEFAULT_MAX_FULL_SYNC_PEERS),
+            ));
+            Gossip::P2P(gossip_sync, handler)
+        } else {
+            Gossip::Rapid(Arc::new(RapidGossipSync::new(network_graph.clone())))
+        };
+
+        let rgs_url = match rgs_url {
+            // an empty url turns rapid gossip sync off
+            Some(rgs_url) if rgs_url.is_empty() => None,
+            Some(rgs_url) => Some(rgs_url),
+            None => gossip::default_rgs_url(network),
+        };
+        if let (Gossip::Rapid(gossip_sync), Some(rgs_url)) = (&gossip, rgs_url) {
+            let rgs_storage = storage.clone();
+            let rgs_gossip_sync = gossip_sync.clone();
+            spawn_local(async move {
+                loop {
+                    if let Err(e) =
+                        gossip::sync_rapid_gossip(&rgs_storage, &rgs_gossip_sync, &rgs_url).await
+                    {
+                        error!("Failed to sync rapid gossip: {e}");
+                    }
+                    sleep((gossip::RGS_SYNC_INTERVAL * 1000) as i32).await;
+                }
+            });
+        }
+
+        let event_bus = Arc::new(EventBus::default());
         let mut nodes_map = HashMap::new();
 
         for node_item in node_storage.clone().nodes {
@@ -367,6 +726,9 @@ impl NodeManager {
                 network,
                 websocket_proxy_addr.clone(),
                 esplora.clone(),
+                network_graph.clone(),
+                gossip.clone(),
+                event_bus.clone(),
             )
             .await?;
 
@@ -378,6 +740,35 @@ impl NodeManager {
             nodes_map.insert(id.to_hex(), Arc::new(node));
         }
 
+        let account_storage = storage.get_accounts().map_err(MutinyError::read_err)?;
+        let mut accounts_map = HashMap::new();
+        for (account, index) in account_storage.accounts {
+            let wallet = MutinyWallet::new_account(
+                mnemonic.clone(),
+                storage.with_wallet_prefix(index.storage_prefix()),
+                network,
+                esplora.clone(),
+                index.descriptor_type,
+                index.account_number,
+            );
+            accounts_map.insert(account, Arc::new(wallet));
+        }
+
+        let mut watch_only_map = HashMap::new();
+        let watch_only_storage = storage
+            .get_watch_only_wallets()
+            .map_err(MutinyError::read_err)?;
+        for (uuid, index) in watch_only_storage.wallets {
+            let wallet = MutinyWallet::new_watch_only(
+                &index.descriptor,
+                index.change_descriptor.as_deref(),
+                storage.with_wallet_prefix(index.storage_prefix()),
+                network,
+                esplora.clone(),
+            )?;
+            watch_only_map.insert(uuid, Arc::new(wallet));
+        }
+
         Ok(NodeManager {
             mnemonic,
             network,
@@ -386,6 +777,15 @@ impl NodeManager {
             storage,
             node_storage: Mutex::new(node_storage),
             nodes: Arc::new(Mutex::new(nodes_map)),
+            network_graph,
+            gossip,
+            event_bus,
+            sync_progress: Arc::new(Mutex::new(MutinySyncProgress {
+                birthday: settings.birthday,
+                ..Default::default()
+            })),
+            accounts: Arc::new(Mutex::new(accounts_map)),
+            watch_only_wallets: Arc::new(Mutex::new(watch_only_map)),
             websocket_proxy_addr,
             esplora,
         })
@@ -406,6 +806,34 @@ impl NodeManager {
         Ok(())
     }
 
+    /// Calls `callback` with every event from our nodes: payments received, sent or failed,
+    /// channels ready or closed and swept channel outputs. Each event is an object with a
+    /// `type` field naming it. Returns an id that can be passed to `unsubscribe`.
+    #[wasm_bindgen]
+    pub fn subscribe(&self, callback: js_sys::Function) -> u32 {
+        let (id, mut events) = self.event_bus.subscribe();
+        spawn_local(async move {
+            while let Some(event) = events.next().await {
+                let value = match serde_wasm_bindgen::to_value(&event) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        error!("could not serialize event {event:?}: {e}");
+                        continue;
+                    }
+                };
+                if let Err(e) = callback.call1(&JsValue::NULL, &value) {
+                    error!("event subscriber threw an error: {e:?}");
+                }
+            }
+        });
+        id
+    }
+
+    #[wasm_bindgen]
+    pub fn unsubscribe(&self, id: u32) {
+        self.event_bus.unsubscribe(id);
+    }
+
     #[wasm_bindgen]
     pub fn show_seed(&self) -> String {
         self.mnemonic.to_string()
@@ -416,10 +844,12 @@ impl NodeManager {
         self.network.to_string()
     }
 
+    /// Gets a new address from the given account, or the default account if none is given.
     #[wasm_bindgen]
-    pub async fn get_new_address(&self) -> Result<String, MutinyJsError> {
+    pub async fn get_new_address(&self, account: Option<u32>) -> Result<String, MutinyJsError> {
         match self
-            .wallet
+            .get_account_wallet(account)
+            .await?
             .wallet
             .lock()
             .await
@@ -431,8 +861,15 @@ impl NodeManager {
     }
 
     #[wasm_bindgen]
-    pub async fn get_wallet_balance(&self) -> Result<u64, MutinyJsError> {
-        match self.wallet.wallet.lock().await.get_balance() {
+    pub async fn get_wallet_balance(&self, account: Option<u32>) -> Result<u64, MutinyJsError> {
+        match self
+            .get_account_wallet(account)
+            .await?
+            .wallet
+            .lock()
+            .await
+            .get_balance()
+        {
             Ok(balance) => Ok(balance.get_total()),
             Err(_) => Err(MutinyJsError::WalletOperationFailed),
         }
@@ -444,12 +881,12 @@ impl NodeManager {
         amount: Option<u64>,
         description: Option<String>,
     ) -> Result<MutinyBip21RawMaterials, MutinyJsError> {
-        let Ok(address) = self.get_new_address().await else {
+        let Ok(address) = self.get_new_address(None).await else {
             return Err(MutinyError::WalletOperationFailed.into());
         };
 
         // TODO if there's no description should be something random I guess
-        let Ok(invoice) = self.create_invoice(amount, description.clone().unwrap_or_else(|| "".into())).await else {
+        let Ok(invoice) = self.create_invoice(amount, description.clone().unwrap_or_else(|| "".into()), None).await else {
             return Err(MutinyError::WalletOperationFailed.into())
         };
 
@@ -471,6 +908,7 @@ impl NodeManager {
         destination_address: String,
         amount: u64,
         fee_rate: Option<f32>,
+        account: Option<u32>,
     ) -> Result<String, MutinyJsError> {
         let send_to = Address::from_str(&destination_address)?;
 
@@ -478,12 +916,154 @@ impl NodeManager {
             return Err(MutinyJsError::IncorrectNetwork);
         }
 
-        match self.wallet.send(send_to, amount, fee_rate).await {
+        let wallet = self.get_account_wallet(account).await?;
+        match wallet.send(send_to, amount, fee_rate).await {
             Ok(txid) => Ok(txid.to_owned().to_string()),
             Err(e) => Err(e.into()),
         }
     }
 
+    /// Sends to many addresses in a single transaction. `recipients` is a list of
+    /// `{ address, amount }` objects. If `drain_to` is given, the remaining balance
+    /// that isn't frozen is sent to that address.
+    #[wasm_bindgen]
+    pub async fn send_batch(
+        &self,
+        recipients: JsValue, /* Vec<BatchRecipient> */
+        drain_to: Option<String>,
+        fee_rate: Option<f32>,
+        account: Option<u32>,
+    ) -> Result<String, MutinyJsError> {
+        let (send_to, drain_to) = self.parse_batch(recipients, drain_to)?;
+
+        let wallet = self.get_account_wallet(account).await?;
+        let txid = wallet.send_batch(send_to, drain_to, fee_rate).await?;
+
+        Ok(txid.to_string())
+    }
+
+    fn parse_batch(
+        &self,
+        recipients: JsValue, /* Vec<BatchRecipient> */
+        drain_to: Option<String>,
+    ) -> Result<(Vec<(Address, u64)>, Option<Address>), MutinyJsError> {
+        let recipients: Vec<BatchRecipient> = serde_wasm_bindgen::from_value(recipients)?;
+
+        let mut send_to = Vec::with_capacity(recipients.len());
+        for recipient in recipients {
+            let address = Address::from_str(&recipient.address)?;
+            if address.network != self.network {
+                return Err(MutinyJsError::IncorrectNetwork);
+            }
+            send_to.push((address, recipient.amount));
+        }
+
+        let drain_to = match drain_to {
+            Some(drain_to) => {
+                let address = Address::from_str(&drain_to)?;
+                if address.network != self.network {
+                    return Err(MutinyJsError::IncorrectNetwork);
+                }
+                Some(address)
+            }
+            None => None,
+        };
+
+        if send_to.is_empty() && drain_to.is_none() {
+            return Err(MutinyJsError::InvalidArgumentsError);
+        }
+
+        Ok((send_to, drain_to))
+    }
+
+    /// Creates and signs a transaction without broadcasting it so the fee, inputs
+    /// and change can be shown first. Broadcast it with `broadcast_preview`.
+    #[wasm_bindgen]
+    pub async fn preview_send(
+        &self,
+        destination_address: String,
+        amount: u64,
+        fee_rate: Option<f32>,
+        account: Option<u32>,
+    ) -> Result<JsValue /* TransactionPreview */, MutinyJsError> {
+        let send_to = Address::from_str(&destination_address)?;
+
+        if send_to.network != self.network {
+            return Err(MutinyJsError::IncorrectNetwork);
+        }
+
+        let wallet = self.get_account_wallet(account).await?;
+        let preview = wallet
+            .preview_batch(vec![(send_to, amount)], None, fee_rate)
+            .await?;
+
+        Ok(serde_wasm_bindgen::to_value(&preview)?)
+    }
+
+    #[wasm_bindgen]
+    pub async fn preview_batch(
+        &self,
+        recipients: JsValue, /* Vec<BatchRecipient> */
+        drain_to: Option<String>,
+        fee_rate: Option<f32>,
+        account: Option<u32>,
+    ) -> Result<JsValue /* TransactionPreview */, MutinyJsError> {
+        let (send_to, drain_to) = self.parse_batch(recipients, drain_to)?;
+
+        let wallet = self.get_account_wallet(account).await?;
+        let preview = wallet.preview_batch(send_to, drain_to, fee_rate).await?;
+
+        Ok(serde_wasm_bindgen::to_value(&preview)?)
+    }
+
+    #[wasm_bindgen]
+    pub async fn preview_sweep(
+        &self,
+        destination_address: String,
+        fee_rate: Option<f32>,
+        account: Option<u32>,
+    ) -> Result<JsValue /* TransactionPreview */, MutinyJsError> {
+        let send_to = Address::from_str(&destination_address)?;
+
+        if send_to.network != self.network {
+            return Err(MutinyJsError::IncorrectNetwork);
+        }
+
+        let wallet = self.get_account_wallet(account).await?;
+        let preview = wallet
+            .preview_batch(vec![], Some(send_to), fee_rate)
+            .await?;
+
+        Ok(serde_wasm_bindgen::to_value(&preview)?)
+    }
+
+    /// Broadcasts the exact transaction returned by one of the preview calls.
+    #[wasm_bindgen]
+    pub async fn broadcast_preview(
+        &self,
+        txid: String,
+        account: Option<u32>,
+    ) -> Result<String, MutinyJsError> {
+        let txid = Txid::from_str(&txid)?;
+        let wallet = self.get_account_wallet(account).await?;
+        let txid = wallet.broadcast_preview(txid).await?;
+
+        Ok(txid.to_string())
+    }
+
+    #[wasm_bindgen]
+    pub async fn discard_preview(
+        &self,
+        txid: String,
+        account: Option<u32>,
+    ) -> Result<(), MutinyJsError> {
+        let txid = Txid::from_str(&txid)?;
+        let wallet = self.get_account_wallet(account).await?;
+        wallet.discard_preview(txid).await?;
+
+        Ok(())
+    }
+
     #[wasm_bindgen]
     pub async fn sweep_wallet(
         &self,
@@ -545,16 +1125,7 @@ impl NodeManager {
     #[wasm_bindgen]
     pub async fn list_onchain(&self) -> Result<JsValue, MutinyJsError> {
         let mut txs = self.wallet.list_transactions(false).await?;
-
-        // Sort by timestamp, but if there's no timestamp put it first,
-        // if timestamps are equal, compare by txid
-        txs.sort_by(|a, b| {
-            a.confirmation_time
-                .as_ref()
-                .map(|c| c.timestamp)
-                .cmp(&b.confirmation_time.as_ref().map(|c| c.timestamp))
-                .then_with(|| a.txid.cmp(&b.txid))
-        });
+        sort_transactions(&mut txs);
 
         Ok(serde_wasm_bindgen::to_value(&txs)?)
     }
@@ -567,34 +1138,88 @@ impl NodeManager {
         Ok(serde_wasm_bindgen::to_value(&txs)?)
     }
 
+    /// Gets the balance across all on-chain accounts and lightning nodes.
     #[wasm_bindgen]
     pub async fn get_balance(&self) -> Result<MutinyBalance, MutinyJsError> {
-        match self.wallet.wallet.lock().await.get_balance() {
-            Ok(onchain) => {
-                let nodes = self.nodes.lock().await;
-                let lightning_msats: u64 = nodes
-                    .iter()
-                    .flat_map(|(_, n)| n.channel_manager.list_usable_channels())
-                    .map(|c| c.outbound_capacity_msat)
-                    .sum();
-
-                Ok(MutinyBalance {
-                    confirmed: onchain.confirmed + onchain.trusted_pending,
-                    unconfirmed: onchain.untrusted_pending + onchain.immature,
-                    lightning: lightning_msats / 1000,
-                })
+        let mut wallets = vec![self.wallet.clone()];
+        wallets.extend(self.accounts.lock().await.values().cloned());
+
+        let mut confirmed = 0;
+        let mut unconfirmed = 0;
+        for wallet in wallets {
+            match wallet.wallet.lock().await.get_balance() {
+                Ok(onchain) => {
+                    confirmed += onchain.confirmed + onchain.trusted_pending;
+                    unconfirmed += onchain.untrusted_pending + onchain.immature;
+                }
+                Err(_) => return Err(MutinyJsError::WalletOperationFailed),
             }
-            Err(_) => Err(MutinyJsError::WalletOperationFailed),
         }
+
+        let nodes = self.nodes.lock().await;
+        let lightning_msats: u64 = nodes
+            .iter()
+            .flat_map(|(_, n)| n.channel_manager.list_usable_channels())
+            .map(|c| c.outbound_capacity_msat)
+            .sum();
+
+        Ok(MutinyBalance {
+            confirmed,
+            unconfirmed,
+            lightning: lightning_msats / 1000,
+            reserved: self.wallet.anchor_reserve().await?,
+        })
     }
 
     #[wasm_bindgen]
-    pub async fn list_utxos(&self) -> Result<JsValue, MutinyJsError> {
-        let utxos = self.wallet.list_utxos().await?;
+    pub async fn list_utxos(&self, account: Option<u32>) -> Result<JsValue, MutinyJsError> {
+        let utxos = self.get_account_wallet(account).await?.list_utxos().await?;
 
         Ok(serde_wasm_bindgen::to_value(&utxos)?)
     }
 
+    #[wasm_bindgen]
+    pub async fn list_frozen_utxos(
+        &self,
+        account: Option<u32>,
+    ) -> Result<JsValue /* Vec<String> */, MutinyJsError> {
+        let frozen: Vec<String> = self
+            .get_account_wallet(account)
+            .await?
+            .list_frozen_utxos()
+            .await?
+            .iter()
+            .map(|o| o.to_string())
+            .collect();
+
+        Ok(serde_wasm_bindgen::to_value(&frozen)?)
+    }
+
+    /// Freezes a utxo so that it isn't spent by any send, sweep or channel open.
+    #[wasm_bindgen]
+    pub async fn freeze_utxo(
+        &self,
+        outpoint: String,
+        account: Option<u32>,
+    ) -> Result<(), MutinyJsError> {
+        let outpoint =
+            OutPoint::from_str(&outpoint).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        let wallet = self.get_account_wallet(account).await?;
+        Ok(wallet.freeze_utxo(outpoint).await?)
+    }
+
+    #[wasm_bindgen]
+    pub async fn unfreeze_utxo(
+        &self,
+        outpoint: String,
+        account: Option<u32>,
+    ) -> Result<(), MutinyJsError> {
+        let outpoint =
+            OutPoint::from_str(&outpoint).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        let wallet = self.get_account_wallet(account).await?;
+        Ok(wallet.unfreeze_utxo(outpoint).await?)
+    }
+
     async fn sync_ldk(&self) -> Result<(), MutinyError> {
         let nodes = self.nodes.lock().await;
 
@@ -614,41 +1239,343 @@ impl NodeManager {
 
     #[wasm_bindgen]
     pub async fn sync(&self) -> Result<(), MutinyJsError> {
+        let mut wallets = vec![self.wallet.clone()];
+        wallets.extend(self.accounts.lock().await.values().cloned());
+        wallets.extend(self.watch_only_wallets.lock().await.values().cloned());
+
+        let tip_height = self.esplora.get_height().await.ok();
+        {
+            let mut progress = self.sync_progress.lock().await;
+            progress.syncing = true;
+            progress.lightning_synced = false;
+            progress.wallets_synced = 0;
+            progress.wallets_total = wallets.len() as u32;
+            progress.tip_height = tip_height;
+        }
+
+        let res = self.sync_all(wallets).await;
+        self.sync_progress.lock().await.syncing = false;
+        res?;
+
+        info!("We are synced!");
+        Ok(())
+    }
+
+    async fn sync_all(&self, wallets: Vec<Arc<MutinyWallet>>) -> Result<(), MutinyError> {
         // Sync ldk first because it may broadcast transactions
         // to addresses that are in our bdk wallet. This way
         // they are found on this iteration of syncing instead
         // of the next one.
         self.sync_ldk().await?;
+        self.sync_progress.lock().await.lightning_synced = true;
 
-        // sync bdk wallet
-        match self.wallet.sync().await {
-            Ok(()) => Ok(info!("We are synced!")),
-            Err(e) => Err(e.into()),
+        // sync bdk wallets, the main wallet, additional accounts and watch only wallets
+        for wallet in wallets {
+            wallet.sync().await?;
+            self.sync_progress.lock().await.wallets_synced += 1;
         }
+
+        Ok(())
     }
 
+    /// Whether the network graph is synced from our peers instead of a rapid gossip sync server.
     #[wasm_bindgen]
-    pub fn estimate_fee_normal(&self) -> u32 {
-        self.chain
-            .get_est_sat_per_1000_weight(ConfirmationTarget::Normal)
+    pub fn get_p2p_gossip(&self) -> Result<bool, MutinyJsError> {
+        Ok(gossip::get_gossip_settings(&self.storage)?.p2p_gossip)
     }
 
+    /// Sets whether to sync the network graph from our peers, with an optional limit on the
+    /// number of peers the whole graph is downloaded from. The other peers only send recent
+    /// gossip. Takes effect the next time the node manager is started.
     #[wasm_bindgen]
-    pub fn estimate_fee_high(&self) -> u32 {
-        self.chain
-            .get_est_sat_per_1000_weight(ConfirmationTarget::HighPriority)
+    pub fn set_p2p_gossip(
+        &self,
+        enabled: bool,
+        max_full_sync_peers: Option<u32>,
+    ) -> Result<(), MutinyJsError> {
+        let settings = GossipSettings {
+            p2p_gossip: enabled,
+            max_full_sync_peers,
+        };
+        Ok(gossip::insert_gossip_settings(&self.storage, settings)?)
     }
 
+    /// Gets the progress of the current or last sync.
     #[wasm_bindgen]
-    pub async fn new_node(&self) -> Result<NodeIdentity, MutinyJsError> {
-        match create_new_node_from_node_manager(self).await {
-            Ok(node_identity) => Ok(node_identity),
-            Err(e) => Err(e.into()),
-        }
+    pub async fn get_sync_progress(&self) -> MutinySyncProgress {
+        *self.sync_progress.lock().await
     }
 
+    /// The block height the wallet was created or restored from, if known.
     #[wasm_bindgen]
-    pub async fn list_nodes(&self) -> Result<JsValue /* Vec<String> */, MutinyJsError> {
+    pub async fn get_wallet_birthday(&self) -> Option<u32> {
+        self.sync_progress.lock().await.birthday
+    }
+
+    /// Adds a new on-chain account derived from the seed with the given descriptor type.
+    /// The account uses the next unused account number for that descriptor type.
+    #[wasm_bindgen]
+    pub async fn add_account(
+        &self,
+        descriptor_type: DescriptorType,
+        label: Option<String>,
+    ) -> Result<MutinyAccount, MutinyJsError> {
+        let mut accounts = self.accounts.lock().await;
+        let mut existing = self.storage.get_accounts().map_err(MutinyError::read_err)?;
+
+        // the default account is taproot account 0
+        let account_number = existing
+            .accounts
+            .values()
+            .filter(|a| a.descriptor_type == descriptor_type)
+            .map(|a| a.account_number + 1)
+            .chain(std::iter::once(match descriptor_type {
+                DescriptorType::Taproot => 1,
+                DescriptorType::NativeSegwit => 0,
+            }))
+            .max()
+            .unwrap_or_default();
+
+        let index = AccountIndex {
+            account: existing.accounts.keys().max().unwrap_or(&0) + 1,
+            label,
+            descriptor_type,
+            account_number,
+        };
+
+        let wallet = MutinyWallet::new_account(
+            self.mnemonic.clone(),
+            self.storage.with_wallet_prefix(index.storage_prefix()),
+            self.network,
+            self.esplora.clone(),
+            index.descriptor_type,
+            index.account_number,
+        );
+
+        existing.accounts.insert(index.account, index.clone());
+        self.storage
+            .insert_accounts(existing)
+            .map_err(MutinyError::from)?;
+        accounts.insert(index.account, Arc::new(wallet));
+
+        Ok((&index).into())
+    }
+
+    #[wasm_bindgen]
+    pub async fn list_accounts(&self) -> Result<JsValue /* Vec<MutinyAccount> */, MutinyJsError> {
+        let mut accounts = vec![MutinyAccount {
+            account: 0,
+            label: None,
+            descriptor_type: DescriptorType::Taproot,
+            account_number: 0,
+        }];
+        accounts.extend(
+            self.storage
+                .get_accounts()
+                .map_err(MutinyError::read_err)?
+                .accounts
+                .values()
+                .map(MutinyAccount::from),
+        );
+        accounts.sort_by_key(|a| a.account);
+
+        Ok(serde_wasm_bindgen::to_value(&accounts)?)
+    }
+
+    async fn get_account_wallet(
+        &self,
+        account: Option<u32>,
+    ) -> Result<Arc<MutinyWallet>, MutinyJsError> {
+        match account {
+            None | Some(0) => Ok(self.wallet.clone()),
+            Some(account) => match self.accounts.lock().await.get(&account) {
+                Some(wallet) => Ok(wallet.clone()),
+                None => {
+                    error!("could not find account {account}");
+                    Err(MutinyJsError::WalletOperationFailed)
+                }
+            },
+        }
+    }
+
+    /// Adds a watch only wallet from an output descriptor or an xpub.
+    /// If no change descriptor is given one is derived from the descriptor when possible.
+    #[wasm_bindgen]
+    pub async fn add_watch_only_wallet(
+        &self,
+        descriptor: String,
+        change_descriptor: Option<String>,
+        label: Option<String>,
+    ) -> Result<MutinyWatchOnlyWallet, MutinyJsError> {
+        let (descriptor, change_descriptor) =
+            parse_watch_only_descriptors(&descriptor, change_descriptor, self.network)?;
+
+        let mut watch_only_wallets = self.watch_only_wallets.lock().await;
+        let mut existing = self
+            .storage
+            .get_watch_only_wallets()
+            .map_err(MutinyError::read_err)?;
+
+        let index = WatchOnlyIndex {
+            uuid: Uuid::new_v4().to_string(),
+            label,
+            descriptor,
+            change_descriptor,
+        };
+
+        let wallet = MutinyWallet::new_watch_only(
+            &index.descriptor,
+            index.change_descriptor.as_deref(),
+            self.storage.with_wallet_prefix(index.storage_prefix()),
+            self.network,
+            self.esplora.clone(),
+        )?;
+
+        existing.wallets.insert(index.uuid.clone(), index.clone());
+        self.storage
+            .insert_watch_only_wallets(existing)
+            .map_err(MutinyError::from)?;
+        watch_only_wallets.insert(index.uuid.clone(), Arc::new(wallet));
+
+        Ok((&index).into())
+    }
+
+    #[wasm_bindgen]
+    pub async fn list_watch_only_wallets(
+        &self,
+    ) -> Result<JsValue /* Vec<MutinyWatchOnlyWallet> */, MutinyJsError> {
+        let wallets: Vec<MutinyWatchOnlyWallet> = self
+            .storage
+            .get_watch_only_wallets()
+            .map_err(MutinyError::read_err)?
+            .wallets
+            .values()
+            .map(MutinyWatchOnlyWallet::from)
+            .collect();
+
+        Ok(serde_wasm_bindgen::to_value(&wallets)?)
+    }
+
+    #[wasm_bindgen]
+    pub async fn remove_watch_only_wallet(&self, id: String) -> Result<(), MutinyJsError> {
+        let mut watch_only_wallets = self.watch_only_wallets.lock().await;
+        let mut existing = self
+            .storage
+            .get_watch_only_wallets()
+            .map_err(MutinyError::read_err)?;
+
+        match existing.wallets.remove(&id) {
+            Some(index) => {
+                self.storage
+                    .insert_watch_only_wallets(existing)
+                    .map_err(MutinyError::from)?;
+                watch_only_wallets.remove(&id);
+                MutinyBrowserStorage::delete_with_prefix(&index.storage_prefix());
+                Ok(())
+            }
+            None => Err(MutinyJsError::WalletOperationFailed),
+        }
+    }
+
+    #[wasm_bindgen]
+    pub async fn get_watch_only_address(&self, id: String) -> Result<String, MutinyJsError> {
+        let wallet = self.get_watch_only_wallet(&id).await?;
+        let address = wallet
+            .wallet
+            .lock()
+            .await
+            .get_address(AddressIndex::New)
+            .map_err(|_| MutinyJsError::WalletOperationFailed)?;
+
+        Ok(address.address.to_string())
+    }
+
+    #[wasm_bindgen]
+    pub async fn get_watch_only_balance(&self, id: String) -> Result<MutinyBalance, MutinyJsError> {
+        let wallet = self.get_watch_only_wallet(&id).await?;
+        let balance = wallet
+            .wallet
+            .lock()
+            .await
+            .get_balance()
+            .map_err(|_| MutinyJsError::WalletOperationFailed)?;
+
+        Ok(MutinyBalance {
+            confirmed: balance.confirmed + balance.trusted_pending,
+            unconfirmed: balance.untrusted_pending + balance.immature,
+            lightning: 0,
+            reserved: 0,
+        })
+    }
+
+    #[wasm_bindgen]
+    pub async fn list_watch_only_transactions(
+        &self,
+        id: String,
+    ) -> Result<JsValue /* Vec<TransactionDetails> */, MutinyJsError> {
+        let wallet = self.get_watch_only_wallet(&id).await?;
+        let mut txs = wallet.list_transactions(false).await?;
+        sort_transactions(&mut txs);
+
+        Ok(serde_wasm_bindgen::to_value(&txs)?)
+    }
+
+    /// Creates an unsigned PSBT spending from a watch only wallet,
+    /// returned as a base64 string so it can be signed elsewhere.
+    #[wasm_bindgen]
+    pub async fn create_watch_only_psbt(
+        &self,
+        id: String,
+        destination_address: String,
+        amount: u64,
+        fee_rate: Option<f32>,
+    ) -> Result<String, MutinyJsError> {
+        let send_to = Address::from_str(&destination_address)?;
+
+        if send_to.network != self.network {
+            return Err(MutinyJsError::IncorrectNetwork);
+        }
+
+        let wallet = self.get_watch_only_wallet(&id).await?;
+        let psbt = wallet
+            .create_unsigned_psbt(send_to, amount, fee_rate)
+            .await?;
+
+        Ok(psbt.to_string())
+    }
+
+    async fn get_watch_only_wallet(&self, id: &str) -> Result<Arc<MutinyWallet>, MutinyJsError> {
+        match self.watch_only_wallets.lock().await.get(id) {
+            Some(wallet) => Ok(wallet.clone()),
+            None => {
+                error!("could not find watch only wallet {id}");
+                Err(MutinyJsError::WalletOperationFailed)
+            }
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn estimate_fee_normal(&self) -> u32 {
+        self.chain
+            .get_est_sat_per_1000_weight(ConfirmationTarget::Normal)
+    }
+
+    #[wasm_bindgen]
+    pub fn estimate_fee_high(&self) -> u32 {
+        self.chain
+            .get_est_sat_per_1000_weight(ConfirmationTarget::HighPriority)
+    }
+
+    #[wasm_bindgen]
+    pub async fn new_node(&self) -> Result<NodeIdentity, MutinyJsError> {
+        match create_new_node_from_node_manager(self).await {
+            Ok(node_identity) => Ok(node_identity),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    #[wasm_bindgen]
+    pub async fn list_nodes(&self) -> Result<JsValue /* Vec<String> */, MutinyJsError> {
         let nodes = self.nodes.lock().await;
         let peers: Vec<String> = nodes.iter().map(|(_, n)| n.pubkey.to_hex()).collect();
 
@@ -716,11 +1643,74 @@ impl NodeManager {
 
     // all values in sats
 
+    /// Creates an invoice that expires after `expiry_secs`, or the node's invoice expiry.
     #[wasm_bindgen]
     pub async fn create_invoice(
         &self,
         amount: Option<u64>,
         description: String,
+        expiry_secs: Option<u32>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        self.create_invoice_for_hash(amount, description, None, expiry_secs)
+            .await
+    }
+
+    /// Creates a hold invoice for the payment hash. Its payment is held until it is
+    /// settled with `settle_hold_invoice` or cancelled with `cancel_hold_invoice`, and
+    /// cancelled automatically before it gets close to expiring.
+    ///
+    /// The payment's HTLC expires `min_final_cltv_expiry` blocks after it arrives, 144 by
+    /// default, a larger one lets the payment be held for longer.
+    #[wasm_bindgen]
+    pub async fn create_hold_invoice(
+        &self,
+        amount: Option<u64>,
+        description: String,
+        payment_hash: String,
+        expiry_secs: Option<u32>,
+        min_final_cltv_expiry: Option<u32>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        let payment_hash = PaymentHash(<[u8; 32]>::from_hex(&payment_hash)?);
+        let hold = HoldInvoiceParams::new(payment_hash, min_final_cltv_expiry)?;
+        self.create_invoice_for_hash(amount, description, Some(hold), expiry_secs)
+            .await
+    }
+
+    /// Claims the held payment of the hold invoice for the preimage's hash.
+    #[wasm_bindgen]
+    pub async fn settle_hold_invoice(&self, preimage: String) -> Result<(), MutinyJsError> {
+        let preimage = PaymentPreimage(<[u8; 32]>::from_hex(&preimage)?);
+        let nodes = self.nodes.lock().await;
+        // phantom payments are held by the node that received them
+        for node in nodes.values() {
+            match node.hold_invoices.settle(preimage) {
+                Err(MutinyError::HoldInvoiceNotFound) => continue,
+                res => return Ok(res?),
+            }
+        }
+        Err(MutinyJsError::HoldInvoiceNotFound)
+    }
+
+    /// Fails the held payment of the hold invoice back to its sender.
+    #[wasm_bindgen]
+    pub async fn cancel_hold_invoice(&self, payment_hash: String) -> Result<(), MutinyJsError> {
+        let payment_hash = PaymentHash(<[u8; 32]>::from_hex(&payment_hash)?);
+        let nodes = self.nodes.lock().await;
+        for node in nodes.values() {
+            match node.hold_invoices.cancel(payment_hash) {
+                Err(MutinyError::HoldInvoiceNotFound) => continue,
+                res => return Ok(res?),
+            }
+        }
+        Err(MutinyJsError::HoldInvoiceNotFound)
+    }
+
+    async fn create_invoice_for_hash(
+        &self,
+        amount: Option<u64>,
+        description: String,
+        hold: Option<HoldInvoiceParams>,
+        expiry_secs: Option<u32>,
     ) -> Result<MutinyInvoice, MutinyJsError> {
         let nodes = self.nodes.lock().await;
         let use_phantom = nodes.len() > 1;
@@ -744,7 +1734,8 @@ impl NodeManager {
         } else {
             return Err(MutinyJsError::WalletOperationFailed);
         };
-        let invoice = first_node.create_invoice(amount, description, route_hints)?;
+        let invoice =
+            first_node.create_invoice(amount, description, route_hints, hold, expiry_secs)?;
 
         Ok(invoice.into())
     }
@@ -755,16 +1746,19 @@ impl NodeManager {
         from_node: String,
         invoice_str: String,
         amt_sats: Option<u64>,
+        policy: JsValue, /* Option<PaymentPolicy> */
     ) -> Result<MutinyInvoice, MutinyJsError> {
         let invoice = Invoice::from_str(&invoice_str)?;
 
         if invoice.currency() != currency_from_network(self.network) {
             return Err(MutinyJsError::IncorrectNetwork);
         }
+        let policy: Option<PaymentPolicy> = serde_wasm_bindgen::from_value(policy)?;
 
         let nodes = self.nodes.lock().await;
         let node = nodes.get(from_node.as_str()).unwrap();
-        node.pay_invoice(invoice, amt_sats).map_err(|e| e.into())
+        node.pay_invoice(invoice, amt_sats, policy)
+            .map_err(|e| e.into())
     }
 
     #[wasm_bindgen]
@@ -773,7 +1767,9 @@ impl NodeManager {
         from_node: String,
         to_node: String,
         amt_sats: u64,
+        policy: JsValue, /* Option<PaymentPolicy> */
     ) -> Result<MutinyInvoice, MutinyJsError> {
+        let policy: Option<PaymentPolicy> = serde_wasm_bindgen::from_value(policy)?;
         let nodes = self.nodes.lock().await;
         debug!("Keysending to {to_node}");
         let node = nodes.get(from_node.as_str()).unwrap();
@@ -783,7 +1779,243 @@ impl NodeManager {
             Err(_) => Err(MutinyJsError::PubkeyInvalid),
         }?;
 
-        node.keysend(node_id, amt_sats).map_err(|e| e.into())
+        node.keysend(node_id, amt_sats, policy)
+            .map_err(|e| e.into())
+    }
+
+    /// Finds how each of our nodes would pay the invoice and returns the cheapest
+    /// route, its fees and the node and channel it starts from. Nothing is sent.
+    #[wasm_bindgen]
+    pub async fn estimate_payment(
+        &self,
+        invoice_str: String,
+        amt_sats: Option<u64>,
+    ) -> Result<JsValue /* PaymentEstimate */, MutinyJsError> {
+        let invoice = Invoice::from_str(&invoice_str)?;
+
+        if invoice.currency() != currency_from_network(self.network) {
+            return Err(MutinyJsError::IncorrectNetwork);
+        }
+
+        let nodes = self.nodes.lock().await;
+        let mut estimates = vec![];
+        let mut error = MutinyError::RoutingFailed;
+        for node in nodes.values() {
+            match node.estimate_payment(&invoice, amt_sats) {
+                Ok(estimate) => estimates.push(estimate),
+                Err(e) => error = e,
+            }
+        }
+        let estimate = cheapest_estimate(estimates).ok_or(error)?;
+
+        Ok(serde_wasm_bindgen::to_value(&estimate)?)
+    }
+
+    /// Resolves a Lightning Address or an LNURL-pay string into the amounts it accepts
+    /// and its description, so they can be shown before paying it with `lnurl_pay`.
+    #[wasm_bindgen]
+    pub async fn decode_lnurl(
+        &self,
+        lnurl: String,
+    ) -> Result<JsValue /* LnUrlPayRequest */, MutinyJsError> {
+        let client = HttpLnUrlClient::new()?;
+        let pay_request = fetch_pay_request(&client, &lnurl).await?;
+        Ok(serde_wasm_bindgen::to_value(&pay_request)?)
+    }
+
+    /// Pays a Lightning Address or an LNURL-pay string. The invoice it returns has to be
+    /// for the amount and commit to the service's metadata.
+    #[wasm_bindgen]
+    pub async fn lnurl_pay(
+        &self,
+        from_node: String,
+        lnurl: String,
+        amount_sats: u64,
+        comment: Option<String>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        let client = HttpLnUrlClient::new()?;
+        let pay_request = fetch_pay_request(&client, &lnurl).await?;
+        let invoice = fetch_invoice(
+            &client,
+            &pay_request,
+            amount_sats * 1_000,
+            comment.as_deref(),
+            self.network,
+        )
+        .await?;
+
+        let node = self.get_node(&from_node).await?;
+        Ok(node.pay_invoice(invoice, None, None)?)
+    }
+
+    /// Resolves an LNURL-withdraw voucher into the amounts it can be claimed for,
+    /// so they can be shown before claiming it with `lnurl_withdraw`.
+    #[wasm_bindgen]
+    pub async fn decode_lnurl_withdraw(
+        &self,
+        lnurl: String,
+    ) -> Result<JsValue /* LnUrlWithdrawRequest */, MutinyJsError> {
+        let client = HttpLnUrlClient::new()?;
+        let withdraw_request = fetch_withdraw_request(&client, &lnurl).await?;
+        Ok(serde_wasm_bindgen::to_value(&withdraw_request)?)
+    }
+
+    /// Claims an LNURL-withdraw voucher with a new invoice for the amount. The service
+    /// pays the returned invoice in the background.
+    #[wasm_bindgen]
+    pub async fn lnurl_withdraw(
+        &self,
+        lnurl: String,
+        amount_sats: u64,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        let client = HttpLnUrlClient::new()?;
+        let withdraw_request = fetch_withdraw_request(&client, &lnurl).await?;
+        if !withdraw_request.allows_amount(amount_sats * 1_000) {
+            return Err(MutinyJsError::InvalidArgumentsError);
+        }
+
+        let invoice = self
+            .create_invoice(
+                Some(amount_sats),
+                withdraw_request.default_description.clone(),
+                None,
+            )
+            .await?;
+        let bolt11 = invoice
+            .bolt11
+            .as_deref()
+            .ok_or(MutinyJsError::InvoiceCreationFailed)?;
+        submit_withdraw_invoice(&client, &withdraw_request, &Invoice::from_str(bolt11)?).await?;
+
+        Ok(invoice)
+    }
+
+    /// Logs into a service with an LNURL-auth string. The key it sees is derived from
+    /// our seed and the service's domain, so it is the same on every login.
+    #[wasm_bindgen]
+    pub async fn lnurl_auth(&self, lnurl: String) -> Result<(), MutinyJsError> {
+        let client = HttpLnUrlClient::new()?;
+        Ok(lnurl_auth(&client, &self.mnemonic, &lnurl).await?)
+    }
+
+    /// Pays the invoice and resolves once the payment succeeded or failed, or after
+    /// `timeout_secs`, 60 seconds by default. A timed out payment may still complete later.
+    /// A failed payment's error tells why it failed and where its last path failed.
+    #[wasm_bindgen]
+    pub async fn pay_invoice_and_wait(
+        &self,
+        from_node: String,
+        invoice_str: String,
+        amt_sats: Option<u64>,
+        policy: JsValue, /* Option<PaymentPolicy> */
+        timeout_secs: Option<u32>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        let invoice = Invoice::from_str(&invoice_str)?;
+
+        if invoice.currency() != currency_from_network(self.network) {
+            return Err(MutinyJsError::IncorrectNetwork);
+        }
+        let policy: Option<PaymentPolicy> = serde_wasm_bindgen::from_value(policy)?;
+
+        // don't hold the lock on the nodes while waiting for the payment
+        let node = self.get_node(&from_node).await?;
+        Ok(node
+            .pay_invoice_with_timeout(&invoice, amt_sats, policy, timeout_secs.map(u64::from))
+            .await?)
+    }
+
+    /// Keysends to the node and resolves once the payment succeeded or failed, or after
+    /// `timeout_secs`, 60 seconds by default. A timed out payment may still complete later.
+    /// A failed payment's error tells why it failed and where its last path failed.
+    #[wasm_bindgen]
+    pub async fn keysend_and_wait(
+        &self,
+        from_node: String,
+        to_node: String,
+        amt_sats: u64,
+        policy: JsValue, /* Option<PaymentPolicy> */
+        timeout_secs: Option<u32>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        let policy: Option<PaymentPolicy> = serde_wasm_bindgen::from_value(policy)?;
+        let node_id = match PublicKey::from_str(to_node.as_str()) {
+            Ok(node_id) => Ok(node_id.inner),
+            Err(_) => Err(MutinyJsError::PubkeyInvalid),
+        }?;
+
+        debug!("Keysending to {to_node}");
+        let node = self.get_node(&from_node).await?;
+        Ok(node
+            .keysend_with_timeout(node_id, amt_sats, policy, timeout_secs.map(u64::from))
+            .await?)
+    }
+
+    /// Probes whether the node could be paid the amount from our node, without paying it.
+    /// The result also trains the scorer, so the next payment there is more likely to
+    /// succeed on the first try.
+    #[wasm_bindgen]
+    pub async fn probe(
+        &self,
+        from_node: String,
+        to_node: String,
+        amt_sats: u64,
+        timeout_secs: Option<u32>,
+    ) -> Result<bool, MutinyJsError> {
+        let node_id = match PublicKey::from_str(to_node.as_str()) {
+            Ok(node_id) => Ok(node_id.inner),
+            Err(_) => Err(MutinyJsError::PubkeyInvalid),
+        }?;
+
+        let node = self.get_node(&from_node).await?;
+        Ok(node
+            .probe(node_id, amt_sats, timeout_secs.map(u64::from))
+            .await?)
+    }
+
+    #[wasm_bindgen]
+    pub async fn get_background_probing(
+        &self,
+        self_node_pubkey: String,
+    ) -> Result<bool, MutinyJsError> {
+        let node = self.get_node(&self_node_pubkey).await?;
+        Ok(node.outbound_payments.background_probing())
+    }
+
+    /// Turns on or off regularly probing the destinations the given node pays the most.
+    #[wasm_bindgen]
+    pub async fn set_background_probing(
+        &self,
+        self_node_pubkey: String,
+        enabled: bool,
+    ) -> Result<(), MutinyJsError> {
+        let node = self.get_node(&self_node_pubkey).await?;
+        Ok(node.outbound_payments.set_background_probing(enabled)?)
+    }
+
+    #[wasm_bindgen]
+    pub async fn get_invoice_expiry(&self, self_node_pubkey: String) -> Result<u32, MutinyJsError> {
+        let node = self.get_node(&self_node_pubkey).await?;
+        Ok(node.invoice_expiry()?)
+    }
+
+    /// Sets how long the given node's invoices are valid for when no expiry is given.
+    #[wasm_bindgen]
+    pub async fn set_invoice_expiry(
+        &self,
+        self_node_pubkey: String,
+        expiry_secs: u32,
+    ) -> Result<(), MutinyJsError> {
+        let node = self.get_node(&self_node_pubkey).await?;
+        Ok(node.set_invoice_expiry(expiry_secs)?)
+    }
+
+    async fn get_node(&self, pubkey: &str) -> Result<Arc<Node>, MutinyJsError> {
+        match self.nodes.lock().await.get(pubkey) {
+            Some(node) => Ok(node.clone()),
+            None => {
+                error!("could not find internal node {pubkey}");
+                Err(MutinyJsError::WalletOperationFailed)
+            }
+        }
     }
 
     #[wasm_bindgen]
@@ -863,41 +2095,89 @@ impl NodeManager {
         }
     }
 
+    /// Closes the channel with the given funding outpoint.
+    ///
+    /// A force close broadcasts our latest commitment transaction, for when the peer is
+    /// unresponsive, and can't be given a fee rate. Otherwise `fee_rate`, in sats per vbyte,
+    /// is the target for the cooperative close.
+    ///
+    /// LDK 0.0.113 can't close a channel to an external script, so the closing transaction
+    /// always pays our balance to our own close output. If an `address` is given, a second
+    /// transaction sweeps that output to it once it is spendable, paying its own on-chain
+    /// fee. That is a few blocks after a cooperative close confirms, but only after the
+    /// channel's CSV delay, up to 2016 blocks, for a force close.
     #[wasm_bindgen]
-    pub async fn close_channel(&self, outpoint: String) -> Result<(), MutinyJsError> {
-        let outpoint: OutPoint =
-            OutPoint::from_str(outpoint.as_str()).expect("Failed to parse outpoint");
-        let nodes = self.nodes.lock().await;
-        let channel_opt: Option<(Arc<Node>, ChannelDetails)> = nodes.iter().find_map(|(_, n)| {
-            n.channel_manager
-                .list_channels()
-                .iter()
-                .find(|c| c.funding_txo.map(|f| f.into_bitcoin_outpoint()) == Some(outpoint))
-                .map(|c| (n.clone(), c.clone()))
-        });
+    pub async fn close_channel(
+        &self,
+        outpoint: String,
+        force: bool,
+        fee_rate: Option<f32>,
+        address: Option<String>,
+    ) -> Result<(), MutinyJsError> {
+        let outpoint: OutPoint = OutPoint::from_str(outpoint.as_str())
+            .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        if force && fee_rate.is_some() {
+            return Err(MutinyJsError::InvalidArgumentsError);
+        }
+        let address = match address {
+            Some(address) => {
+                let address = Address::from_str(&address)?;
+                if address.network != self.network {
+                    return Err(MutinyJsError::IncorrectNetwork);
+                }
+                Some(address)
+            }
+            None => None,
+        };
 
-        match channel_opt {
-            Some((node, channel)) => {
-                node.channel_manager
-                    .close_channel(&channel.channel_id, &channel.counterparty.node_id)
-                    .map_err(|_| MutinyJsError::ChannelClosingFailed)?;
+        let nodes = self.nodes.lock().await;
+        let (node, channel) = nodes
+            .iter()
+            .find_map(|(_, n)| {
+                n.channel_manager
+                    .list_channels()
+                    .iter()
+                    .find(|c| c.funding_txo.map(|f| f.into_bitcoin_outpoint()) == Some(outpoint))
+                    .map(|c| (n.clone(), c.clone()))
+            })
+            .ok_or(MutinyJsError::ChannelNotFound)?;
 
-                Ok(())
-            }
-            None => Err(MutinyJsError::ChannelClosingFailed),
+        if let Some(address) = address {
+            node.persister
+                .persist_close_destination(&outpoint, &address)
+                .map_err(MutinyError::from)?;
         }
+
+        let channel_id = &channel.channel_id;
+        let counterparty = &channel.counterparty.node_id;
+        let res = if force {
+            node.channel_manager
+                .force_close_broadcasting_latest_txn(channel_id, counterparty)
+        } else if let Some(fee_rate) = fee_rate {
+            // sats per vbyte to sats per 1000 weight units
+            let target_feerate = (fee_rate * 250.0) as u32;
+            node.channel_manager.close_channel_with_target_feerate(
+                channel_id,
+                counterparty,
+                target_feerate,
+            )
+        } else {
+            node.channel_manager.close_channel(channel_id, counterparty)
+        };
+
+        res.map_err(|e| {
+            error!("could not close channel {outpoint}: {e:?}");
+            MutinyJsError::ChannelClosingFailed
+        })
     }
 
+    /// Lists the channels of all nodes, including the ones being opened and the closed
+    /// ones that still have funds to claim on-chain.
     #[wasm_bindgen]
-    pub async fn list_channels(&self) -> Result<JsValue, MutinyJsError> {
+    pub async fn list_channels(&self) -> Result<JsValue /* Vec<MutinyChannel> */, MutinyJsError> {
         let nodes = self.nodes.lock().await;
-        let channels: Vec<ChannelDetails> = nodes
-            .iter()
-            .flat_map(|(_, n)| n.channel_manager.list_channels())
-            .collect();
-
         let mutiny_channels: Vec<MutinyChannel> =
-            channels.iter().map(MutinyChannel::from).collect();
+            nodes.iter().flat_map(|(_, n)| n.list_channels()).collect();
 
         Ok(serde_wasm_bindgen::to_value(&mutiny_channels)?)
     }
@@ -950,6 +2230,115 @@ impl NodeManager {
         Ok(serde_wasm_bindgen::to_value(&storage_peers)?)
     }
 
+    /// Gets the policy the given node uses to decide on inbound channels.
+    #[wasm_bindgen]
+    pub async fn get_channel_policy(
+        &self,
+        self_node_pubkey: String,
+    ) -> Result<JsValue /* ChannelAcceptancePolicy */, MutinyJsError> {
+        if let Some(node) = self.nodes.lock().await.get(&self_node_pubkey) {
+            Ok(serde_wasm_bindgen::to_value(
+                &node.channel_acceptor.policy(),
+            )?)
+        } else {
+            error!("could not find internal node {self_node_pubkey}");
+            Err(MutinyError::WalletOperationFailed.into())
+        }
+    }
+
+    /// Sets the policy the given node uses to decide on inbound channels.
+    #[wasm_bindgen]
+    pub async fn set_channel_policy(
+        &self,
+        self_node_pubkey: String,
+        policy: JsValue, /* ChannelAcceptancePolicy */
+    ) -> Result<(), MutinyJsError> {
+        let policy: ChannelAcceptancePolicy = serde_wasm_bindgen::from_value(policy)?;
+        if let Some(node) = self.nodes.lock().await.get(&self_node_pubkey) {
+            Ok(node.channel_acceptor.set_policy(policy)?)
+        } else {
+            error!("could not find internal node {self_node_pubkey}");
+            Err(MutinyError::WalletOperationFailed.into())
+        }
+    }
+
+    /// Gets the retry strategy and fee limit the given node uses by default for payments.
+    #[wasm_bindgen]
+    pub async fn get_payment_policy(
+        &self,
+        self_node_pubkey: String,
+    ) -> Result<JsValue /* PaymentPolicy */, MutinyJsError> {
+        let node = self.get_node(&self_node_pubkey).await?;
+        Ok(serde_wasm_bindgen::to_value(
+            &node.outbound_payments.policy(),
+        )?)
+    }
+
+    /// Sets the retry strategy and fee limit the given node uses by default for payments.
+    #[wasm_bindgen]
+    pub async fn set_payment_policy(
+        &self,
+        self_node_pubkey: String,
+        policy: JsValue, /* PaymentPolicy */
+    ) -> Result<(), MutinyJsError> {
+        let policy: PaymentPolicy = serde_wasm_bindgen::from_value(policy)?;
+        let node = self.get_node(&self_node_pubkey).await?;
+        Ok(node.outbound_payments.set_policy(policy)?)
+    }
+
+    /// Lists the inbound channel requests that are waiting to be approved or rejected.
+    #[wasm_bindgen]
+    pub async fn list_channel_requests(
+        &self,
+    ) -> Result<JsValue /* Vec<MutinyChannelRequest> */, MutinyJsError> {
+        let nodes = self.nodes.lock().await;
+        let requests: Vec<MutinyChannelRequest> = nodes
+            .iter()
+            .flat_map(|(_, n)| n.channel_acceptor.list_pending_requests())
+            .collect();
+
+        Ok(serde_wasm_bindgen::to_value(&requests)?)
+    }
+
+    #[wasm_bindgen]
+    pub async fn approve_channel_request(
+        &self,
+        temporary_channel_id: String,
+    ) -> Result<(), MutinyJsError> {
+        let node = self.find_channel_request(&temporary_channel_id).await?;
+        let temporary_channel_id = <[u8; 32]>::from_hex(&temporary_channel_id)?;
+        Ok(node
+            .channel_acceptor
+            .approve_request(temporary_channel_id)?)
+    }
+
+    #[wasm_bindgen]
+    pub async fn reject_channel_request(
+        &self,
+        temporary_channel_id: String,
+    ) -> Result<(), MutinyJsError> {
+        let node = self.find_channel_request(&temporary_channel_id).await?;
+        let temporary_channel_id = <[u8; 32]>::from_hex(&temporary_channel_id)?;
+        Ok(node.channel_acceptor.reject_request(temporary_channel_id)?)
+    }
+
+    async fn find_channel_request(
+        &self,
+        temporary_channel_id: &str,
+    ) -> Result<Arc<Node>, MutinyJsError> {
+        let nodes = self.nodes.lock().await;
+        nodes
+            .values()
+            .find(|n| {
+                n.channel_acceptor
+                    .list_pending_requests()
+                    .iter()
+                    .any(|r| r.temporary_channel_id() == temporary_channel_id)
+            })
+            .cloned()
+            .ok_or(MutinyJsError::ChannelRequestNotFound)
+    }
+
     #[wasm_bindgen]
     pub async fn get_bitcoin_price(&self) -> Result<f32, MutinyJsError> {
         let client = Client::builder().build().unwrap();
@@ -984,6 +2373,24 @@ impl NodeManager {
     }
 }
 
+// Sort by timestamp, but if there's no timestamp put it first,
+// if timestamps are equal, compare by txid
+fn sort_transactions(txs: &mut [TransactionDetails]) {
+    txs.sort_by(|a, b| {
+        a.confirmation_time
+            .as_ref()
+            .map(|c| c.timestamp)
+            .cmp(&b.confirmation_time.as_ref().map(|c| c.timestamp))
+            .then_with(|| a.txid.cmp(&b.txid))
+    });
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct BatchRecipient {
+    address: String,
+    amount: u64,
+}
+
 #[derive(Deserialize, Clone, Copy, Debug)]
 struct CoingeckoResponse {
     pub bitcoin: CoingeckoPrice,
@@ -1044,6 +2451,9 @@ pub(crate) async fn create_new_node_from_node_manager(
         node_manager.network,
         node_manager.websocket_proxy_addr.clone(),
         node_manager.esplora.clone(),
+        node_manager.network_graph.clone(),
+        node_manager.gossip.clone(),
+        node_manager.event_bus.clone(),
     )
     .await
     {
@@ -1067,8 +2477,17 @@ pub(crate) async fn create_new_node_from_node_manager(
 
 #[cfg(test)]
 mod tests {
+    use crate::error::MutinyJsError;
+    use crate::event::HTLCStatus;
     use crate::keymanager::generate_seed;
+    use crate::logging::MutinyLogger;
     use crate::nodemanager::NodeManager;
+    use bitcoin::hashes::{sha256, Hash};
+    use bitcoin_hashes::hex::ToHex;
+    use lightning::ln::PaymentHash;
+    use lightning_invoice::Invoice;
+    use std::str::FromStr;
+    use std::sync::Arc;
 
     use crate::test::*;
 
@@ -1087,6 +2506,9 @@ mod tests {
             None,
             Some("testnet".to_owned()),
             None,
+            None,
+            None,
+            Some(String::new()),
         )
         .await
         .expect("node manager should initialize");
@@ -1106,6 +2528,9 @@ mod tests {
             None,
             Some("testnet".to_owned()),
             None,
+            None,
+            None,
+            Some(String::new()),
         )
         .await
         .unwrap();
@@ -1116,6 +2541,30 @@ mod tests {
         cleanup_test();
     }
 
+    #[test]
+    async fn restore_with_birthday() {
+        log!("restoring with birthday");
+
+        let seed = generate_seed(12).expect("Failed to gen seed");
+        let nm = NodeManager::new(
+            "password".to_string(),
+            Some(seed.to_string()),
+            None,
+            Some("testnet".to_owned()),
+            None,
+            Some(2_400_000),
+            Some(20),
+            Some(String::new()),
+        )
+        .await
+        .expect("node manager should initialize");
+
+        assert_eq!(Some(2_400_000), nm.get_wallet_birthday().await);
+        assert_eq!(Some(2_400_000), nm.get_sync_progress().await.birthday);
+
+        cleanup_test();
+    }
+
     #[test]
     async fn created_new_nodes() {
         log!("creating new nodes");
@@ -1127,6 +2576,9 @@ mod tests {
             None,
             Some("testnet".to_owned()),
             None,
+            None,
+            None,
+            Some(String::new()),
         )
         .await
         .expect("node manager should initialize");
@@ -1156,4 +2608,128 @@ mod tests {
 
         cleanup_test();
     }
+
+    #[test]
+    async fn list_channels_of_new_node() {
+        log!("listing channels of a new node");
+
+        let seed = generate_seed(12).expect("Failed to gen seed");
+        let nm = NodeManager::new(
+            "password".to_string(),
+            Some(seed.to_string()),
+            None,
+            Some("testnet".to_owned()),
+            None,
+            None,
+            None,
+            Some(String::new()),
+        )
+        .await
+        .expect("node manager should initialize");
+
+        let node_identity = nm.new_node().await.expect("should create new node");
+        let node = nm.get_node(&node_identity.pubkey).await.unwrap();
+
+        // no channels are open and no closed channel has funds left to claim
+        assert!(node.list_channels().is_empty());
+
+        cleanup_test();
+    }
+
+    #[test]
+    async fn hold_invoices() {
+        log!("holding payments to hold invoices");
+
+        let nm = NodeManager::new(
+            "password".to_string(),
+            None,
+            None,
+            Some("testnet".to_owned()),
+            None,
+            None,
+            None,
+            Some(String::new()),
+        )
+        .await
+        .expect("node manager should initialize");
+        let node_identity = nm.new_node().await.expect("should create new node");
+        let node = nm.get_node(&node_identity.pubkey).await.unwrap();
+        let logger = Arc::new(MutinyLogger::default());
+        let create = |preimage: [u8; 32], min_final_cltv_expiry: Option<u32>| {
+            nm.create_hold_invoice(
+                Some(1_000),
+                "hold".to_string(),
+                sha256::Hash::hash(&preimage).to_hex(),
+                None,
+                min_final_cltv_expiry,
+            )
+        };
+        let read = |payment_hash: PaymentHash| {
+            node.persister
+                .read_payment_info(payment_hash, true, logger.clone())
+                .unwrap()
+        };
+
+        // too short to hold the payment for any time
+        assert!(create([1; 32], Some(30)).await.is_err());
+
+        let preimage = [1; 32];
+        let payment_hash = PaymentHash(sha256::Hash::hash(&preimage).into_inner());
+        let invoice = create(preimage, None).await.unwrap();
+        let invoice = Invoice::from_str(&invoice.bolt11().unwrap()).unwrap();
+        assert_eq!(invoice.min_final_cltv_expiry(), 144);
+
+        // the payment is held until the preimage is given, and cancelled a block before
+        // LDK fails it back 21 blocks before it expires, with 6 blocks for routing
+        let height = node.channel_manager.current_best_block().height();
+        assert!(node.hold_invoices.hold(payment_hash, 1_000_000));
+        let held = read(payment_hash);
+        assert!(matches!(held.status, HTLCStatus::Held));
+        assert_eq!(held.hold.unwrap().cancel_at_height, Some(height + 116));
+
+        nm.settle_hold_invoice(preimage.to_hex()).await.unwrap();
+        assert_eq!(read(payment_hash).preimage, Some(preimage));
+
+        // a larger final CLTV delta holds the payment for longer
+        let preimage = [2; 32];
+        let payment_hash = PaymentHash(sha256::Hash::hash(&preimage).into_inner());
+        create(preimage, Some(288)).await.unwrap();
+        let height = node.channel_manager.current_best_block().height();
+        assert!(node.hold_invoices.hold(payment_hash, 1_000_000));
+        assert_eq!(
+            read(payment_hash).hold.unwrap().cancel_at_height,
+            Some(height + 260)
+        );
+
+        nm.cancel_hold_invoice(payment_hash.0.to_hex())
+            .await
+            .unwrap();
+        assert!(matches!(read(payment_hash).status, HTLCStatus::Failed));
+        assert!(matches!(
+            nm.settle_hold_invoice(preimage.to_hex()).await,
+            Err(MutinyJsError::HoldInvoiceNotFound)
+        ));
+
+        // held payments that reach their deadline are cancelled
+        let preimage = [3; 32];
+        let payment_hash = PaymentHash(sha256::Hash::hash(&preimage).into_inner());
+        create(preimage, None).await.unwrap();
+        assert!(node.hold_invoices.hold(payment_hash, 1_000_000));
+        node.hold_invoices.cancel_expired();
+        assert!(matches!(read(payment_hash).status, HTLCStatus::Held));
+
+        let mut expiring = read(payment_hash);
+        expiring.hold.as_mut().unwrap().cancel_at_height =
+            Some(node.channel_manager.current_best_block().height());
+        node.persister
+            .persist_payment_info(payment_hash, expiring, true)
+            .unwrap();
+        node.hold_invoices.cancel_expired();
+        assert!(matches!(read(payment_hash).status, HTLCStatus::Failed));
+
+        // payments to invoices that are not hold invoices are failed back
+        assert!(!node.hold_invoices.hold(PaymentHash([4; 32]), 1_000_000));
+
+        cleanup_test();
+    }
 }
@@ -0,0 +1,792 @@
+use crate::error::MutinyError;
+use crate::event::PaymentFailure;
+use crate::ldkstorage::{MutinyNodePersister, PhantomChannelManager};
+use crate::logging::MutinyLogger;
+use crate::node::{NetworkGraph, Scorer};
+use bitcoin::hashes::Hash;
+use bitcoin_hashes::hex::ToHex;
+use lightning::chain::keysinterface::{KeysInterface, PhantomKeysManager};
+use lightning::ln::channelmanager::{PaymentId, PaymentSendFailure};
+use lightning::ln::{PaymentHash, PaymentPreimage};
+use lightning::routing::router::{find_route, PaymentParameters, Route, RouteParameters};
+use lightning::routing::scoring::Score;
+use lightning::util::events::Event;
+use lightning_invoice::Invoice;
+use log::{error, info, warn};
+use secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+// The final cltv delta we use for keysends and probes
+const DEFAULT_FINAL_CLTV_EXPIRY_DELTA: u32 = 40;
+
+/// How often the background prober probes the destinations we pay the most.
+pub(crate) const BACKGROUND_PROBING_INTERVAL_SECS: u64 = 30 * 60;
+
+// How many of the destinations we pay the most are probed in the background
+const MAX_PROBED_DESTINATIONS: usize = 5;
+
+/// How long a payment keeps being retried over other routes when some of its paths fail.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(tag = "type")]
+pub enum RetryStrategy {
+    /// Retry up to `count` times
+    Attempts { count: u32 },
+    /// Retry until `secs` seconds have passed since the payment was sent
+    Timeout { secs: u64 },
+}
+
+impl Default for RetryStrategy {
+    fn default() -> Self {
+        RetryStrategy::Attempts { count: 5 }
+    }
+}
+
+/// The most we are willing to pay in routing fees for a payment.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum MaxRoutingFee {
+    Sats {
+        sats: u64,
+    },
+    /// A percentage of the amount sent, e.g. `0.5` for half a percent
+    Percent {
+        percent: f64,
+    },
+}
+
+impl MaxRoutingFee {
+    pub(crate) fn max_fee_msat(&self, amount_msat: u64) -> u64 {
+        match self {
+            MaxRoutingFee::Sats { sats } => sats * 1_000,
+            MaxRoutingFee::Percent { percent } => (amount_msat as f64 * percent / 100.0) as u64,
+        }
+    }
+}
+
+/// How a node sends its payments, used when a payment does not come with its own policy.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct PaymentPolicy {
+    #[serde(default)]
+    pub retry: RetryStrategy,
+    /// No limit when not set
+    #[serde(default)]
+    pub max_fee: Option<MaxRoutingFee>,
+}
+
+impl PaymentPolicy {
+    fn validate(&self) -> Result<(), MutinyError> {
+        match self.max_fee {
+            Some(MaxRoutingFee::Percent { percent }) if !percent.is_finite() || percent < 0.0 => {
+                Err(MutinyError::InvalidArgumentsError)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether a payment sent at `started_at` and retried `retries` times may be retried again.
+    fn can_retry(&self, retries: u32, started_at: u64, now: u64) -> bool {
+        match self.retry {
+            RetryStrategy::Attempts { count } => retries < count,
+            RetryStrategy::Timeout { secs } => now.saturating_sub(started_at) < secs,
+        }
+    }
+
+    fn check_fee(&self, route: &Route, amount_msat: u64) -> Result<(), MutinyError> {
+        match self.max_fee {
+            Some(max_fee) if route.get_total_fees() > max_fee.max_fee_msat(amount_msat) => {
+                Err(MutinyError::RoutingFeeTooHigh)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The amount to pay for the invoice, `amt_sats` is only allowed for invoices without an amount.
+pub(crate) fn invoice_amount_msat(
+    invoice: &Invoice,
+    amt_sats: Option<u64>,
+) -> Result<u64, MutinyError> {
+    match (invoice.amount_milli_satoshis(), amt_sats) {
+        (None, Some(amt_sats)) => Ok(amt_sats * 1_000),
+        (Some(amount_msat), None) => Ok(amount_msat),
+        _ => Err(MutinyError::InvoiceInvalid),
+    }
+}
+
+pub(crate) fn invoice_route_params(invoice: &Invoice, amount_msat: u64) -> RouteParameters {
+    let expiry = invoice.duration_since_epoch() + invoice.expiry_time();
+    let mut payment_params = PaymentParameters::from_node_id(invoice.recover_payee_pub_key())
+        .with_expiry_time(expiry.as_secs())
+        .with_route_hints(invoice.route_hints());
+    if let Some(features) = invoice.features() {
+        payment_params = payment_params.with_features(features.clone());
+    }
+    RouteParameters {
+        payment_params,
+        final_value_msat: amount_msat,
+        final_cltv_expiry_delta: invoice.min_final_cltv_expiry() as u32,
+    }
+}
+
+/// The route a payment would take and what it would cost, nothing is sent to get it.
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct PaymentEstimate {
+    /// The pubkey of our node that would send the payment
+    pub node: String,
+    pub amount_sats: u64,
+    pub fees_msat: u64,
+    pub fees_sats: u64,
+    /// Whether the fee is allowed by the node's default payment policy
+    pub within_fee_limit: bool,
+    /// More than one when the payment would be split
+    pub paths: Vec<EstimatedPath>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct EstimatedPath {
+    /// Our channel the path starts with
+    pub channel_id: Option<String>,
+    pub hop_count: usize,
+    pub fees_msat: u64,
+    /// How many blocks the payment could be locked up for along this path
+    pub total_cltv_expiry_delta: u32,
+    pub hops: Vec<EstimatedHop>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct EstimatedHop {
+    pub pubkey: String,
+    pub short_channel_id: u64,
+    /// For the last hop, the amount paid to the recipient
+    pub fee_msat: u64,
+    pub cltv_expiry_delta: u32,
+}
+
+/// What paying `amount_msat` over `route` would cost, `channel_id` finds our channel
+/// with the given short channel id.
+fn estimate_route(
+    node: String,
+    route: &Route,
+    amount_msat: u64,
+    policy: &PaymentPolicy,
+    channel_id: impl Fn(u64) -> Option<String>,
+) -> PaymentEstimate {
+    let paths = route
+        .paths
+        .iter()
+        .map(|path| {
+            let hops: Vec<EstimatedHop> = path
+                .iter()
+                .map(|hop| EstimatedHop {
+                    pubkey: hop.pubkey.to_hex(),
+                    short_channel_id: hop.short_channel_id,
+                    fee_msat: hop.fee_msat,
+                    cltv_expiry_delta: hop.cltv_expiry_delta,
+                })
+                .collect();
+            EstimatedPath {
+                channel_id: path
+                    .first()
+                    .and_then(|first| channel_id(first.short_channel_id)),
+                hop_count: hops.len(),
+                // the last hop's fee is the amount paid to the recipient
+                fees_msat: hops
+                    .split_last()
+                    .map_or(0, |(_, h)| h.iter().map(|hop| hop.fee_msat).sum()),
+                total_cltv_expiry_delta: hops.iter().map(|hop| hop.cltv_expiry_delta).sum(),
+                hops,
+            }
+        })
+        .collect();
+
+    let fees_msat = route.get_total_fees();
+    PaymentEstimate {
+        node,
+        amount_sats: amount_msat / 1_000,
+        fees_msat,
+        fees_sats: fees_msat / 1_000,
+        within_fee_limit: policy.check_fee(route, amount_msat).is_ok(),
+        paths,
+    }
+}
+
+/// The estimate of the node that would pay the least in fees.
+pub(crate) fn cheapest_estimate(estimates: Vec<PaymentEstimate>) -> Option<PaymentEstimate> {
+    estimates.into_iter().min_by_key(|e| e.fees_msat)
+}
+
+/// A node we paid before, the background prober keeps the scorer warm for the routes to it.
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub(crate) struct PaymentDestination {
+    pub pubkey: String,
+    pub payments: u32,
+    /// The amount of the last payment, which is also the amount probed for
+    pub last_amount_msat: u64,
+}
+
+/// Counts a payment of `amount_msat` to the node with the given pubkey.
+fn add_payment(destinations: &mut Vec<PaymentDestination>, pubkey: String, amount_msat: u64) {
+    match destinations.iter_mut().find(|d| d.pubkey == pubkey) {
+        Some(destination) => {
+            destination.payments += 1;
+            destination.last_amount_msat = amount_msat;
+        }
+        None => destinations.push(PaymentDestination {
+            pubkey,
+            payments: 1,
+            last_amount_msat: amount_msat,
+        }),
+    }
+}
+
+/// The destinations the background prober probes, the ones we paid the most first.
+fn most_paid_destinations(mut destinations: Vec<PaymentDestination>) -> Vec<PaymentDestination> {
+    destinations.sort_by(|a, b| b.payments.cmp(&a.payments));
+    destinations.truncate(MAX_PROBED_DESTINATIONS);
+    destinations
+}
+
+struct PendingPayment {
+    policy: PaymentPolicy,
+    retries: u32,
+    started_at: u64,
+}
+
+/// Finds routes for a node's outbound payments, sends them and retries their failed paths,
+/// following the node's [`PaymentPolicy`] or the one given with the payment.
+///
+/// The fee limit is checked against every route before it is used, so a payment that
+/// would pay too much is rejected before any HTLC is sent.
+pub(crate) struct OutboundPayments {
+    channel_manager: Arc<PhantomChannelManager>,
+    network_graph: Arc<NetworkGraph>,
+    scorer: Arc<Mutex<Scorer>>,
+    keys_manager: Arc<PhantomKeysManager>,
+    persister: Arc<MutinyNodePersister>,
+    logger: Arc<MutinyLogger>,
+    policy: Mutex<PaymentPolicy>,
+    pending: Mutex<HashMap<PaymentHash, PendingPayment>>,
+    destinations: Mutex<Vec<PaymentDestination>>,
+    background_probing: AtomicBool,
+}
+
+impl OutboundPayments {
+    pub(crate) fn new(
+        channel_manager: Arc<PhantomChannelManager>,
+        network_graph: Arc<NetworkGraph>,
+        scorer: Arc<Mutex<Scorer>>,
+        keys_manager: Arc<PhantomKeysManager>,
+        persister: Arc<MutinyNodePersister>,
+        logger: Arc<MutinyLogger>,
+    ) -> Result<Self, MutinyError> {
+        let policy = persister.read_payment_policy()?;
+        let destinations = persister.read_payment_destinations()?;
+        let background_probing = persister.read_background_probing()?;
+        Ok(Self {
+            channel_manager,
+            network_graph,
+            scorer,
+            keys_manager,
+            persister,
+            logger,
+            policy: Mutex::new(policy),
+            pending: Mutex::new(HashMap::new()),
+            destinations: Mutex::new(destinations),
+            background_probing: AtomicBool::new(background_probing),
+        })
+    }
+
+    pub(crate) fn policy(&self) -> PaymentPolicy {
+        self.policy.lock().unwrap().clone()
+    }
+
+    pub(crate) fn set_policy(&self, policy: PaymentPolicy) -> Result<(), MutinyError> {
+        policy.validate()?;
+        self.persister.persist_payment_policy(&policy)?;
+        *self.policy.lock().unwrap() = policy;
+        Ok(())
+    }
+
+    pub(crate) fn find_route(&self, route_params: &RouteParameters) -> Result<Route, MutinyError> {
+        let first_hops = self.channel_manager.list_usable_channels();
+        let first_hops = first_hops.iter().collect::<Vec<_>>();
+        let scorer = self.scorer.lock().unwrap();
+        find_route(
+            &self.channel_manager.get_our_node_id(),
+            route_params,
+            &*self.network_graph,
+            Some(&first_hops),
+            self.logger.clone(),
+            &*scorer,
+            &self.keys_manager.get_secure_random_bytes(),
+        )
+        .map_err(|e| {
+            warn!("could not find route: {}", e.err);
+            MutinyError::RoutingFailed
+        })
+    }
+
+    /// Finds the route a payment would take, without sending anything.
+    pub(crate) fn estimate(
+        &self,
+        route_params: &RouteParameters,
+    ) -> Result<PaymentEstimate, MutinyError> {
+        let route = self.find_route(route_params)?;
+        let usable_channels = self.channel_manager.list_usable_channels();
+
+        Ok(estimate_route(
+            self.channel_manager.get_our_node_id().to_hex(),
+            &route,
+            route_params.final_value_msat,
+            &self.policy(),
+            // the first hop is one of our channels
+            |short_channel_id| {
+                usable_channels
+                    .iter()
+                    .find(|c| c.get_outbound_payment_scid() == Some(short_channel_id))
+                    .map(|c| c.channel_id.to_hex())
+            },
+        ))
+    }
+
+    pub(crate) fn pay_invoice(
+        &self,
+        invoice: &Invoice,
+        amount_msat: u64,
+        policy: Option<PaymentPolicy>,
+    ) -> Result<(), MutinyError> {
+        let route_params = invoice_route_params(invoice, amount_msat);
+        let payment_hash = PaymentHash(invoice.payment_hash().into_inner());
+        let payment_secret = Some(*invoice.payment_secret());
+        self.send(payment_hash, &route_params, policy, |route| {
+            self.channel_manager.send_payment(
+                route,
+                payment_hash,
+                &payment_secret,
+                PaymentId(payment_hash.0),
+            )
+        })
+    }
+
+    pub(crate) fn keysend(
+        &self,
+        to_node: PublicKey,
+        amount_msat: u64,
+        preimage: PaymentPreimage,
+        payment_hash: PaymentHash,
+        policy: Option<PaymentPolicy>,
+    ) -> Result<(), MutinyError> {
+        let route_params = RouteParameters {
+            payment_params: PaymentParameters::for_keysend(to_node),
+            final_value_msat: amount_msat,
+            final_cltv_expiry_delta: DEFAULT_FINAL_CLTV_EXPIRY_DELTA,
+        };
+
+        self.send(payment_hash, &route_params, policy, |route| {
+            self.channel_manager
+                .send_spontaneous_payment(route, Some(preimage), PaymentId(payment_hash.0))
+                .map(|_| ())
+        })
+    }
+
+    fn send(
+        &self,
+        payment_hash: PaymentHash,
+        route_params: &RouteParameters,
+        policy: Option<PaymentPolicy>,
+        send: impl FnOnce(&Route) -> Result<(), PaymentSendFailure>,
+    ) -> Result<(), MutinyError> {
+        let policy = match policy {
+            Some(policy) => {
+                policy.validate()?;
+                policy
+            }
+            None => self.policy(),
+        };
+
+        let route = self.find_route(route_params)?;
+        policy.check_fee(&route, route_params.final_value_msat)?;
+
+        let pending = PendingPayment {
+            policy,
+            retries: 0,
+            started_at: crate::utils::now().as_secs(),
+        };
+        self.pending.lock().unwrap().insert(payment_hash, pending);
+
+        match send(&route) {
+            // the paths that failed are retried once their PaymentPathFailed events come in
+            Ok(()) | Err(PaymentSendFailure::PartialFailure { .. }) => {
+                self.record_destination(
+                    &route_params.payment_params.payee_pubkey,
+                    route_params.final_value_msat,
+                );
+                Ok(())
+            }
+            Err(e) => {
+                error!("failed to send payment {}: {e:?}", payment_hash.0.to_hex());
+                self.pending.lock().unwrap().remove(&payment_hash);
+                Err(MutinyError::RoutingFailed)
+            }
+        }
+    }
+
+    /// Retries a failed path of a payment over a new route, or abandons the payment when
+    /// its policy does not allow another retry, which makes LDK fail it.
+    /// Returns why the payment was abandoned.
+    pub(crate) fn retry_failed_path(
+        &self,
+        payment_hash: PaymentHash,
+        payment_id: PaymentId,
+        route_params: Option<RouteParameters>,
+    ) -> Result<(), PaymentFailure> {
+        let mut pending = self.pending.lock().unwrap();
+        let payment = match pending.get_mut(&payment_hash) {
+            Some(payment) => payment,
+            // not sent by us, e.g. a probe
+            None => return Ok(()),
+        };
+
+        let now = crate::utils::now().as_secs();
+        let result = match route_params {
+            // LDK can't retry the payment
+            None => Err(PaymentFailure::RouteNotFound),
+            Some(_)
+                if !payment
+                    .policy
+                    .can_retry(payment.retries, payment.started_at, now) =>
+            {
+                Err(PaymentFailure::RetriesExhausted)
+            }
+            Some(route_params) => {
+                payment.retries += 1;
+                self.find_route(&route_params)
+                    .map_err(|_| PaymentFailure::RouteNotFound)
+                    .and_then(|route| {
+                        payment
+                            .policy
+                            .check_fee(&route, route_params.final_value_msat)
+                            .map_err(|_| PaymentFailure::RoutingFeeTooHigh)?;
+                        self.channel_manager
+                            .retry_payment(&route, payment_id)
+                            .map_err(|e| {
+                                error!(
+                                    "failed to retry payment {}: {e:?}",
+                                    payment_hash.0.to_hex()
+                                );
+                                PaymentFailure::RouteNotFound
+                            })
+                    })
+            }
+        };
+
+        if let Err(failure) = result {
+            info!(
+                "abandoning payment {}: {failure:?}",
+                payment_hash.0.to_hex()
+            );
+            pending.remove(&payment_hash);
+            self.channel_manager.abandon_payment(payment_id);
+        }
+        result
+    }
+
+    pub(crate) fn payment_resolved(&self, payment_hash: &PaymentHash) {
+        self.pending.lock().unwrap().remove(payment_hash);
+    }
+
+    /// Sends probes along the route we would pay `amount_msat` to the node over,
+    /// one per path. Returns the payment hashes of the probes.
+    pub(crate) fn probe(
+        &self,
+        to_node: PublicKey,
+        amount_msat: u64,
+    ) -> Result<Vec<PaymentHash>, MutinyError> {
+        let route_params = RouteParameters {
+            payment_params: PaymentParameters::from_node_id(to_node),
+            final_value_msat: amount_msat,
+            final_cltv_expiry_delta: DEFAULT_FINAL_CLTV_EXPIRY_DELTA,
+        };
+        let route = self.find_route(&route_params)?;
+
+        route
+            .paths
+            .into_iter()
+            .map(|path| {
+                self.channel_manager
+                    .send_probe(path)
+                    .map(|(payment_hash, _)| payment_hash)
+                    .map_err(|e| {
+                        error!("failed to send probe to {to_node}: {e:?}");
+                        MutinyError::RoutingFailed
+                    })
+            })
+            .collect()
+    }
+
+    pub(crate) fn background_probing(&self) -> bool {
+        self.background_probing.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_background_probing(&self, enabled: bool) -> Result<(), MutinyError> {
+        self.persister.persist_background_probing(enabled)?;
+        self.background_probing.store(enabled, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Probes the destinations we pay the most, the results train the scorer.
+    pub(crate) fn probe_destinations(&self) {
+        let destinations = self.destinations.lock().unwrap().clone();
+        for destination in most_paid_destinations(destinations) {
+            let result = PublicKey::from_str(&destination.pubkey)
+                .map_err(|_| MutinyError::PeerInfoParseFailed)
+                .and_then(|pubkey| self.probe(pubkey, destination.last_amount_msat));
+            if let Err(e) = result {
+                warn!("could not probe {}: {e}", destination.pubkey);
+            }
+        }
+    }
+
+    fn record_destination(&self, pubkey: &PublicKey, amount_msat: u64) {
+        let mut destinations = self.destinations.lock().unwrap();
+        add_payment(&mut destinations, pubkey.to_hex(), amount_msat);
+
+        if let Err(e) = self.persister.persist_payment_destinations(&destinations) {
+            error!("could not persist payment destinations: {e}");
+        }
+    }
+
+    /// Teaches the scorer about the paths our payments and probes went over.
+    pub(crate) fn update_scorer(&self, event: &Event) {
+        let mut scorer = self.scorer.lock().unwrap();
+        match event {
+            Event::PaymentPathFailed {
+                path,
+                short_channel_id: Some(short_channel_id),
+                ..
+            } => scorer.payment_path_failed(&path.iter().collect::<Vec<_>>(), *short_channel_id),
+            Event::PaymentPathSuccessful { path, .. } => {
+                scorer.payment_path_successful(&path.iter().collect::<Vec<_>>())
+            }
+            Event::ProbeSuccessful { path, .. } => {
+                scorer.probe_successful(&path.iter().collect::<Vec<_>>())
+            }
+            Event::ProbeFailed {
+                path,
+                short_channel_id: Some(short_channel_id),
+                ..
+            } => scorer.probe_failed(&path.iter().collect::<Vec<_>>(), *short_channel_id),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::localstorage::MutinyBrowserStorage;
+    use crate::test::*;
+    use lightning::ln::features::{ChannelFeatures, NodeFeatures};
+    use lightning::routing::router::RouteHop;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_max_routing_fee() {
+        log!("max routing fee");
+
+        assert_eq!(
+            MaxRoutingFee::Sats { sats: 10 }.max_fee_msat(1_000_000),
+            10_000
+        );
+        assert_eq!(
+            MaxRoutingFee::Percent { percent: 0.5 }.max_fee_msat(1_000_000),
+            5_000
+        );
+
+        let policy = PaymentPolicy {
+            max_fee: Some(MaxRoutingFee::Percent { percent: -1.0 }),
+            ..Default::default()
+        };
+        assert!(policy.validate().is_err());
+        assert!(PaymentPolicy::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_retry_strategy() {
+        log!("retry strategy");
+
+        let policy = PaymentPolicy::default();
+        assert!(policy.can_retry(4, 0, 1_000));
+        assert!(!policy.can_retry(5, 0, 1_000));
+
+        let policy = PaymentPolicy {
+            retry: RetryStrategy::Timeout { secs: 30 },
+            ..Default::default()
+        };
+        assert!(policy.can_retry(100, 1_000, 1_029));
+        assert!(!policy.can_retry(0, 1_000, 1_030));
+
+        let json = r#"{"retry":{"type":"Timeout","secs":30},"max_fee":{"type":"Sats","sats":100}}"#;
+        let policy: PaymentPolicy = serde_json::from_str(json).unwrap();
+        assert_eq!(policy.retry, RetryStrategy::Timeout { secs: 30 });
+        assert_eq!(policy.max_fee, Some(MaxRoutingFee::Sats { sats: 100 }));
+    }
+
+    fn hop(short_channel_id: u64, fee_msat: u64, cltv_expiry_delta: u32) -> RouteHop {
+        RouteHop {
+            pubkey: PublicKey::from_str(
+                "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54",
+            )
+            .unwrap(),
+            node_features: NodeFeatures::empty(),
+            short_channel_id,
+            channel_features: ChannelFeatures::empty(),
+            fee_msat,
+            cltv_expiry_delta,
+        }
+    }
+
+    fn estimate(node: &str, fees_msat: u64) -> PaymentEstimate {
+        PaymentEstimate {
+            node: node.to_string(),
+            amount_sats: 100,
+            fees_msat,
+            fees_sats: fees_msat / 1_000,
+            within_fee_limit: true,
+            paths: vec![],
+        }
+    }
+
+    #[test]
+    fn test_estimate_route() {
+        log!("estimate route");
+
+        // split over two paths, the last hop of each carries its part of the payment
+        let route = Route {
+            paths: vec![
+                vec![hop(1, 1_000, 40), hop(2, 500, 144), hop(3, 60_000, 40)],
+                vec![hop(4, 40_000, 40)],
+            ],
+            payment_params: None,
+        };
+        let channel_id = |scid| (scid == 1).then(|| "our_channel".to_string());
+
+        let estimate = estimate_route(
+            "node".to_string(),
+            &route,
+            100_000,
+            &PaymentPolicy::default(),
+            channel_id,
+        );
+        assert_eq!(estimate.node, "node");
+        assert_eq!(estimate.amount_sats, 100);
+        assert_eq!(estimate.fees_msat, 1_500);
+        assert_eq!(estimate.fees_sats, 1);
+        assert!(estimate.within_fee_limit);
+        assert_eq!(estimate.paths.len(), 2);
+
+        let first = &estimate.paths[0];
+        assert_eq!(first.channel_id, Some("our_channel".to_string()));
+        assert_eq!(first.hop_count, 3);
+        assert_eq!(first.fees_msat, 1_500);
+        assert_eq!(first.total_cltv_expiry_delta, 224);
+        assert_eq!(first.hops[2].short_channel_id, 3);
+        assert_eq!(first.hops[2].fee_msat, 60_000);
+
+        // a direct payment pays no fees
+        let direct = &estimate.paths[1];
+        assert_eq!(direct.channel_id, None);
+        assert_eq!(direct.hop_count, 1);
+        assert_eq!(direct.fees_msat, 0);
+        assert_eq!(direct.total_cltv_expiry_delta, 40);
+
+        let policy = PaymentPolicy {
+            max_fee: Some(MaxRoutingFee::Sats { sats: 1 }),
+            ..Default::default()
+        };
+        let estimate = estimate_route("node".to_string(), &route, 100_000, &policy, channel_id);
+        assert!(!estimate.within_fee_limit);
+
+        let policy = PaymentPolicy {
+            max_fee: Some(MaxRoutingFee::Percent { percent: 1.5 }),
+            ..Default::default()
+        };
+        let estimate = estimate_route("node".to_string(), &route, 100_000, &policy, channel_id);
+        assert!(estimate.within_fee_limit);
+    }
+
+    #[test]
+    fn test_cheapest_estimate() {
+        log!("cheapest estimate");
+
+        assert_eq!(cheapest_estimate(vec![]), None);
+
+        let estimates = vec![
+            estimate("first", 3_000),
+            estimate("second", 1_000),
+            estimate("third", 2_000),
+        ];
+        assert_eq!(cheapest_estimate(estimates).unwrap().node, "second");
+    }
+
+    #[test]
+    fn test_most_paid_destinations() {
+        log!("most paid destinations");
+
+        let mut destinations = vec![];
+        for (i, payments) in [1, 4, 2, 7, 3, 5, 6].into_iter().enumerate() {
+            for _ in 0..payments {
+                add_payment(&mut destinations, format!("node{i}"), 1_000);
+            }
+        }
+        add_payment(&mut destinations, "node3".to_string(), 5_000);
+        assert_eq!(destinations.len(), 7);
+
+        let probed = most_paid_destinations(destinations);
+        assert_eq!(probed.len(), MAX_PROBED_DESTINATIONS);
+        let pubkeys: Vec<&str> = probed.iter().map(|d| d.pubkey.as_str()).collect();
+        assert_eq!(pubkeys, vec!["node3", "node6", "node5", "node1", "node4"]);
+        assert_eq!(probed[0].payments, 8);
+        assert_eq!(probed[0].last_amount_msat, 5_000);
+    }
+
+    #[test]
+    fn test_persist_background_probing() {
+        log!("persist background probing");
+
+        let persister = MutinyNodePersister::new(
+            "node".to_string(),
+            MutinyBrowserStorage::new("".to_string()),
+        );
+        assert!(!persister.read_background_probing().unwrap());
+
+        persister.persist_background_probing(true).unwrap();
+        assert!(persister.read_background_probing().unwrap());
+
+        // read back by a node that was restarted
+        let persister = MutinyNodePersister::new(
+            "node".to_string(),
+            MutinyBrowserStorage::new("".to_string()),
+        );
+        assert!(persister.read_background_probing().unwrap());
+
+        let destinations = vec![PaymentDestination {
+            pubkey: "node".to_string(),
+            payments: 2,
+            last_amount_msat: 1_000,
+        }];
+        persister
+            .persist_payment_destinations(&destinations)
+            .unwrap();
+        assert_eq!(persister.read_payment_destinations().unwrap(), destinations);
+
+        cleanup_test();
+    }
+}
@@ -1,6 +1,7 @@
+use crate::node::OnionMessenger;
 use crate::{ldkstorage::PhantomChannelManager, logging::MutinyLogger, socket::WsSocketDescriptor};
 use bitcoin::secp256k1::PublicKey;
-use lightning::ln::msgs::NetAddress;
+use lightning::ln::msgs::{NetAddress, RoutingMessageHandler};
 use lightning::ln::peer_handler::PeerHandleError;
 use lightning::ln::peer_handler::{IgnoringMessageHandler, PeerManager as LdkPeerManager};
 use std::sync::Arc;
@@ -53,8 +54,8 @@ pub(crate) trait PeerManager {
 pub(crate) type PeerManagerImpl = LdkPeerManager<
     WsSocketDescriptor,
     Arc<PhantomChannelManager>,
-    Arc<IgnoringMessageHandler>,
-    Arc<IgnoringMessageHandler>,
+    Arc<dyn RoutingMessageHandler + Send + Sync>,
+    Arc<OnionMessenger>,
     Arc<MutinyLogger>,
     Arc<IgnoringMessageHandler>,
 >;
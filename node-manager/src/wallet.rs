@@ -1,25 +1,48 @@
 use futures::lock::Mutex;
-use log::{debug, info};
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
 
 use bdk::blockchain::{Blockchain, EsploraBlockchain};
-use bdk::keys::ExtendedKey;
+use bdk::database::Database;
+use bdk::descriptor::{ExtendedDescriptor, IntoWalletDescriptor};
+use bdk::keys::{ExtendedKey, KeyMap};
 use bdk::template::DescriptorTemplateOut;
-use bdk::{FeeRate, LocalUtxo, SignOptions, SyncOptions, TransactionDetails, Wallet};
+use bdk::wallet::AddressIndex;
+use bdk::{FeeRate, KeychainKind, LocalUtxo, SignOptions, SyncOptions, TransactionDetails, Wallet};
 use bdk_macros::maybe_await;
 use bip39::Mnemonic;
-use bitcoin::util::bip32::{ChildNumber, DerivationPath, ExtendedPrivKey};
-use bitcoin::{Address, Network, Txid};
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::psbt::{self, PartiallySignedTransaction};
+use bitcoin::secp256k1::{All, Secp256k1};
+use bitcoin::util::bip32::{ChildNumber, DerivationPath, ExtendedPrivKey, ExtendedPubKey};
+use bitcoin::{Address, Network, OutPoint, Transaction, Txid};
+use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::spawn_local;
 
 use crate::error::MutinyError;
 use crate::localstorage::MutinyBrowserStorage;
 
+/// The script type used for the descriptors of an on-chain account.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[wasm_bindgen]
+pub enum DescriptorType {
+    /// BIP-86 single key taproot, `m/86'/coin'/account'`
+    Taproot,
+    /// BIP-84 native segwit, `m/84'/coin'/account'`
+    NativeSegwit,
+}
+
 #[derive(Debug)]
 pub struct MutinyWallet {
     pub wallet: Mutex<Wallet<MutinyBrowserStorage>>,
     pub blockchain: Arc<EsploraBlockchain>,
+    pub watch_only: bool,
+    // Signed transactions from previews that are waiting to be broadcast, they only live
+    // in memory and the inputs of previews from before a restart are released on startup
+    pending_transactions: Mutex<HashMap<Txid, Transaction>>,
 }
 
 impl MutinyWallet {
@@ -28,13 +51,32 @@ impl MutinyWallet {
         database: MutinyBrowserStorage,
         network: Network,
         esplora: Arc<EsploraBlockchain>,
+    ) -> MutinyWallet {
+        Self::new_account(
+            mnemonic,
+            database,
+            network,
+            esplora,
+            DescriptorType::Taproot,
+            0,
+        )
+    }
+
+    /// Creates the wallet for the given account of the mnemonic.
+    /// Every account should be given its own database.
+    pub fn new_account(
+        mnemonic: Mnemonic,
+        database: MutinyBrowserStorage,
+        network: Network,
+        esplora: Arc<EsploraBlockchain>,
+        descriptor_type: DescriptorType,
+        account_number: u32,
     ) -> MutinyWallet {
         let entropy = mnemonic.to_entropy();
         let xprivkey = ExtendedPrivKey::new_master(network, &entropy).unwrap();
         let xkey = ExtendedKey::from(xprivkey);
-        let account_number = 0;
         let (receive_descriptor_template, change_descriptor_template) =
-            get_tr_descriptors_for_extended_key(xkey, network, account_number);
+            get_descriptors_for_extended_key(xkey, network, descriptor_type, account_number);
 
         let wallet = Wallet::new(
             receive_descriptor_template,
@@ -44,12 +86,43 @@ impl MutinyWallet {
         )
         .expect("Error creating wallet");
 
+        if let Err(e) = release_stale_preview_utxos(&wallet.database()) {
+            error!("could not release the inputs of previews from before a restart: {e}");
+        }
+
         MutinyWallet {
             wallet: Mutex::new(wallet),
             blockchain: esplora,
+            watch_only: false,
+            pending_transactions: Mutex::new(HashMap::new()),
         }
     }
 
+    /// Creates a wallet that can only track funds and create unsigned PSBTs.
+    /// The descriptors must not contain any private keys.
+    pub fn new_watch_only(
+        descriptor: &str,
+        change_descriptor: Option<&str>,
+        database: MutinyBrowserStorage,
+        network: Network,
+        esplora: Arc<EsploraBlockchain>,
+    ) -> Result<MutinyWallet, MutinyError> {
+        let secp = Secp256k1::new();
+        let receive = public_descriptor(&secp, descriptor, network)?;
+        let change = change_descriptor
+            .map(|d| public_descriptor(&secp, d, network))
+            .transpose()?;
+
+        let wallet = Wallet::new(receive, change, network, database)?;
+
+        Ok(MutinyWallet {
+            wallet: Mutex::new(wallet),
+            blockchain: esplora,
+            watch_only: true,
+            pending_transactions: Mutex::new(HashMap::new()),
+        })
+    }
+
     pub async fn sync(&self) -> Result<(), MutinyError> {
         let wallet = self.wallet.lock().await;
 
@@ -75,6 +148,36 @@ impl MutinyWallet {
         Ok(self.wallet.lock().await.list_unspent()?)
     }
 
+    pub async fn list_frozen_utxos(&self) -> Result<Vec<OutPoint>, MutinyError> {
+        let wallet = self.wallet.lock().await;
+        let frozen = wallet
+            .database()
+            .get_frozen_utxos()
+            .map_err(MutinyError::read_err)?;
+        Ok(frozen)
+    }
+
+    /// Frozen utxos are never selected as inputs, including for sweeps and channel funding.
+    pub async fn freeze_utxo(&self, outpoint: OutPoint) -> Result<(), MutinyError> {
+        let wallet = self.wallet.lock().await;
+        let database = wallet.database();
+        let mut frozen = database.get_frozen_utxos().map_err(MutinyError::read_err)?;
+        if !frozen.contains(&outpoint) {
+            frozen.push(outpoint);
+            database.insert_frozen_utxos(frozen)?;
+        }
+        Ok(())
+    }
+
+    pub async fn unfreeze_utxo(&self, outpoint: OutPoint) -> Result<(), MutinyError> {
+        let wallet = self.wallet.lock().await;
+        let database = wallet.database();
+        let mut frozen = database.get_frozen_utxos().map_err(MutinyError::read_err)?;
+        frozen.retain(|o| *o != outpoint);
+        database.insert_frozen_utxos(frozen)?;
+        Ok(())
+    }
+
     pub async fn list_transactions(
         &self,
         include_raw: bool,
@@ -96,14 +199,32 @@ impl MutinyWallet {
             .find(|tx| tx.txid == txid))
     }
 
-    pub async fn create_signed_psbt(
+    /// Builds a transaction paying every recipient. If `drain_to` is given the rest of
+    /// the spendable balance, after fees and the anchor reserve, is sent to it.
+    async fn build_psbt(
         &self,
-        send_to: Address,
-        amount: u64,
+        wallet: &Wallet<MutinyBrowserStorage>,
+        recipients: Vec<(Address, u64)>,
+        drain_to: Option<Address>,
         fee_rate: Option<f32>,
-    ) -> Result<bitcoin::psbt::PartiallySignedTransaction, MutinyError> {
-        let wallet = self.wallet.lock().await;
-        if send_to.network != wallet.network() {
+    ) -> Result<
+        (
+            bitcoin::psbt::PartiallySignedTransaction,
+            TransactionDetails,
+        ),
+        MutinyError,
+    > {
+        if recipients.is_empty() && drain_to.is_none() {
+            return Err(MutinyError::WalletOperationFailed);
+        }
+
+        let network = wallet.network();
+        if recipients
+            .iter()
+            .map(|(address, _)| address)
+            .chain(drain_to.iter())
+            .any(|address| address.network != network)
+        {
             return Err(MutinyError::IncorrectNetwork);
         }
 
@@ -112,71 +233,291 @@ impl MutinyWallet {
         } else {
             self.blockchain.estimate_fee(1).await?
         };
-        let (mut psbt, details) = {
+        let unspendable = unspendable_utxos(wallet)?;
+        let reserve = anchor_reserve(wallet)?;
+        // draining the wallet sends the anchor reserve back to ourselves
+        let reserve_to = match drain_to {
+            Some(_) if reserve > 0 => Some(wallet.get_internal_address(AddressIndex::New)?),
+            _ => None,
+        };
+        let (psbt, details) = {
             let mut builder = wallet.build_tx();
+            for (address, amount) in recipients {
+                builder.add_recipient(address.script_pubkey(), amount);
+            }
+            if let Some(reserve_to) = reserve_to {
+                builder.add_recipient(reserve_to.script_pubkey(), reserve);
+            }
+            if let Some(drain_to) = drain_to {
+                builder
+                    .drain_wallet() // Spend all outputs in this wallet that aren't frozen or reserved.
+                    .drain_to(drain_to.script_pubkey());
+            }
             builder
-                .add_recipient(send_to.script_pubkey(), amount)
+                .unspendable(unspendable.clone())
                 .enable_rbf()
                 .fee_rate(fee_rate);
             builder.finish()?
         };
+
+        if reserve > 0 {
+            // frozen and reserved coins can't be spent on fee bumping either
+            let locked: u64 = wallet
+                .list_unspent()?
+                .iter()
+                .filter(|utxo| unspendable.contains(&utxo.outpoint))
+                .map(|utxo| utxo.txout.value)
+                .sum();
+            let spendable = wallet.get_balance()?.get_spendable();
+            let remaining = (spendable + details.received)
+                .saturating_sub(details.sent)
+                .saturating_sub(locked);
+            if remaining < reserve {
+                return Err(MutinyError::AnchorReserveError);
+            }
+        }
+
         debug!("Transaction details: {:#?}", details);
         debug!("Unsigned PSBT: {}", &psbt);
+        Ok((psbt, details))
+    }
+
+    async fn build_signed_psbt(
+        &self,
+        recipients: Vec<(Address, u64)>,
+        drain_to: Option<Address>,
+        fee_rate: Option<f32>,
+    ) -> Result<
+        (
+            bitcoin::psbt::PartiallySignedTransaction,
+            TransactionDetails,
+        ),
+        MutinyError,
+    > {
+        if self.watch_only {
+            return Err(MutinyError::WalletSigningFailed);
+        }
+
+        let wallet = self.wallet.lock().await;
+        let (mut psbt, details) = self
+            .build_psbt(&wallet, recipients, drain_to, fee_rate)
+            .await?;
         let finalized = wallet.sign(&mut psbt, SignOptions::default())?;
         debug!("{}", finalized);
-        Ok(psbt)
+        Ok((psbt, details))
     }
 
-    pub async fn send(
+    pub async fn create_unsigned_psbt(
         &self,
-        destination_address: Address,
+        send_to: Address,
         amount: u64,
         fee_rate: Option<f32>,
-    ) -> Result<bitcoin::Txid, MutinyError> {
-        let psbt = self
-            .create_signed_psbt(destination_address, amount, fee_rate)
+    ) -> Result<bitcoin::psbt::PartiallySignedTransaction, MutinyError> {
+        let wallet = self.wallet.lock().await;
+        let (psbt, _) = self
+            .build_psbt(&wallet, vec![(send_to, amount)], None, fee_rate)
             .await?;
+        Ok(psbt)
+    }
 
-        let raw_transaction = psbt.extract_tx();
-        let txid = raw_transaction.txid();
+    pub async fn create_signed_psbt(
+        &self,
+        send_to: Address,
+        amount: u64,
+        fee_rate: Option<f32>,
+    ) -> Result<bitcoin::psbt::PartiallySignedTransaction, MutinyError> {
+        self.create_signed_batch_psbt(vec![(send_to, amount)], None, fee_rate)
+            .await
+    }
 
-        maybe_await!(self.blockchain.broadcast(&raw_transaction))?;
-        debug!("Transaction broadcast! TXID: {txid}");
-        Ok(txid)
+    pub async fn create_signed_batch_psbt(
+        &self,
+        recipients: Vec<(Address, u64)>,
+        drain_to: Option<Address>,
+        fee_rate: Option<f32>,
+    ) -> Result<bitcoin::psbt::PartiallySignedTransaction, MutinyError> {
+        let (psbt, _) = self
+            .build_signed_psbt(recipients, drain_to, fee_rate)
+            .await?;
+        Ok(psbt)
     }
 
-    pub async fn create_sweep_psbt(
+    /// Creates a signed channel funding transaction and reserves its inputs so that
+    /// other sends can't spend them until the channel is discarded or closed.
+    pub async fn create_funding_psbt(
         &self,
-        destination_address: Address,
+        send_to: Address,
+        amount: u64,
         fee_rate: Option<f32>,
     ) -> Result<bitcoin::psbt::PartiallySignedTransaction, MutinyError> {
+        if self.watch_only {
+            return Err(MutinyError::WalletSigningFailed);
+        }
+
+        // hold the lock until the inputs are reserved so nothing else can select them
         let wallet = self.wallet.lock().await;
+        let (mut psbt, _) = self
+            .build_psbt(&wallet, vec![(send_to.clone(), amount)], None, fee_rate)
+            .await?;
+        wallet.sign(&mut psbt, SignOptions::default())?;
 
-        if destination_address.network != wallet.network() {
-            return Err(MutinyError::IncorrectNetwork);
+        let txid = psbt.unsigned_tx.txid();
+        let index = psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .position(|o| o.script_pubkey == send_to.script_pubkey())
+            .ok_or(MutinyError::FundingTxCreationFailed)?;
+        let channel_id = lightning::chain::transaction::OutPoint {
+            txid,
+            index: index as u16,
         }
+        .to_channel_id();
 
-        let fee_rate = if let Some(rate) = fee_rate {
-            FeeRate::from_sat_per_vb(rate)
-        } else {
-            self.blockchain.estimate_fee(1).await?
-        };
-        let (mut psbt, details) = {
+        let database = wallet.database();
+        let mut reserved = database
+            .get_reserved_utxos()
+            .map_err(MutinyError::read_err)?;
+        let inputs = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .map(|i| i.previous_output)
+            .collect();
+        reserved.insert(channel_id.to_hex(), inputs);
+        database.insert_reserved_utxos(reserved)?;
+
+        Ok(psbt)
+    }
+
+    /// Releases the inputs reserved for the given channel's funding transaction.
+    pub async fn release_reserved_utxos(&self, channel_id: &[u8; 32]) -> Result<(), MutinyError> {
+        let wallet = self.wallet.lock().await;
+        let database = wallet.database();
+        let mut reserved = database
+            .get_reserved_utxos()
+            .map_err(MutinyError::read_err)?;
+        if reserved.remove(&channel_id.to_hex()).is_some() {
+            database.insert_reserved_utxos(reserved)?;
+        }
+        Ok(())
+    }
+
+    /// Keeps [`ANCHOR_RESERVE_SATS`] of the on-chain balance aside for the channel so that we
+    /// can fee bump its commitment transaction if it is force closed. The reserve is kept
+    /// after the channel closes, until it is removed once nothing is left to claim.
+    pub async fn add_anchor_channel(&self, channel_id: [u8; 32]) -> Result<(), MutinyError> {
+        let wallet = self.wallet.lock().await;
+        let database = wallet.database();
+        let mut channels = database
+            .get_anchor_channels()
+            .map_err(MutinyError::read_err)?;
+        if channels.insert(channel_id.to_hex()) {
+            database.insert_anchor_channels(channels)?;
+        }
+        Ok(())
+    }
+
+    pub async fn remove_anchor_channel(&self, channel_id: &[u8; 32]) -> Result<(), MutinyError> {
+        let wallet = self.wallet.lock().await;
+        let database = wallet.database();
+        let mut channels = database
+            .get_anchor_channels()
+            .map_err(MutinyError::read_err)?;
+        if channels.remove(&channel_id.to_hex()) {
+            database.insert_anchor_channels(channels)?;
+        }
+        Ok(())
+    }
+
+    /// The amount of the on-chain balance that sends are not allowed to spend.
+    pub async fn anchor_reserve(&self) -> Result<u64, MutinyError> {
+        anchor_reserve(&*self.wallet.lock().await)
+    }
+
+    /// Builds and signs a child transaction spending `outpoint` together with wallet utxos,
+    /// paying enough fees for the parent and child to reach `target_fee_rate` as a package.
+    /// The input for `outpoint` is left for the caller to sign. This may spend the anchor reserve.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create_cpfp_psbt(
+        &self,
+        outpoint: OutPoint,
+        psbt_input: psbt::Input,
+        satisfaction_weight: usize,
+        parent_weight: usize,
+        parent_fee: u64,
+        target_fee_rate: FeeRate,
+    ) -> Result<PartiallySignedTransaction, MutinyError> {
+        if self.watch_only {
+            return Err(MutinyError::WalletSigningFailed);
+        }
+
+        let wallet = self.wallet.lock().await;
+        let change = wallet.get_internal_address(AddressIndex::New)?;
+        let unspendable = unspendable_utxos(&wallet)?;
+
+        let build = |fee: Option<u64>| {
             let mut builder = wallet.build_tx();
             builder
-                .drain_wallet() // Spend all outputs in this wallet.
-                .drain_to(destination_address.script_pubkey())
-                .enable_rbf()
-                .fee_rate(fee_rate);
-            builder.finish()?
+                .add_foreign_utxo(outpoint, psbt_input.clone(), satisfaction_weight)?
+                .drain_to(change.script_pubkey())
+                .unspendable(unspendable.clone())
+                .enable_rbf();
+            match fee {
+                Some(fee) => builder.fee_absolute(fee),
+                None => builder.fee_rate(target_fee_rate),
+            };
+            builder.finish()
         };
-        debug!("Transaction details: {:#?}", details);
-        debug!("Unsigned PSBT: {}", &psbt);
-        let finalized = wallet.sign(&mut psbt, SignOptions::default())?;
-        debug!("{}", finalized);
+
+        // the child alone at the target fee rate tells us its size, on top of that
+        // it has to pay for whatever the parent is missing
+        let (_, child) = build(None)?;
+        let package_fee = child.fee.unwrap_or_default()
+            + target_fee_rate
+                .fee_wu(parent_weight)
+                .saturating_sub(parent_fee);
+        let (mut psbt, details) = build(Some(package_fee))?;
+        debug!("CPFP transaction details: {:#?}", details);
+
+        wallet.sign(&mut psbt, SignOptions::default())?;
         Ok(psbt)
     }
 
+    pub async fn send(
+        &self,
+        destination_address: Address,
+        amount: u64,
+        fee_rate: Option<f32>,
+    ) -> Result<bitcoin::Txid, MutinyError> {
+        self.send_batch(vec![(destination_address, amount)], None, fee_rate)
+            .await
+    }
+
+    /// Sends to all of the recipients in a single transaction, optionally sending
+    /// the remaining balance to `drain_to`.
+    pub async fn send_batch(
+        &self,
+        recipients: Vec<(Address, u64)>,
+        drain_to: Option<Address>,
+        fee_rate: Option<f32>,
+    ) -> Result<Txid, MutinyError> {
+        let psbt = self
+            .create_signed_batch_psbt(recipients, drain_to, fee_rate)
+            .await?;
+
+        self.broadcast(psbt.extract_tx()).await
+    }
+
+    pub async fn create_sweep_psbt(
+        &self,
+        destination_address: Address,
+        fee_rate: Option<f32>,
+    ) -> Result<bitcoin::psbt::PartiallySignedTransaction, MutinyError> {
+        self.create_signed_batch_psbt(vec![], Some(destination_address), fee_rate)
+            .await
+    }
+
     pub async fn sweep(
         &self,
         destination_address: Address,
@@ -186,7 +527,141 @@ impl MutinyWallet {
             .create_sweep_psbt(destination_address, fee_rate)
             .await?;
 
-        let raw_transaction = psbt.extract_tx();
+        self.broadcast(psbt.extract_tx()).await
+    }
+
+    /// Builds and signs the transaction without broadcasting it. The transaction is kept,
+    /// and its inputs reserved, until it is broadcast with [`MutinyWallet::broadcast_preview`]
+    /// or discarded.
+    pub async fn preview_batch(
+        &self,
+        recipients: Vec<(Address, u64)>,
+        drain_to: Option<Address>,
+        fee_rate: Option<f32>,
+    ) -> Result<TransactionPreview, MutinyError> {
+        if self.watch_only {
+            return Err(MutinyError::WalletSigningFailed);
+        }
+
+        // hold the lock until the inputs are reserved so nothing else can select them
+        let wallet = self.wallet.lock().await;
+        let (mut psbt, details) = self
+            .build_psbt(&wallet, recipients, drain_to, fee_rate)
+            .await?;
+        wallet.sign(&mut psbt, SignOptions::default())?;
+
+        let preview = {
+            let network = wallet.network();
+
+            let inputs = psbt
+                .unsigned_tx
+                .input
+                .iter()
+                .zip(psbt.inputs.iter())
+                .map(|(txin, input)| PreviewInput {
+                    outpoint: txin.previous_output,
+                    amount: input.witness_utxo.as_ref().map(|o| o.value),
+                })
+                .collect();
+
+            let mut outputs = vec![];
+            let mut change = None;
+            for txout in psbt.unsigned_tx.output.iter() {
+                let output = PreviewOutput {
+                    address: Address::from_script(&txout.script_pubkey, network)
+                        .map(|a| a.to_string()),
+                    amount: txout.value,
+                };
+                let keychain = wallet
+                    .database()
+                    .get_path_from_script_pubkey(&txout.script_pubkey)?;
+                if matches!(keychain, Some((KeychainKind::Internal, _))) && change.is_none() {
+                    change = Some(output);
+                } else {
+                    outputs.push(output);
+                }
+            }
+
+            let tx = psbt.extract_tx();
+            let vsize = tx.vsize();
+            let fee = details.fee.unwrap_or_default();
+            let fee_rate = fee as f32 / vsize as f32;
+            let confirmation_target = MutinyBrowserStorage::get_fee_estimates()
+                .ok()
+                .and_then(|estimates| confirmation_target_for_fee_rate(&estimates, fee_rate));
+
+            let preview = TransactionPreview {
+                txid: tx.txid(),
+                fee,
+                fee_rate,
+                vsize,
+                inputs,
+                outputs,
+                change,
+                confirmation_target,
+            };
+
+            let database = wallet.database();
+            let mut reserved = database
+                .get_reserved_utxos()
+                .map_err(MutinyError::read_err)?;
+            let inputs = tx.input.iter().map(|i| i.previous_output).collect();
+            reserved.insert(preview_reservation_key(&tx.txid()), inputs);
+            database.insert_reserved_utxos(reserved)?;
+
+            self.pending_transactions.lock().await.insert(tx.txid(), tx);
+            preview
+        };
+
+        Ok(preview)
+    }
+
+    /// Broadcasts the exact transaction that was returned by a preview.
+    pub async fn broadcast_preview(&self, txid: Txid) -> Result<Txid, MutinyError> {
+        let tx = match self.pending_transactions.lock().await.remove(&txid) {
+            Some(tx) => tx,
+            None => return Err(MutinyError::WalletOperationFailed),
+        };
+
+        match self.broadcast(tx.clone()).await {
+            Ok(txid) => {
+                self.release_preview_utxos(&txid).await?;
+                Ok(txid)
+            }
+            Err(e) => {
+                // keep the preview and its inputs so the broadcast can be tried again
+                self.pending_transactions.lock().await.insert(txid, tx);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn discard_preview(&self, txid: Txid) -> Result<(), MutinyError> {
+        if self
+            .pending_transactions
+            .lock()
+            .await
+            .remove(&txid)
+            .is_some()
+        {
+            self.release_preview_utxos(&txid).await?;
+        }
+        Ok(())
+    }
+
+    async fn release_preview_utxos(&self, txid: &Txid) -> Result<(), MutinyError> {
+        let wallet = self.wallet.lock().await;
+        let database = wallet.database();
+        let mut reserved = database
+            .get_reserved_utxos()
+            .map_err(MutinyError::read_err)?;
+        if reserved.remove(&preview_reservation_key(txid)).is_some() {
+            database.insert_reserved_utxos(reserved)?;
+        }
+        Ok(())
+    }
+
+    async fn broadcast(&self, raw_transaction: Transaction) -> Result<Txid, MutinyError> {
         let txid = raw_transaction.txid();
 
         maybe_await!(self.blockchain.broadcast(&raw_transaction))?;
@@ -195,10 +670,92 @@ impl MutinyWallet {
     }
 }
 
+fn anchor_reserve(wallet: &Wallet<MutinyBrowserStorage>) -> Result<u64, MutinyError> {
+    let channels = wallet
+        .database()
+        .get_anchor_channels()
+        .map_err(MutinyError::read_err)?;
+    Ok(channels.len() as u64 * ANCHOR_RESERVE_SATS)
+}
+
+// The reserved utxos of previews are kept next to the channel funding ones, keyed by txid
+const PREVIEW_RESERVATION_PREFIX: &str = "preview_";
+
+fn preview_reservation_key(txid: &Txid) -> String {
+    format!("{PREVIEW_RESERVATION_PREFIX}{txid}")
+}
+
+// The transactions of previews are gone after a restart, so their inputs would stay reserved
+fn release_stale_preview_utxos(database: &MutinyBrowserStorage) -> Result<(), MutinyError> {
+    let mut reserved = database
+        .get_reserved_utxos()
+        .map_err(MutinyError::read_err)?;
+    let count = reserved.len();
+    reserved.retain(|key, _| !key.starts_with(PREVIEW_RESERVATION_PREFIX));
+    if reserved.len() != count {
+        database.insert_reserved_utxos(reserved)?;
+    }
+    Ok(())
+}
+
+// Frozen utxos and the ones reserved for pending channel funding or previews can't be spent
+fn unspendable_utxos(wallet: &Wallet<MutinyBrowserStorage>) -> Result<Vec<OutPoint>, MutinyError> {
+    let database = wallet.database();
+    let mut unspendable = database.get_frozen_utxos().map_err(MutinyError::read_err)?;
+    let reserved = database
+        .get_reserved_utxos()
+        .map_err(MutinyError::read_err)?;
+    unspendable.extend(reserved.into_values().flatten());
+    Ok(unspendable)
+}
+
+/// A signed transaction that has not been broadcast yet.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TransactionPreview {
+    pub txid: Txid,
+    pub fee: u64,
+    /// sats per vbyte
+    pub fee_rate: f32,
+    pub vsize: usize,
+    pub inputs: Vec<PreviewInput>,
+    pub outputs: Vec<PreviewOutput>,
+    pub change: Option<PreviewOutput>,
+    /// The number of blocks the transaction is expected to confirm in,
+    /// based on the cached fee estimates
+    pub confirmation_target: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PreviewInput {
+    pub outpoint: OutPoint,
+    pub amount: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PreviewOutput {
+    pub address: Option<String>,
+    pub amount: u64,
+}
+
+// Finds the soonest confirmation target whose estimated fee rate is met,
+// the estimates are keyed by the number of blocks
+fn confirmation_target_for_fee_rate(
+    estimates: &HashMap<String, f64>,
+    fee_rate: f32,
+) -> Option<u16> {
+    estimates
+        .iter()
+        .filter_map(|(target, rate)| target.parse::<u16>().ok().map(|t| (t, *rate)))
+        .filter(|(_, rate)| *rate <= fee_rate as f64)
+        .map(|(target, _)| target)
+        .min()
+}
+
 // mostly copied from sensei
-fn get_tr_descriptors_for_extended_key(
+fn get_descriptors_for_extended_key(
     xkey: ExtendedKey,
     network: Network,
+    descriptor_type: DescriptorType,
     account_number: u32,
 ) -> (DescriptorTemplateOut, DescriptorTemplateOut) {
     let master_xprv = xkey.into_xprv(network).unwrap();
@@ -209,32 +766,106 @@ fn get_tr_descriptors_for_extended_key(
         Network::Regtest => 1,
     };
 
-    let base_path = DerivationPath::from_str("m/86'").unwrap();
-    let derivation_path = base_path.extend([
+    let purpose = match descriptor_type {
+        DescriptorType::Taproot => 86,
+        DescriptorType::NativeSegwit => 84,
+    };
+
+    let derivation_path = DerivationPath::from(vec![
+        ChildNumber::from_hardened_idx(purpose).unwrap(),
         ChildNumber::from_hardened_idx(coin_type).unwrap(),
         ChildNumber::from_hardened_idx(account_number).unwrap(),
     ]);
 
-    let receive_descriptor_template = bdk::descriptor!(tr((
-        master_xprv,
-        derivation_path.extend([ChildNumber::Normal { index: 0 }])
-    )))
-    .unwrap();
-    let change_descriptor_template = bdk::descriptor!(tr((
-        master_xprv,
-        derivation_path.extend([ChildNumber::Normal { index: 1 }])
-    )))
-    .unwrap();
+    let receive_path = derivation_path.extend([ChildNumber::Normal { index: 0 }]);
+    let change_path = derivation_path.extend([ChildNumber::Normal { index: 1 }]);
 
-    (receive_descriptor_template, change_descriptor_template)
+    match descriptor_type {
+        DescriptorType::Taproot => (
+            bdk::descriptor!(tr((master_xprv, receive_path))).unwrap(),
+            bdk::descriptor!(tr((master_xprv, change_path))).unwrap(),
+        ),
+        DescriptorType::NativeSegwit => (
+            bdk::descriptor!(wpkh((master_xprv, receive_path))).unwrap(),
+            bdk::descriptor!(wpkh((master_xprv, change_path))).unwrap(),
+        ),
+    }
+}
+
+/// Turns the user's input into receive and change descriptors for a watch-only wallet.
+/// A bare xpub is treated as a native segwit account, otherwise the input is expected
+/// to be an output descriptor. If no change descriptor is given and the descriptor
+/// derives from a `/0/*` path, the change descriptor uses the matching `/1/*` path.
+pub(crate) fn parse_watch_only_descriptors(
+    input: &str,
+    change_descriptor: Option<String>,
+    network: Network,
+) -> Result<(String, Option<String>), MutinyError> {
+    let input = input.trim();
+    if let Ok(xpub) = ExtendedPubKey::from_str(input) {
+        if (xpub.network == Network::Bitcoin) != (network == Network::Bitcoin) {
+            return Err(MutinyError::IncorrectNetwork);
+        }
+        return Ok((
+            format!("wpkh({xpub}/0/*)"),
+            Some(format!("wpkh({xpub}/1/*)")),
+        ));
+    }
+
+    // strip the checksum, bdk will add it back when it parses the descriptor
+    let descriptor = input.split('#').next().unwrap_or(input).to_string();
+    if change_descriptor.is_some() {
+        return Ok((descriptor, change_descriptor));
+    }
+
+    let change = descriptor.rfind("/0/*").map(|idx| {
+        let mut change = descriptor.clone();
+        change.replace_range(idx..idx + 4, "/1/*");
+        change
+    });
+
+    Ok((descriptor, change))
+}
+
+// Parses a descriptor and makes sure it does not hold any private keys
+fn public_descriptor(
+    secp: &Secp256k1<All>,
+    descriptor: &str,
+    network: Network,
+) -> Result<(ExtendedDescriptor, KeyMap), MutinyError> {
+    let (descriptor, key_map) = descriptor
+        .into_wallet_descriptor(secp, network)
+        .map_err(|_| MutinyError::InvalidDescriptor)?;
+
+    if !key_map.is_empty() {
+        return Err(MutinyError::InvalidDescriptor);
+    }
+
+    Ok((descriptor, key_map))
+}
+
+/// The number of consecutive unused addresses checked before a sync stops looking further.
+pub const DEFAULT_GAP_LIMIT: usize = 5;
+
+/// The on-chain funds kept aside for each open anchor channel, enough to CPFP
+/// a commitment transaction at a high fee rate.
+pub const ANCHOR_RESERVE_SATS: u64 = 25_000;
+
+// This is the WalletSettings object saved to the DB
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct WalletSettings {
+    /// The block height the mnemonic was created or restored from
+    pub birthday: Option<u32>,
+    pub gap_limit: Option<usize>,
 }
 
 pub fn esplora_from_network(
     network: Network,
     user_provided_url: Option<String>,
+    gap_limit: usize,
 ) -> EsploraBlockchain {
     if let Some(url) = user_provided_url {
-        EsploraBlockchain::new(&url, 5)
+        EsploraBlockchain::new(&url, gap_limit)
     } else {
         let url = match network {
             Network::Bitcoin => "https://blockstream.info/api",
@@ -242,6 +873,525 @@ pub fn esplora_from_network(
             Network::Signet => "https://mempool.space/signet/api",
             Network::Regtest => "http://localhost:3003",
         };
-        EsploraBlockchain::new(url, 5)
+        EsploraBlockchain::new(url, gap_limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::MutinyError;
+    use crate::keymanager::generate_seed;
+    use crate::localstorage::MutinyBrowserStorage;
+    use crate::test::*;
+    use crate::wallet::{
+        confirmation_target_for_fee_rate, esplora_from_network, get_descriptors_for_extended_key,
+        parse_watch_only_descriptors, public_descriptor, DescriptorType, MutinyWallet,
+        ANCHOR_RESERVE_SATS, DEFAULT_GAP_LIMIT,
+    };
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    use bdk::database::BatchOperations;
+    use bdk::descriptor::IntoWalletDescriptor;
+    use bdk::keys::ExtendedKey;
+    use bdk::wallet::AddressIndex;
+    use bdk::{BlockTime, FeeRate, KeychainKind, LocalUtxo, TransactionDetails};
+    use bip39::Mnemonic;
+    use bitcoin::hashes::hex::ToHex;
+    use bitcoin::psbt::{self, PartiallySignedTransaction};
+    use bitcoin::secp256k1::Secp256k1;
+    use bitcoin::util::bip32::ExtendedPrivKey;
+    use bitcoin::{Address, Network, OutPoint, PackedLockTime, Script, Transaction, TxIn, TxOut};
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const PASSWORD: &str = "password";
+
+    const RECIPIENT: &str = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+
+    const DRAIN_TO: &str = "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7";
+
+    fn create_wallet() -> MutinyWallet {
+        cleanup_test();
+        open_wallet(generate_seed(12).unwrap())
+    }
+
+    // Opens the wallet of the mnemonic over what is already in storage, like after a reload
+    fn open_wallet(mnemonic: Mnemonic) -> MutinyWallet {
+        let esplora = Arc::new(esplora_from_network(
+            Network::Testnet,
+            None,
+            DEFAULT_GAP_LIMIT,
+        ));
+        MutinyWallet::new(
+            mnemonic,
+            MutinyBrowserStorage::new(PASSWORD.to_string()),
+            Network::Testnet,
+            esplora,
+        )
+    }
+
+    // Gives the wallet a confirmed utxo without syncing it
+    async fn fund_wallet(wallet: &MutinyWallet, amount: u64) -> OutPoint {
+        let wallet = wallet.wallet.lock().await;
+        let address = wallet.get_address(AddressIndex::New).unwrap().address;
+        let txout = TxOut {
+            value: amount,
+            script_pubkey: address.script_pubkey(),
+        };
+        // every funding transaction needs its own txid
+        let index = wallet.list_unspent().unwrap().len() as u32;
+        let tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime(index),
+            input: vec![TxIn::default()],
+            output: vec![txout.clone()],
+        };
+        let outpoint = OutPoint {
+            txid: tx.txid(),
+            vout: 0,
+        };
+
+        let mut database = MutinyBrowserStorage::new(PASSWORD.to_string());
+        database.set_raw_tx(&tx).unwrap();
+        database
+            .set_tx(&TransactionDetails {
+                transaction: None,
+                txid: tx.txid(),
+                received: amount,
+                sent: 0,
+                fee: None,
+                confirmation_time: Some(BlockTime {
+                    height: 1,
+                    timestamp: 0,
+                }),
+            })
+            .unwrap();
+        database
+            .set_utxo(&LocalUtxo {
+                outpoint,
+                txout,
+                keychain: KeychainKind::External,
+                is_spent: false,
+            })
+            .unwrap();
+
+        outpoint
+    }
+
+    fn spent(psbt: &PartiallySignedTransaction) -> Vec<OutPoint> {
+        psbt.unsigned_tx
+            .input
+            .iter()
+            .map(|i| i.previous_output)
+            .collect()
+    }
+
+    fn paid(psbt: &PartiallySignedTransaction, address: &str) -> Option<u64> {
+        let script_pubkey = Address::from_str(address).unwrap().script_pubkey();
+        psbt.unsigned_tx
+            .output
+            .iter()
+            .find(|o| o.script_pubkey == script_pubkey)
+            .map(|o| o.value)
+    }
+
+    const XPUB: &str = "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8";
+
+    #[test]
+    fn test_parse_watch_only_xpub() {
+        log!("parse watch only xpub");
+
+        let (receive, change) = parse_watch_only_descriptors(XPUB, None, Network::Bitcoin).unwrap();
+        assert_eq!(format!("wpkh({XPUB}/0/*)"), receive);
+        assert_eq!(Some(format!("wpkh({XPUB}/1/*)")), change);
+
+        assert!(parse_watch_only_descriptors(XPUB, None, Network::Testnet).is_err());
+    }
+
+    #[test]
+    fn test_parse_watch_only_descriptor() {
+        log!("parse watch only descriptor");
+
+        let descriptor = format!("tr({XPUB}/0/*)#abcdefgh");
+        let (receive, change) =
+            parse_watch_only_descriptors(&descriptor, None, Network::Bitcoin).unwrap();
+        assert_eq!(format!("tr({XPUB}/0/*)"), receive);
+        assert_eq!(Some(format!("tr({XPUB}/1/*)")), change);
+
+        let single = format!("wpkh({XPUB})");
+        let (receive, change) =
+            parse_watch_only_descriptors(&single, None, Network::Bitcoin).unwrap();
+        assert_eq!(single, receive);
+        assert_eq!(None, change);
+    }
+
+    #[test]
+    fn test_reject_private_descriptor() {
+        log!("reject private descriptor");
+
+        let secp = Secp256k1::new();
+        assert!(public_descriptor(&secp, &format!("wpkh({XPUB}/0/*)"), Network::Bitcoin).is_ok());
+
+        let xprv = "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi";
+        assert!(public_descriptor(&secp, &format!("wpkh({xprv}/0/*)"), Network::Bitcoin).is_err());
+    }
+
+    #[test]
+    fn test_account_descriptors() {
+        log!("account descriptors");
+
+        let secp = Secp256k1::new();
+        let xprv = ExtendedPrivKey::new_master(Network::Testnet, &[42; 32]).unwrap();
+
+        let (receive, change) = get_descriptors_for_extended_key(
+            ExtendedKey::from(xprv),
+            Network::Testnet,
+            DescriptorType::NativeSegwit,
+            1,
+        );
+        let (receive, _) = receive
+            .into_wallet_descriptor(&secp, Network::Testnet)
+            .unwrap();
+        let (change, _) = change
+            .into_wallet_descriptor(&secp, Network::Testnet)
+            .unwrap();
+        assert!(receive.to_string().starts_with("wpkh("));
+        assert!(receive.to_string().contains("/84'/1'/1'"));
+        assert!(change.to_string().contains("/1/*)"));
+
+        let (receive, _) = get_descriptors_for_extended_key(
+            ExtendedKey::from(xprv),
+            Network::Testnet,
+            DescriptorType::Taproot,
+            0,
+        );
+        let (receive, _) = receive
+            .into_wallet_descriptor(&secp, Network::Testnet)
+            .unwrap();
+        assert!(receive.to_string().starts_with("tr("));
+        assert!(receive.to_string().contains("/86'/1'/0'"));
+    }
+
+    #[test]
+    fn test_confirmation_target_for_fee_rate() {
+        log!("confirmation target for fee rate");
+
+        let estimates: HashMap<String, f64> = HashMap::from([
+            ("1".to_string(), 20.0),
+            ("3".to_string(), 10.0),
+            ("6".to_string(), 5.0),
+            ("144".to_string(), 1.0),
+        ]);
+
+        assert_eq!(Some(1), confirmation_target_for_fee_rate(&estimates, 25.0));
+        assert_eq!(Some(3), confirmation_target_for_fee_rate(&estimates, 10.0));
+        assert_eq!(Some(144), confirmation_target_for_fee_rate(&estimates, 2.0));
+        assert_eq!(None, confirmation_target_for_fee_rate(&estimates, 0.5));
+    }
+
+    #[test]
+    async fn test_batch_send_max() {
+        log!("batch send max");
+
+        let wallet = create_wallet();
+        let first = fund_wallet(&wallet, 100_000).await;
+        let second = fund_wallet(&wallet, 50_000).await;
+
+        let recipient = Address::from_str(RECIPIENT).unwrap();
+        let drain_to = Address::from_str(DRAIN_TO).unwrap();
+        let psbt = wallet
+            .create_signed_batch_psbt(vec![(recipient, 10_000)], Some(drain_to), Some(1.0))
+            .await
+            .unwrap();
+
+        // everything is spent and whatever the recipient and fee don't take is drained
+        let inputs = spent(&psbt);
+        assert_eq!(2, inputs.len());
+        assert!(inputs.contains(&first) && inputs.contains(&second));
+        assert_eq!(2, psbt.unsigned_tx.output.len());
+        assert_eq!(Some(10_000), paid(&psbt, RECIPIENT));
+        let drained = paid(&psbt, DRAIN_TO).unwrap();
+        assert!(drained < 140_000 && drained > 139_000);
+
+        cleanup_test();
+    }
+
+    #[test]
+    async fn test_reject_invalid_recipients() {
+        log!("reject invalid recipients");
+
+        let wallet = create_wallet();
+        fund_wallet(&wallet, 100_000).await;
+
+        let no_recipients = wallet
+            .create_signed_batch_psbt(vec![], None, Some(1.0))
+            .await;
+        assert!(matches!(
+            no_recipients,
+            Err(MutinyError::WalletOperationFailed)
+        ));
+
+        let mainnet = Address::from_str("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").unwrap();
+        let recipient = Address::from_str(RECIPIENT).unwrap();
+        let wrong_network = wallet
+            .create_signed_batch_psbt(
+                vec![(recipient, 10_000), (mainnet.clone(), 10_000)],
+                None,
+                Some(1.0),
+            )
+            .await;
+        assert!(matches!(wrong_network, Err(MutinyError::IncorrectNetwork)));
+
+        let wrong_drain = wallet
+            .create_signed_batch_psbt(vec![], Some(mainnet), Some(1.0))
+            .await;
+        assert!(matches!(wrong_drain, Err(MutinyError::IncorrectNetwork)));
+
+        cleanup_test();
+    }
+
+    #[test]
+    async fn test_frozen_utxos_are_not_spent() {
+        log!("frozen utxos are not spent");
+
+        let wallet = create_wallet();
+        let frozen = fund_wallet(&wallet, 100_000).await;
+        let spendable = fund_wallet(&wallet, 50_000).await;
+        wallet.freeze_utxo(frozen).await.unwrap();
+        assert_eq!(vec![frozen], wallet.list_frozen_utxos().await.unwrap());
+
+        let drain_to = Address::from_str(DRAIN_TO).unwrap();
+        let psbt = wallet
+            .create_signed_batch_psbt(vec![], Some(drain_to.clone()), Some(1.0))
+            .await
+            .unwrap();
+        assert_eq!(vec![spendable], spent(&psbt));
+
+        // more than the unfrozen utxo can pay for
+        let recipient = Address::from_str(RECIPIENT).unwrap();
+        let too_much = wallet
+            .create_signed_batch_psbt(vec![(recipient, 75_000)], None, Some(1.0))
+            .await;
+        assert!(too_much.is_err());
+
+        wallet.unfreeze_utxo(frozen).await.unwrap();
+        assert!(wallet.list_frozen_utxos().await.unwrap().is_empty());
+        let psbt = wallet
+            .create_signed_batch_psbt(vec![], Some(drain_to), Some(1.0))
+            .await
+            .unwrap();
+        assert_eq!(2, spent(&psbt).len());
+
+        cleanup_test();
+    }
+
+    #[test]
+    async fn test_preview_reserves_inputs() {
+        log!("preview reserves inputs");
+
+        let wallet = create_wallet();
+        let first = fund_wallet(&wallet, 100_000).await;
+        let second = fund_wallet(&wallet, 50_000).await;
+
+        let drain_to = Address::from_str(DRAIN_TO).unwrap();
+        let recipient = Address::from_str(RECIPIENT).unwrap();
+        let preview = wallet
+            .preview_batch(vec![(recipient.clone(), 80_000)], None, Some(1.0))
+            .await
+            .unwrap();
+        assert_eq!(
+            vec![first],
+            preview
+                .inputs
+                .iter()
+                .map(|i| i.outpoint)
+                .collect::<Vec<_>>()
+        );
+
+        // other sends and previews can't spend the coins of a pending preview
+        let psbt = wallet
+            .create_signed_batch_psbt(vec![], Some(drain_to.clone()), Some(1.0))
+            .await
+            .unwrap();
+        assert_eq!(vec![second], spent(&psbt));
+        let second_preview = wallet
+            .preview_batch(vec![(recipient, 80_000)], None, Some(1.0))
+            .await;
+        assert!(second_preview.is_err());
+
+        wallet.discard_preview(preview.txid).await.unwrap();
+        let psbt = wallet
+            .create_signed_batch_psbt(vec![], Some(drain_to), Some(1.0))
+            .await
+            .unwrap();
+        assert_eq!(2, spent(&psbt).len());
+
+        cleanup_test();
+    }
+
+    #[test]
+    async fn test_previews_are_released_after_a_restart() {
+        log!("previews are released after a restart");
+
+        cleanup_test();
+        let mnemonic = generate_seed(12).unwrap();
+        let wallet = open_wallet(mnemonic.clone());
+        fund_wallet(&wallet, 100_000).await;
+        fund_wallet(&wallet, 50_000).await;
+
+        // a channel funding reservation outlives the restart
+        let channel_id = [1; 32];
+        let funding = fund_wallet(&wallet, 20_000).await;
+        {
+            let bdk_wallet = wallet.wallet.lock().await;
+            let database = bdk_wallet.database();
+            let mut reserved = database.get_reserved_utxos().unwrap();
+            reserved.insert(channel_id.to_hex(), vec![funding]);
+            database.insert_reserved_utxos(reserved).unwrap();
+        }
+
+        let recipient = Address::from_str(RECIPIENT).unwrap();
+        let preview = wallet
+            .preview_batch(vec![(recipient, 80_000)], None, Some(1.0))
+            .await
+            .unwrap();
+        drop(wallet);
+
+        let wallet = open_wallet(mnemonic);
+        assert!(wallet.broadcast_preview(preview.txid).await.is_err());
+        let drain_to = Address::from_str(DRAIN_TO).unwrap();
+        let psbt = wallet
+            .create_signed_batch_psbt(vec![], Some(drain_to), Some(1.0))
+            .await
+            .unwrap();
+        let spent = spent(&psbt);
+        assert_eq!(2, spent.len());
+        assert!(!spent.contains(&funding));
+
+        cleanup_test();
+    }
+
+    #[test]
+    async fn test_anchor_reserve() {
+        log!("anchor reserve");
+
+        let wallet = create_wallet();
+        fund_wallet(&wallet, 100_000).await;
+        let channel_id = [1; 32];
+        wallet.add_anchor_channel(channel_id).await.unwrap();
+        wallet.add_anchor_channel(channel_id).await.unwrap();
+        assert_eq!(ANCHOR_RESERVE_SATS, wallet.anchor_reserve().await.unwrap());
+
+        let recipient = Address::from_str(RECIPIENT).unwrap();
+        let into_reserve = wallet
+            .create_signed_batch_psbt(vec![(recipient, 80_000)], None, Some(1.0))
+            .await;
+        assert!(matches!(into_reserve, Err(MutinyError::AnchorReserveError)));
+
+        // draining leaves the reserve in the wallet
+        let drain_to = Address::from_str(DRAIN_TO).unwrap();
+        let psbt = wallet
+            .create_signed_batch_psbt(vec![], Some(drain_to.clone()), Some(1.0))
+            .await
+            .unwrap();
+        let drained = paid(&psbt, DRAIN_TO).unwrap();
+        assert!(drained < 100_000 - ANCHOR_RESERVE_SATS && drained > 74_000);
+        let kept: u64 = {
+            let bdk_wallet = wallet.wallet.lock().await;
+            psbt.unsigned_tx
+                .output
+                .iter()
+                .filter(|o| bdk_wallet.is_mine(&o.script_pubkey).unwrap())
+                .map(|o| o.value)
+                .sum()
+        };
+        assert_eq!(ANCHOR_RESERVE_SATS, kept);
+
+        wallet.remove_anchor_channel(&channel_id).await.unwrap();
+        assert_eq!(0, wallet.anchor_reserve().await.unwrap());
+        let psbt = wallet
+            .create_signed_batch_psbt(vec![], Some(drain_to), Some(1.0))
+            .await
+            .unwrap();
+        assert_eq!(1, psbt.unsigned_tx.output.len());
+
+        cleanup_test();
+    }
+
+    #[test]
+    async fn test_anchor_reserve_excludes_frozen_utxos() {
+        log!("anchor reserve excludes frozen utxos");
+
+        let wallet = create_wallet();
+        fund_wallet(&wallet, 100_000).await;
+        let frozen = fund_wallet(&wallet, 50_000).await;
+        wallet.freeze_utxo(frozen).await.unwrap();
+        wallet.add_anchor_channel([1; 32]).await.unwrap();
+
+        // the frozen coins would cover the reserve, but they can't be spent on fee bumping
+        let recipient = Address::from_str(RECIPIENT).unwrap();
+        let into_reserve = wallet
+            .create_signed_batch_psbt(vec![(recipient, 80_000)], None, Some(1.0))
+            .await;
+        assert!(matches!(into_reserve, Err(MutinyError::AnchorReserveError)));
+
+        // draining still keeps the reserve out of the frozen coins
+        let drain_to = Address::from_str(DRAIN_TO).unwrap();
+        let psbt = wallet
+            .create_signed_batch_psbt(vec![], Some(drain_to), Some(1.0))
+            .await
+            .unwrap();
+        assert!(!spent(&psbt).contains(&frozen));
+
+        cleanup_test();
+    }
+
+    #[test]
+    async fn test_create_cpfp_psbt() {
+        log!("create cpfp psbt");
+
+        let wallet = create_wallet();
+        fund_wallet(&wallet, 100_000).await;
+
+        // a parent paying nothing, with an anchor sized output for the child to spend
+        let anchor = TxOut {
+            value: 330,
+            script_pubkey: Script::new_v0_p2wsh(&Script::new().wscript_hash()),
+        };
+        let parent = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![anchor.clone()],
+        };
+        let outpoint = OutPoint {
+            txid: parent.txid(),
+            vout: 0,
+        };
+        let psbt_input = psbt::Input {
+            non_witness_utxo: Some(parent.clone()),
+            witness_utxo: Some(anchor),
+            ..Default::default()
+        };
+        let parent_weight = 1_000;
+        let target_fee_rate = FeeRate::from_sat_per_vb(10.0);
+
+        let psbt = wallet
+            .create_cpfp_psbt(outpoint, psbt_input, 116, parent_weight, 0, target_fee_rate)
+            .await
+            .unwrap();
+        assert!(spent(&psbt).contains(&outpoint));
+        assert_eq!(2, psbt.unsigned_tx.input.len());
+
+        // the child pays for itself and for the whole parent
+        let fee = 100_330 - psbt.unsigned_tx.output.iter().map(|o| o.value).sum::<u64>();
+        let child_fee = target_fee_rate.fee_wu(psbt.unsigned_tx.weight());
+        assert!(fee >= child_fee + target_fee_rate.fee_wu(parent_weight));
+        assert!(fee < 2 * (child_fee + target_fee_rate.fee_wu(parent_weight)));
+
+        cleanup_test();
     }
 }